@@ -0,0 +1,171 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+/// 加密字段的前缀，用于区分密文和尚未迁移的明文
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+/// 脱敏值的前缀，前端回传该值时表示保留原密钥
+pub const REDACTED_PREFIX: &str = "••••";
+const NONCE_LEN: usize = 12;
+
+/// 敏感字段加解密器
+///
+/// 使用AES-256-GCM，主密钥保存在数据库同目录下的本地密钥文件中。
+pub struct SecretCipher {
+    cipher: Aes256Gcm,
+}
+
+impl SecretCipher {
+    /// 从密钥文件加载主密钥，文件不存在时生成新密钥
+    pub fn load_or_create(key_path: &Path) -> Result<Self, String> {
+        let key_bytes = if key_path.exists() {
+            let encoded = fs::read_to_string(key_path)
+                .map_err(|e| format!("读取主密钥失败: {}", e))?;
+            let bytes = BASE64.decode(encoded.trim())
+                .map_err(|e| format!("主密钥格式无效: {}", e))?;
+            if bytes.len() != 32 {
+                return Err("主密钥长度无效".to_string());
+            }
+            bytes
+        } else {
+            let key = Aes256Gcm::generate_key(OsRng);
+            write_key_file(key_path, &BASE64.encode(key))?;
+            info!("已生成新的主密钥: {}", key_path.display());
+            key.to_vec()
+        };
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes)),
+        })
+    }
+
+    pub fn is_encrypted(value: &str) -> bool {
+        value.starts_with(ENCRYPTED_PREFIX)
+    }
+
+    /// 加密字段值，空值和能用当前密钥解密的密文原样返回
+    ///
+    /// 只有前缀相同但无法解密的值仍按明文加密，避免未加密存储后无法读取。
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, String> {
+        if plaintext.is_empty() || (Self::is_encrypted(plaintext) && self.decrypt(plaintext).is_ok()) {
+            return Ok(plaintext.to_string());
+        }
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|e| format!("加密失败: {}", e))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode(payload)))
+    }
+
+    /// 解密字段值，未加密的旧数据原样返回
+    pub fn decrypt(&self, value: &str) -> Result<String, String> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+
+        let payload = BASE64.decode(encoded)
+            .map_err(|e| format!("密文格式无效: {}", e))?;
+        if payload.len() < NONCE_LEN {
+            return Err("密文长度无效".to_string());
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "解密失败，主密钥可能已更换".to_string())?;
+        String::from_utf8(plaintext).map_err(|e| format!("解密结果不是有效的UTF-8: {}", e))
+    }
+
+    pub fn encrypt_opt(&self, value: &Option<String>) -> Result<Option<String>, String> {
+        value.as_deref().map(|v| self.encrypt(v)).transpose()
+    }
+
+    pub fn decrypt_opt(&self, value: &Option<String>) -> Result<Option<String>, String> {
+        value.as_deref().map(|v| self.decrypt(v)).transpose()
+    }
+}
+
+/// 生成脱敏显示值，只保留末尾4位
+pub fn redact(secret: &str) -> String {
+    if secret.is_empty() {
+        return String::new();
+    }
+    let chars: Vec<char> = secret.chars().collect();
+    let tail: String = if chars.len() > 8 {
+        chars[chars.len() - 4..].iter().collect()
+    } else {
+        String::new()
+    };
+    format!("{}{}", REDACTED_PREFIX, tail)
+}
+
+fn write_key_file(path: &Path, content: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("创建密钥目录失败: {}", e))?;
+    }
+    fs::write(path, content).map_err(|e| format!("写入主密钥失败: {}", e))?;
+
+    // 密钥文件仅允许当前用户读写
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("设置密钥文件权限失败: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(dir: &Path, name: &str) -> SecretCipher {
+        SecretCipher::load_or_create(&dir.join(name)).unwrap()
+    }
+
+    #[test]
+    fn round_trip_and_key_reuse() {
+        let dir = tempfile::tempdir().unwrap();
+        let cipher = cipher(dir.path(), "master.key");
+
+        let encrypted = cipher.encrypt("sk-secret").unwrap();
+        assert!(SecretCipher::is_encrypted(&encrypted));
+        assert_ne!(encrypted, cipher.encrypt("sk-secret").unwrap());
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), "sk-secret");
+        // 已加密的值不重复加密，空值和旧明文原样返回
+        assert_eq!(cipher.encrypt(&encrypted).unwrap(), encrypted);
+        assert_eq!(cipher.encrypt("").unwrap(), "");
+        assert_eq!(cipher.decrypt("legacy-plaintext").unwrap(), "legacy-plaintext");
+
+        let reloaded = self::cipher(dir.path(), "master.key");
+        assert_eq!(reloaded.decrypt(&encrypted).unwrap(), "sk-secret");
+    }
+
+    #[test]
+    fn wrong_key_and_prefixed_plaintext() {
+        let dir = tempfile::tempdir().unwrap();
+        let cipher = cipher(dir.path(), "a.key");
+        let other = self::cipher(dir.path(), "b.key");
+
+        let encrypted = cipher.encrypt("sk-secret").unwrap();
+        assert!(other.decrypt(&encrypted).is_err());
+
+        // 带有密文前缀但无法解密的明文仍然加密存储
+        let tricky = "enc:v1:not-really-encrypted";
+        let stored = cipher.encrypt(tricky).unwrap();
+        assert_ne!(stored, tricky);
+        assert_eq!(cipher.decrypt(&stored).unwrap(), tricky);
+    }
+
+    #[test]
+    fn redacts_all_but_tail() {
+        assert_eq!(redact(""), "");
+        assert_eq!(redact("short"), REDACTED_PREFIX);
+        assert_eq!(redact("sk-1234567890abcd"), format!("{}abcd", REDACTED_PREFIX));
+    }
+}
@@ -1,9 +1,12 @@
 use sqlx::{sqlite::SqlitePool, Pool, Sqlite, Row};
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+use crate::crypto::SecretCipher;
+use crate::migrations;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AIProvider {
     pub id: String,
@@ -30,6 +33,7 @@ pub struct ProxySettings {
     pub requires_auth: bool,
     pub username: String,
     pub password: String,
+    pub routing: Option<String>, // JSON string，代理路由规则（含命名代理的认证信息，加密存储）
     pub updated_at: DateTime<Utc>,
 }
 
@@ -109,6 +113,19 @@ pub struct SceneMessage {
     pub metadata: Option<String>, // JSON string
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessageSearchHit {
+    pub source: String, // "chat" or "scene"
+    pub message_id: String,
+    pub session_id: String,
+    pub agent_id: Option<String>,
+    pub scene_id: Option<String>,
+    pub role: String,
+    pub snippet: String,
+    pub timestamp: DateTime<Utc>,
+    pub rank: f64, // bm25得分，越小越相关
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MCPServerConfig {
     pub id: String,
@@ -126,6 +143,27 @@ pub struct MCPServerConfig {
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkspaceRoot {
+    pub id: String,
+    pub name: String,
+    pub path: String, // 规范化后的绝对路径
+    pub mode: String, // "read_only" or "read_write"
+    pub policy: Option<String>, // JSON string，文件访问策略
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PermissionGrant {
+    pub id: String,
+    pub subject_type: String, // "agent" or "mcp_server"
+    pub subject_id: String,
+    pub action: String, // "fs_write", "fs_delete", "fs_move", "mcp_tool"
+    pub target: String, // 工作区ID或工具名，"*" 表示全部
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppSettings {
     pub key: String,
@@ -135,6 +173,7 @@ pub struct AppSettings {
 
 pub struct Database {
     pool: Pool<Sqlite>,
+    cipher: SecretCipher,
 }
 
 impl Database {
@@ -153,12 +192,35 @@ impl Database {
         
         let pool = SqlitePool::connect(&db_url).await?;
         
-        let db = Database { pool };
+        // 主密钥与数据库文件放在同一目录
+        let key_path = db_path.with_file_name("master.key");
+        let cipher = SecretCipher::load_or_create(&key_path)
+            .map_err(|e| sqlx::Error::Configuration(e.into()))?;
+        
+        let db = Database { pool, cipher };
         db.init_schema().await?;
         
         Ok(db)
     }
     
+    /// 测试用的内存数据库，主密钥写在 `key_dir` 中
+    #[cfg(test)]
+    pub async fn open_in_memory(key_dir: &Path) -> Result<Self, sqlx::Error> {
+        // 每个连接都会打开独立的内存库，因此只用一个连接
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await?;
+        let cipher = SecretCipher::load_or_create(&key_dir.join("master.key"))
+            .map_err(|e| sqlx::Error::Configuration(e.into()))?;
+        let db = Database { pool, cipher };
+        db.init_schema().await?;
+        Ok(db)
+    }
+    
+    /// 应用数据目录，数据库、主密钥和操作日志都放在这里
+    pub fn data_dir() -> PathBuf {
+        let db_path = Self::get_db_path();
+        db_path.parent().map(Path::to_path_buf).unwrap_or(db_path)
+    }
+    
     fn get_db_path() -> PathBuf {
         // 使用系统应用数据目录，避免影响开发时的文件监听
         let mut path = match std::env::var("APPDATA") {
@@ -176,170 +238,15 @@ impl Database {
     }
     
     async fn init_schema(&self) -> Result<(), sqlx::Error> {
-        // AI Providers table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS ai_providers (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                api_endpoint TEXT NOT NULL,
-                api_key TEXT NOT NULL,
-                models TEXT NOT NULL,
-                default_model_id TEXT,
-                custom_config TEXT,
-                use_custom_config BOOLEAN,
-                auto_fetch_config TEXT,
-                preset_type TEXT,
-                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-        "#).execute(&self.pool).await?;
-        
-        // Proxy Settings table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS proxy_settings (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                enabled BOOLEAN NOT NULL DEFAULT FALSE,
-                proxy_type TEXT NOT NULL DEFAULT 'http',
-                host TEXT NOT NULL DEFAULT '',
-                port INTEGER NOT NULL DEFAULT 0,
-                requires_auth BOOLEAN NOT NULL DEFAULT FALSE,
-                username TEXT NOT NULL DEFAULT '',
-                password TEXT NOT NULL DEFAULT '',
-                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-        "#).execute(&self.pool).await?;
-        
-        // Agents table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS agents (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                description TEXT NOT NULL,
-                system_prompt TEXT NOT NULL,
-                provider_id TEXT NOT NULL,
-                model_id TEXT NOT NULL,
-                keep_history BOOLEAN NOT NULL DEFAULT TRUE,
-                max_history_messages INTEGER,
-                icon TEXT,
-                is_stream_mode BOOLEAN,
-                temperature REAL,
-                settings TEXT,
-                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-        "#).execute(&self.pool).await?;
-        
-        // Agent Sessions table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS agent_sessions (
-                id TEXT PRIMARY KEY,
-                agent_id TEXT NOT NULL,
-                name TEXT NOT NULL,
-                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (agent_id) REFERENCES agents (id) ON DELETE CASCADE
-            )
-        "#).execute(&self.pool).await?;
-        
-        // Messages table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS messages (
-                id TEXT PRIMARY KEY,
-                session_id TEXT NOT NULL,
-                content TEXT NOT NULL,
-                role TEXT NOT NULL,
-                timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                streaming BOOLEAN,
-                canceled BOOLEAN,
-                reasoning_content TEXT,
-                reasoning_collapsed BOOLEAN,
-                generation_start_time DATETIME,
-                generation_end_time DATETIME,
-                generation_duration INTEGER,
-                FOREIGN KEY (session_id) REFERENCES agent_sessions (id) ON DELETE CASCADE
-            )
-        "#).execute(&self.pool).await?;
-        
-        // Scenes table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS scenes (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                description TEXT NOT NULL,
-                scenario_prompt TEXT NOT NULL,
-                participants TEXT NOT NULL,
-                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-        "#).execute(&self.pool).await?;
-        
-        // Scene Sessions table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS scene_sessions (
-                id TEXT PRIMARY KEY,
-                scene_id TEXT NOT NULL,
-                name TEXT NOT NULL,
-                is_active BOOLEAN NOT NULL DEFAULT TRUE,
-                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (scene_id) REFERENCES scenes (id) ON DELETE CASCADE
-            )
-        "#).execute(&self.pool).await?;
-        
-        // Scene Messages table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS scene_messages (
-                id TEXT PRIMARY KEY,
-                session_id TEXT NOT NULL,
-                participant_id TEXT NOT NULL,
-                agent_id TEXT,
-                role TEXT NOT NULL,
-                content TEXT NOT NULL,
-                timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                metadata TEXT,
-                FOREIGN KEY (session_id) REFERENCES scene_sessions (id) ON DELETE CASCADE
-            )
-        "#).execute(&self.pool).await?;
-        
-        // MCP Server Configs table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS mcp_server_configs (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                description TEXT NOT NULL,
-                enabled BOOLEAN NOT NULL DEFAULT TRUE,
-                server_type TEXT NOT NULL,
-                server_class TEXT,
-                command TEXT,
-                args TEXT,
-                env TEXT,
-                capabilities TEXT NOT NULL,
-                permissions TEXT NOT NULL,
-                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-        "#).execute(&self.pool).await?;
-        
-        // App Settings table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS app_settings (
-                key TEXT PRIMARY KEY,
-                value TEXT NOT NULL,
-                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-        "#).execute(&self.pool).await?;
-        
-        // Create indexes for better query performance
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_session_id ON messages(session_id)").execute(&self.pool).await?;
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_timestamp ON messages(timestamp)").execute(&self.pool).await?;
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_agent_sessions_agent_id ON agent_sessions(agent_id)").execute(&self.pool).await?;
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_scene_messages_session_id ON scene_messages(session_id)").execute(&self.pool).await?;
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_scene_sessions_scene_id ON scene_sessions(scene_id)").execute(&self.pool).await?;
-        
-        Ok(())
+        // 按版本号执行尚未应用的迁移
+        migrations::run(&self.pool, &self.cipher).await
     }
     
     pub fn pool(&self) -> &Pool<Sqlite> {
         &self.pool
     }
+    
+    pub fn cipher(&self) -> &SecretCipher {
+        &self.cipher
+    }
 } 
\ No newline at end of file
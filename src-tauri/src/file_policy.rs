@@ -0,0 +1,277 @@
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Component, Path};
+
+/// 二进制检测时读取的字节数
+pub const BINARY_SNIFF_LEN: usize = 8000;
+
+/// 工作区文件访问策略，以JSON保存在工作区配置中
+///
+/// 不含 `/` 的模式匹配路径中的任意一级名称（如 `*.pem`、`node_modules`），
+/// 含 `/` 的模式匹配相对于工作区根目录的完整路径，`**` 可跨目录。
+/// 拒绝规则优先于允许规则；允许列表为空时允许所有文件。
+/// `allow_binary` 只约束文本读写命令，二进制读写命令仍受路径规则和大小限制约束。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilePolicy {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub max_file_size: u64, // 字节
+    pub allow_binary: bool,
+    pub allow_hidden: bool,
+}
+
+impl Default for FilePolicy {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            max_file_size: 10 * 1024 * 1024,
+            allow_binary: false,
+            allow_hidden: false,
+        }
+    }
+}
+
+/// 策略拒绝访问的原因，`rule` 标明触发的规则
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyViolation {
+    pub rule: String,
+    pub path: String,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "路径 {} 被工作区策略规则 {} 阻止", self.path, self.rule)
+    }
+}
+
+#[derive(Clone)]
+struct Pattern {
+    source: String,
+    matcher: GlobMatcher,
+    // 不含 `/` 的模式逐级匹配名称
+    per_component: bool,
+}
+
+impl Pattern {
+    fn new(source: &str) -> Result<Self, String> {
+        let per_component = !source.contains('/');
+        let glob = if per_component {
+            Glob::new(source)
+        } else {
+            GlobBuilder::new(source.trim_start_matches('/')).literal_separator(true).build()
+        };
+        let matcher = glob.map_err(|e| format!("无效的文件模式 {}: {}", source, e))?.compile_matcher();
+        Ok(Self { source: source.to_string(), matcher, per_component })
+    }
+
+    /// 名称模式匹配任意一级，路径模式匹配完整路径
+    fn matches(&self, relative: &str) -> bool {
+        if self.per_component {
+            relative.split('/').any(|name| self.matcher.is_match(name))
+        } else {
+            self.matcher.is_match(relative)
+        }
+    }
+
+    /// 只匹配文件名，用于允许列表
+    fn matches_file(&self, relative: &str) -> bool {
+        if self.per_component {
+            relative.rsplit('/').next().is_some_and(|name| self.matcher.is_match(name))
+        } else {
+            self.matcher.is_match(relative)
+        }
+    }
+}
+
+/// 编译后的文件访问策略
+#[derive(Clone)]
+pub struct PolicyEngine {
+    policy: FilePolicy,
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>,
+}
+
+impl PolicyEngine {
+    pub fn new(policy: FilePolicy) -> Result<Self, String> {
+        let allow = policy.allow.iter().map(|p| Pattern::new(p)).collect::<Result<_, _>>()?;
+        let deny = policy.deny.iter().map(|p| Pattern::new(p)).collect::<Result<_, _>>()?;
+        Ok(Self { policy, allow, deny })
+    }
+
+    /// 从工作区保存的JSON创建策略，未配置时使用默认策略
+    pub fn from_json(json: Option<&str>) -> Result<Self, String> {
+        let policy = match json.map(str::trim).filter(|j| !j.is_empty()) {
+            Some(json) => serde_json::from_str(json).map_err(|e| format!("工作区策略格式无效: {}", e))?,
+            None => FilePolicy::default(),
+        };
+        Self::new(policy)
+    }
+
+    /// 检查相对于工作区根目录的路径，目录只检查隐藏和拒绝规则
+    pub fn check_path(&self, relative: &Path, is_dir: bool) -> Result<(), PolicyViolation> {
+        let names: Vec<String> = relative.components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        if names.is_empty() {
+            return Ok(());
+        }
+        let joined = names.join("/");
+        let violation = |rule: String| PolicyViolation { rule, path: joined.clone() };
+
+        if !self.policy.allow_hidden && names.iter().any(|name| name.starts_with('.')) {
+            return Err(violation("allow_hidden=false".to_string()));
+        }
+
+        if let Some(pattern) = self.deny.iter().find(|p| p.matches(&joined)) {
+            return Err(violation(format!("deny:{}", pattern.source)));
+        }
+
+        if !is_dir && !self.allow.is_empty() && !self.allow.iter().any(|p| p.matches_file(&joined)) {
+            return Err(violation("allow (未匹配任何允许模式)".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// 检查文件大小
+    pub fn check_size(&self, relative: &Path, size: u64) -> Result<(), PolicyViolation> {
+        if size > self.policy.max_file_size {
+            return Err(PolicyViolation {
+                rule: format!("max_file_size={} (实际 {} 字节)", self.policy.max_file_size, size),
+                path: relative.to_string_lossy().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// 检查文件开头的内容是否为二进制
+    pub fn check_content(&self, relative: &Path, head: &[u8]) -> Result<(), PolicyViolation> {
+        if !self.policy.allow_binary && is_binary(head) {
+            return Err(PolicyViolation {
+                rule: "allow_binary=false".to_string(),
+                path: relative.to_string_lossy().to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 根据开头的字节判断是否为二进制内容：包含NUL字节或不是有效的UTF-8
+pub fn is_binary(head: &[u8]) -> bool {
+    let sample = &head[..head.len().min(BINARY_SNIFF_LEN)];
+    if sample.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => false,
+        // 采样截断在多字节字符中间时 error_len 为 None
+        Err(e) => e.error_len().is_some(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(policy: FilePolicy) -> PolicyEngine {
+        PolicyEngine::new(policy).unwrap()
+    }
+
+    fn rule(result: Result<(), PolicyViolation>) -> String {
+        result.unwrap_err().rule
+    }
+
+    #[test]
+    fn default_policy_allows_files_without_known_extensions() {
+        let engine = engine(FilePolicy::default());
+        for path in ["Makefile.am", "Cargo.lock", "data/report.csv", "proto/api.proto", "LICENSE"] {
+            assert!(engine.check_path(Path::new(path), false).is_ok(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn hidden_files_are_blocked_unless_allowed() {
+        let default = engine(FilePolicy::default());
+        assert_eq!(rule(default.check_path(Path::new(".env"), false)), "allow_hidden=false");
+        assert_eq!(rule(default.check_path(Path::new(".git/config"), false)), "allow_hidden=false");
+        assert_eq!(rule(default.check_path(Path::new("src/.cache"), true)), "allow_hidden=false");
+
+        let permissive = engine(FilePolicy { allow_hidden: true, ..FilePolicy::default() });
+        assert!(permissive.check_path(Path::new(".env"), false).is_ok());
+    }
+
+    #[test]
+    fn deny_rules_match_names_at_any_depth_and_full_paths() {
+        let engine = engine(FilePolicy {
+            deny: vec!["*.pem".to_string(), "node_modules".to_string(), "secrets/**".to_string()],
+            ..FilePolicy::default()
+        });
+
+        assert_eq!(rule(engine.check_path(Path::new("certs/server.pem"), false)), "deny:*.pem");
+        assert_eq!(rule(engine.check_path(Path::new("web/node_modules/a/index.js"), false)), "deny:node_modules");
+        assert_eq!(rule(engine.check_path(Path::new("web/node_modules"), true)), "deny:node_modules");
+        assert_eq!(rule(engine.check_path(Path::new("secrets/prod/key.txt"), false)), "deny:secrets/**");
+        // 路径模式只从工作区根目录开始匹配
+        assert!(engine.check_path(Path::new("docs/secrets/readme.md"), false).is_ok());
+    }
+
+    #[test]
+    fn allow_list_applies_to_files_only_and_deny_takes_precedence() {
+        let engine = engine(FilePolicy {
+            allow: vec!["*.rs".to_string(), "docs/**/*.md".to_string()],
+            deny: vec!["generated.rs".to_string()],
+            ..FilePolicy::default()
+        });
+
+        assert!(engine.check_path(Path::new("src/main.rs"), false).is_ok());
+        assert!(engine.check_path(Path::new("docs/guide/intro.md"), false).is_ok());
+        assert!(engine.check_path(Path::new("src"), true).is_ok());
+        assert!(rule(engine.check_path(Path::new("README.md"), false)).starts_with("allow"));
+        // 路径模式从工作区根目录开始匹配
+        assert!(engine.check_path(Path::new("docs/intro.md"), false).is_ok());
+        assert!(rule(engine.check_path(Path::new("src/docs/intro.md"), false)).starts_with("allow"));
+        assert_eq!(rule(engine.check_path(Path::new("src/generated.rs"), false)), "deny:generated.rs");
+    }
+
+    #[test]
+    fn size_limit_names_the_rule() {
+        let engine = engine(FilePolicy { max_file_size: 100, ..FilePolicy::default() });
+        assert!(engine.check_size(Path::new("a.txt"), 100).is_ok());
+        let violation = engine.check_size(Path::new("a.txt"), 101).unwrap_err();
+        assert!(violation.rule.starts_with("max_file_size=100"));
+        assert!(violation.to_string().contains("a.txt"));
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(!is_binary("普通文本 with ascii\n".as_bytes()));
+        assert!(is_binary(b"PNG\x00\x01\x02"));
+        assert!(is_binary(&[0xff, 0xfe, 0x41]));
+        // 采样在多字节字符中间截断不算二进制
+        assert!(!is_binary(&"中文".as_bytes()[..4]));
+
+        let strict = engine(FilePolicy::default());
+        assert_eq!(rule(strict.check_content(Path::new("a.bin"), b"\x00")), "allow_binary=false");
+        let permissive = engine(FilePolicy { allow_binary: true, ..FilePolicy::default() });
+        assert!(permissive.check_content(Path::new("a.bin"), b"\x00").is_ok());
+    }
+
+    #[test]
+    fn policy_json_parsing() {
+        assert!(PolicyEngine::from_json(None).is_ok());
+        assert!(PolicyEngine::from_json(Some("")).is_ok());
+
+        let engine = PolicyEngine::from_json(Some(r#"{"deny": ["*.log"], "max_file_size": 5}"#)).unwrap();
+        assert_eq!(engine.policy.max_file_size, 5);
+        assert!(!engine.policy.allow_hidden);
+
+        assert!(PolicyEngine::from_json(Some("{not json")).is_err());
+        assert!(PolicyEngine::from_json(Some(r#"{"deny": ["a[b"]}"#)).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::file_policy::is_binary;
+use crate::fs_edit::content_hash;
+
+/// 二进制读取结果，内容为base64编码
+#[derive(Debug, Serialize)]
+pub struct BinaryContent {
+    pub data: String,
+    pub mime_type: String,
+    pub size: usize,
+    pub hash: String,
+}
+
+impl BinaryContent {
+    pub fn new(path: &Path, bytes: &[u8]) -> Self {
+        Self {
+            data: BASE64.encode(bytes),
+            mime_type: sniff_mime(path, bytes),
+            size: bytes.len(),
+            hash: content_hash(bytes),
+        }
+    }
+}
+
+/// 识别MIME类型：优先按文件头，其次按扩展名，都无法识别时按内容区分文本和二进制
+pub fn sniff_mime(path: &Path, bytes: &[u8]) -> String {
+    if let Some(kind) = infer::get(bytes) {
+        return kind.mime_type().to_string();
+    }
+    if let Some(mime) = mime_guess::from_path(path).first_raw() {
+        return mime.to_string();
+    }
+    if is_binary(bytes) {
+        "application/octet-stream".to_string()
+    } else {
+        "text/plain".to_string()
+    }
+}
+
+/// 解码base64内容，同时接受 `data:<mime>;base64,` 形式的data URL
+pub fn decode_base64(data: &str) -> Result<Vec<u8>, String> {
+    let data = data.trim();
+    let payload = match data.strip_prefix("data:") {
+        Some(rest) => rest.split_once(";base64,")
+            .map(|(_, payload)| payload)
+            .ok_or("data URL 不是base64编码")?,
+        None => data,
+    };
+    let payload: String = payload.chars().filter(|c| !c.is_whitespace()).collect();
+    BASE64.decode(payload).map_err(|e| format!("base64解码失败: {}", e))
+}
@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+/// 文件内容的SHA-256哈希（十六进制），用于检测编辑前文件是否被修改
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// 读取范围，按行（从1开始，包含结束行）或按字节偏移
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReadRange {
+    pub start_line: Option<usize>,
+    pub end_line: Option<usize>,
+    pub offset: Option<usize>,
+    pub length: Option<usize>,
+}
+
+/// 部分读取的结果，`hash` 为整个文件的哈希
+#[derive(Debug, Serialize)]
+pub struct RangedRead {
+    pub content: String,
+    pub hash: String,
+    pub total_bytes: usize,
+    pub total_lines: usize,
+    pub start_line: Option<usize>,
+    pub end_line: Option<usize>,
+    pub offset: Option<usize>,
+    pub length: Option<usize>,
+}
+
+/// 按范围截取文件内容
+///
+/// 同时指定行范围和字节范围时以行范围为准；
+/// 字节范围会收缩到完整的UTF-8字符边界，返回实际使用的偏移和长度。
+pub fn read_range(content: &str, range: &ReadRange) -> RangedRead {
+    let mut result = RangedRead {
+        content: String::new(),
+        hash: content_hash(content.as_bytes()),
+        total_bytes: content.len(),
+        total_lines: content.lines().count(),
+        start_line: None,
+        end_line: None,
+        offset: None,
+        length: None,
+    };
+
+    if range.start_line.is_some() || range.end_line.is_some() {
+        let start = range.start_line.unwrap_or(1).max(1);
+        let end = range.end_line.unwrap_or(result.total_lines).min(result.total_lines);
+        if start <= end {
+            result.content = content.split_inclusive('\n').skip(start - 1).take(end - start + 1).collect();
+        }
+        result.start_line = Some(start);
+        result.end_line = Some(end.max(start - 1));
+    } else {
+        let mut start = range.offset.unwrap_or(0).min(content.len());
+        let mut end = range.length.map(|l| start.saturating_add(l)).unwrap_or(content.len()).min(content.len());
+        while !content.is_char_boundary(start) {
+            start += 1;
+        }
+        while end > start && !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        result.content = content[start..end.max(start)].to_string();
+        result.offset = Some(start);
+        result.length = Some(end.saturating_sub(start));
+    }
+
+    result
+}
+
+/// 单个编辑操作
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FileEdit {
+    /// 替换文本，未设置 replace_all 时搜索文本必须唯一
+    Replace {
+        search: String,
+        replace: String,
+        #[serde(default)]
+        replace_all: bool,
+    },
+    /// 应用unified diff格式的补丁
+    Patch { diff: String },
+}
+
+/// 按顺序应用编辑操作，任一操作失败则整体失败
+pub fn apply_edits(content: &str, edits: &[FileEdit]) -> Result<String, String> {
+    let mut current = content.to_string();
+    for (index, edit) in edits.iter().enumerate() {
+        current = match edit {
+            FileEdit::Replace { search, replace, replace_all } => {
+                apply_replace(&current, search, replace, *replace_all)
+            }
+            FileEdit::Patch { diff } => apply_patch(&current, diff),
+        }
+        .map_err(|e| format!("第 {} 个编辑失败: {}", index + 1, e))?;
+    }
+    Ok(current)
+}
+
+fn apply_replace(content: &str, search: &str, replace: &str, replace_all: bool) -> Result<String, String> {
+    if search.is_empty() {
+        return Err("搜索文本不能为空".to_string());
+    }
+    match content.matches(search).count() {
+        0 => Err("未找到搜索文本".to_string()),
+        1 => Ok(content.replacen(search, replace, 1)),
+        _ if replace_all => Ok(content.replace(search, replace)),
+        count => Err(format!("搜索文本出现 {} 次，请提供更多上下文使其唯一", count)),
+    }
+}
+
+struct Hunk {
+    old_start: usize,
+    old_count: usize,
+    old_lines: Vec<String>,
+    new_lines: Vec<String>,
+}
+
+/// 解析unified diff中的补丁块，忽略第一个补丁块之前的 `---`/`+++` 文件头
+///
+/// 补丁只能修改一个文件，补丁块之后再出现文件头时报错。
+fn parse_hunks(diff: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut lines = diff.trim_end_matches(['\r', '\n']).lines().peekable();
+    while let Some(line) = lines.next() {
+        if let Some(header) = line.strip_prefix("@@") {
+            // @@ -old_start,old_count +new_start,new_count @@，省略count时为1
+            let old_range = header.split_whitespace()
+                .find_map(|part| part.strip_prefix('-'))
+                .ok_or_else(|| format!("补丁块头格式无效: {}", line))?;
+            let mut parts = old_range.splitn(2, ',');
+            let mut parse = |default: Option<usize>| match (parts.next(), default) {
+                (Some(value), _) => value.parse::<usize>().map_err(|_| format!("补丁块头格式无效: {}", line)),
+                (None, Some(default)) => Ok(default),
+                (None, None) => Err(format!("补丁块头格式无效: {}", line)),
+            };
+            let old_start = parse(None)?;
+            let old_count = parse(Some(1))?;
+            hunks.push(Hunk { old_start, old_count, old_lines: Vec::new(), new_lines: Vec::new() });
+            continue;
+        }
+
+        let Some(hunk) = hunks.last_mut() else { continue };
+        let is_file_header = line.starts_with("--- ")
+            && lines.peek().is_some_and(|next| next.starts_with("+++ "));
+        if is_file_header || line.starts_with("diff --git ") {
+            return Err("补丁包含多个文件的修改，每次只能修改一个文件".to_string());
+        }
+        if let Some(text) = line.strip_prefix('+') {
+            hunk.new_lines.push(text.to_string());
+        } else if let Some(text) = line.strip_prefix('-') {
+            hunk.old_lines.push(text.to_string());
+        } else if let Some(text) = line.strip_prefix(' ') {
+            hunk.old_lines.push(text.to_string());
+            hunk.new_lines.push(text.to_string());
+        } else if line.is_empty() {
+            // 部分工具会去掉空上下文行前的空格
+            hunk.old_lines.push(String::new());
+            hunk.new_lines.push(String::new());
+        } else if !line.starts_with('\\') {
+            return Err(format!("补丁行格式无效: {}", line));
+        }
+    }
+
+    if hunks.is_empty() {
+        return Err("补丁中没有找到补丁块".to_string());
+    }
+    Ok(hunks)
+}
+
+fn apply_patch(content: &str, diff: &str) -> Result<String, String> {
+    let hunks = parse_hunks(diff)?;
+    let eol = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let trailing_newline = content.is_empty() || content.ends_with('\n');
+    let mut lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
+
+    // 之前的补丁块导致的行号偏移
+    let mut shift: isize = 0;
+    for (index, hunk) in hunks.iter().enumerate() {
+        // 纯插入的补丁块（旧行数为0）插入在 old_start 行之后
+        let start = if hunk.old_count == 0 { hunk.old_start } else { hunk.old_start.max(1) - 1 };
+        let expected = (start as isize + shift).max(0) as usize;
+        let position = find_block(&lines, &hunk.old_lines, expected)
+            .ok_or_else(|| format!("第 {} 个补丁块的上下文与文件内容不匹配", index + 1))?;
+
+        lines.splice(position..position + hunk.old_lines.len(), hunk.new_lines.iter().cloned());
+        shift += hunk.new_lines.len() as isize - hunk.old_lines.len() as isize;
+        shift += position as isize - expected as isize;
+    }
+
+    let mut result = lines.join(eol);
+    if trailing_newline && !result.is_empty() {
+        result.push_str(eol);
+    }
+    Ok(result)
+}
+
+/// 从期望位置向两侧查找匹配的行块，返回最近的位置
+fn find_block(lines: &[String], block: &[String], expected: usize) -> Option<usize> {
+    if block.is_empty() {
+        return Some(expected.min(lines.len()));
+    }
+    if block.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - block.len();
+    let matches_at = |pos: usize| lines[pos..pos + block.len()] == *block;
+
+    let expected = expected.min(last);
+    for distance in 0..=last {
+        if expected + distance <= last && matches_at(expected + distance) {
+            return Some(expected + distance);
+        }
+        if distance <= expected && distance > 0 && matches_at(expected - distance) {
+            return Some(expected - distance);
+        }
+    }
+    None
+}
+
+/// 先写入同目录下的临时文件再重命名，避免写入中途失败留下半个文件
+///
+/// 保留原文件的权限；目标是符号链接时写入链接指向的文件，链接本身保持不变。
+pub fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let is_symlink = fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+    let path = if is_symlink { fs::canonicalize(path)? } else { path.to_path_buf() };
+
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    // 每次写入使用独立的临时文件，避免并发写入同一文件时互相覆盖或删除
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", name, Uuid::new_v4().simple()));
+    fs::write(&temp_path, content)?;
+    let permissions = match fs::metadata(&path) {
+        Ok(metadata) => fs::set_permissions(&temp_path, metadata.permissions()),
+        Err(_) => Ok(()),
+    };
+    permissions.and_then(|_| fs::rename(&temp_path, &path)).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_hunks_reads_ranges_and_skips_headers() {
+        let diff = "--- a/src/main.rs\n+++ b/src/main.rs\n@@ -3 +3 @@\n-old\n+new\n@@ -5,0 +6,2 @@ fn main\n+a\n+b\n\\ No newline at end of file\n";
+        let hunks = parse_hunks(diff).unwrap();
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].old_start, hunks[0].old_count), (3, 1));
+        assert_eq!((hunks[1].old_start, hunks[1].old_count), (5, 0));
+        assert_eq!(hunks[1].new_lines, vec!["a", "b"]);
+
+        assert!(parse_hunks("just text").is_err());
+        assert!(parse_hunks("@@ -x +1 @@\n").is_err());
+        assert!(parse_hunks("@@ -1 +1 @@\n-a\n+b\n*oops").is_err());
+    }
+
+    #[test]
+    fn rejects_multi_file_patches() {
+        let diff = "--- a/one.txt\n+++ b/one.txt\n@@ -1 +1 @@\n-a\n+b\n--- a/two.txt\n+++ b/two.txt\n@@ -1 +1 @@\n-c\n+d\n";
+        assert!(matches!(parse_hunks(diff), Err(e) if e.contains("多个文件")));
+        let git = "@@ -1 +1 @@\n-a\n+b\ndiff --git a/two.txt b/two.txt\n";
+        assert!(parse_hunks(git).is_err());
+        // 删除以 "-- " 开头的行不是文件头
+        assert_eq!(apply_patch("-- comment\nx\n", "@@ -1,2 +1 @@\n--- comment\n x\n").unwrap(), "x\n");
+    }
+
+    #[test]
+    fn pure_insertions_go_after_old_start() {
+        let content = "1\n2\n3\n4\n5\n6\n";
+        assert_eq!(apply_patch(content, "@@ -5,0 +6,2 @@\n+a\n+b\n").unwrap(), "1\n2\n3\n4\n5\na\nb\n6\n");
+        assert_eq!(apply_patch(content, "@@ -0,0 +1 @@\n+top\n").unwrap(), "top\n1\n2\n3\n4\n5\n6\n");
+        assert_eq!(apply_patch(content, "@@ -6,0 +7 @@\n+end\n").unwrap(), "1\n2\n3\n4\n5\n6\nend\n");
+    }
+
+    #[test]
+    fn hunks_with_offset_are_found_nearby() {
+        let content = "header\nextra\nfn a() {\n    old();\n}\nfn b() {\n    keep();\n}\n";
+        // 补丁中的行号比实际少一行，仍能就近匹配
+        let diff = "@@ -2,3 +2,3 @@\n fn a() {\n-    old();\n+    new();\n }\n@@ -5,3 +5,4 @@\n fn b() {\n     keep();\n+    more();\n }\n";
+        assert_eq!(
+            apply_patch(content, diff).unwrap(),
+            "header\nextra\nfn a() {\n    new();\n}\nfn b() {\n    keep();\n    more();\n}\n",
+        );
+        assert!(apply_patch(content, "@@ -1 +1 @@\n-missing\n+x\n").is_err());
+    }
+
+    #[test]
+    fn preserves_crlf_and_missing_trailing_newline() {
+        assert_eq!(apply_patch("a\r\nb\r\nc\r\n", "@@ -2 +2 @@\n-b\n+B\n").unwrap(), "a\r\nB\r\nc\r\n");
+        assert_eq!(apply_patch("a\nb", "@@ -2 +2 @@\n-b\n+B\n\\ No newline at end of file\n").unwrap(), "a\nB");
+        assert_eq!(apply_patch("", "@@ -0,0 +1 @@\n+new\n").unwrap(), "new\n");
+    }
+
+    #[test]
+    fn find_block_prefers_closest_match() {
+        let content = lines("x\ny\nx\ny\nx\ny");
+        let block = lines("x\ny");
+        assert_eq!(find_block(&content, &block, 0), Some(0));
+        // 距离相同时优先向后查找
+        assert_eq!(find_block(&content, &block, 3), Some(4));
+        assert_eq!(find_block(&content, &block, 10), Some(4));
+        assert_eq!(find_block(&content, &lines("z"), 0), None);
+        assert_eq!(find_block(&content, &[], 10), Some(6));
+        assert_eq!(find_block(&lines("x"), &block, 0), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_keeps_permissions_and_symlinks() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("run.sh");
+        fs::write(&script, "echo old").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o750)).unwrap();
+        symlink(&script, dir.path().join("link.sh")).unwrap();
+
+        write_atomic(&dir.path().join("link.sh"), b"echo new").unwrap();
+        assert_eq!(fs::read_to_string(&script).unwrap(), "echo new");
+        assert_eq!(fs::metadata(&script).unwrap().permissions().mode() & 0o777, 0o750);
+        assert!(fs::symlink_metadata(dir.path().join("link.sh")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn concurrent_atomic_writes_do_not_collide() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shared.txt");
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        write_atomic(&path, format!("writer {}", i).as_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(fs::read_to_string(&path).unwrap().starts_with("writer "));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
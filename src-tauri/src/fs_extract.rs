@@ -0,0 +1,454 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use crate::file_policy::is_binary;
+use crate::fs_binary::sniff_mime;
+
+/// DOCX中 `word/document.xml` 解压后的最大字节数，防止压缩炸弹耗尽内存
+const MAX_DOCX_XML_BYTES: u64 = 64 * 1024 * 1024;
+
+/// 文档提取选项
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ExtractOptions {
+    pub format: String, // "markdown" or "text"
+    pub max_chars: usize, // 提取结果的最大字符数，超出部分截断
+    pub chunk_size: usize, // 每块的最大字符数，0 表示不分块
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            format: "markdown".to_string(),
+            max_chars: 200_000,
+            chunk_size: 4000,
+        }
+    }
+}
+
+/// 提取结果的一块，`section` 为块开始处所在的页或章节
+#[derive(Debug, Serialize)]
+pub struct TextChunk {
+    pub index: usize,
+    pub section: Option<String>,
+    pub start_char: usize,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtractedDocument {
+    pub kind: String, // "pdf", "docx", "html", "csv", "text"
+    pub mime_type: String,
+    pub page_count: Option<usize>,
+    pub total_chars: usize,
+    pub truncated: bool,
+    pub chunks: Vec<TextChunk>,
+}
+
+/// 提取出的内容块，渲染时转换为带页码和章节标记的文本
+enum Block {
+    Page(usize),
+    Heading { level: usize, text: String },
+    Text(String),
+}
+
+/// 把PDF、DOCX、HTML、CSV或纯文本文件转换为文本并分块
+pub fn extract(path: &Path, bytes: &[u8], options: &ExtractOptions) -> Result<ExtractedDocument, String> {
+    let mime_type = sniff_mime(path, bytes);
+    let extension = path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let (kind, blocks, page_count) = if bytes.starts_with(b"%PDF") {
+        let blocks = extract_pdf(bytes)?;
+        let pages = blocks.iter().filter(|b| matches!(b, Block::Page(_))).count();
+        ("pdf", blocks, Some(pages))
+    } else if extension == "docx" || mime_type.contains("wordprocessingml") {
+        ("docx", extract_docx(bytes)?, None)
+    } else if matches!(extension.as_str(), "html" | "htm" | "xhtml") || mime_type == "text/html" {
+        ("html", extract_html(bytes), None)
+    } else if matches!(extension.as_str(), "csv" | "tsv") {
+        let delimiter = if extension == "tsv" { b'\t' } else { b',' };
+        ("csv", extract_csv(bytes, delimiter)?, None)
+    } else if !is_binary(bytes) {
+        ("text", vec![Block::Text(String::from_utf8_lossy(bytes).to_string())], None)
+    } else {
+        return Err(format!("不支持提取该类型的文件: {}", mime_type));
+    };
+
+    let markdown = options.format != "text";
+    let (mut text, markers) = render(&blocks, markdown);
+
+    let total_chars = text.chars().count();
+    let truncated = total_chars > options.max_chars;
+    if let Some((end, _)) = text.char_indices().nth(options.max_chars) {
+        text.truncate(end);
+    }
+
+    Ok(ExtractedDocument {
+        kind: kind.to_string(),
+        mime_type,
+        page_count,
+        total_chars,
+        truncated,
+        chunks: chunk(&text, &markers, options.chunk_size),
+    })
+}
+
+fn extract_pdf(bytes: &[u8]) -> Result<Vec<Block>, String> {
+    // pdf-extract 遇到格式异常的文件可能会panic
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes))
+        .map_err(|_| "解析PDF失败: 文件格式异常".to_string())?
+        .map_err(|e| format!("解析PDF失败: {}", e))?;
+
+    let mut blocks = Vec::new();
+    for (index, page) in pages.into_iter().enumerate() {
+        blocks.push(Block::Page(index + 1));
+        let page = page.trim();
+        if !page.is_empty() {
+            blocks.push(Block::Text(page.to_string()));
+        }
+    }
+    Ok(blocks)
+}
+
+/// 解析 `word/document.xml`，保留标题、列表和表格结构
+fn extract_docx(bytes: &[u8]) -> Result<Vec<Block>, String> {
+    let xml = read_zip_entry(bytes, "word/document.xml", MAX_DOCX_XML_BYTES)?;
+
+    let mut reader = Reader::from_str(&xml);
+    let mut blocks = Vec::new();
+    let mut paragraph = String::new();
+    let mut heading: Option<usize> = None;
+    let mut list_item = false;
+    let mut in_text = false;
+    // 表格：行、当前行的单元格、当前单元格
+    let mut table: Option<Vec<Vec<String>>> = None;
+    let mut row: Vec<String> = Vec::new();
+    let mut cell = String::new();
+
+    loop {
+        let event = reader.read_event().map_err(|e| format!("解析DOCX失败: {}", e))?;
+        match event {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"t" => in_text = true,
+                b"tab" => paragraph.push('\t'),
+                b"br" | b"cr" => paragraph.push('\n'),
+                b"pStyle" => heading = attribute(&e, b"val").and_then(|style| heading_level(&style)),
+                b"numPr" => list_item = true,
+                b"tbl" => table = Some(Vec::new()),
+                _ => {}
+            },
+            Event::Text(t) if in_text => {
+                paragraph.push_str(&t.unescape().map_err(|e| format!("解析DOCX失败: {}", e))?);
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => {
+                    let text = std::mem::take(&mut paragraph).trim().to_string();
+                    if table.is_some() {
+                        if !text.is_empty() {
+                            if !cell.is_empty() {
+                                cell.push(' ');
+                            }
+                            cell.push_str(&text);
+                        }
+                    } else if !text.is_empty() {
+                        blocks.push(match heading {
+                            Some(level) => Block::Heading { level, text },
+                            None if list_item => Block::Text(format!("- {}", text)),
+                            None => Block::Text(text),
+                        });
+                    }
+                    heading = None;
+                    list_item = false;
+                }
+                b"tc" => row.push(std::mem::take(&mut cell)),
+                b"tr" => {
+                    if let Some(rows) = table.as_mut() {
+                        rows.push(std::mem::take(&mut row));
+                    }
+                }
+                b"tbl" => {
+                    if let Some(rows) = table.take() {
+                        if !rows.is_empty() {
+                            blocks.push(Block::Text(render_table(&rows)));
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(blocks)
+}
+
+/// 读取压缩包中的文本文件，解压后超过 `limit` 字节时报错
+fn read_zip_entry(bytes: &[u8], name: &str, limit: u64) -> Result<String, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| format!("解析DOCX失败: {}", e))?;
+    let entry = archive.by_name(name).map_err(|e| format!("解析DOCX失败: {}", e))?;
+    let mut content = String::new();
+    entry.take(limit + 1)
+        .read_to_string(&mut content)
+        .map_err(|e| format!("解析DOCX失败: {}", e))?;
+    if content.len() as u64 > limit {
+        return Err(format!("解析DOCX失败: {} 解压后超过 {} MB", name, limit / 1024 / 1024));
+    }
+    Ok(content)
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element.attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok().map(|v| v.to_string()))
+}
+
+/// 内置样式 `Title`、`Heading1` 到 `Heading6`，更低级别的标题按正文处理
+fn heading_level(style: &str) -> Option<usize> {
+    let style = style.to_ascii_lowercase().replace(' ', "");
+    if style == "title" {
+        return Some(1);
+    }
+    style.strip_prefix("heading")?.parse::<usize>().ok().filter(|l| (1..=6).contains(l))
+}
+
+fn extract_html(bytes: &[u8]) -> Vec<Block> {
+    // 宽度足够大，避免按固定宽度折行
+    let text = html2text::from_read(bytes, 10_000);
+
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        let level = line.chars().take_while(|c| *c == '#').count();
+        if (1..=6).contains(&level) && line[level..].starts_with(' ') {
+            if !paragraph.is_empty() {
+                blocks.push(Block::Text(paragraph.join("\n").trim().to_string()));
+                paragraph.clear();
+            }
+            blocks.push(Block::Heading { level, text: line[level..].trim().to_string() });
+        } else {
+            paragraph.push(line);
+        }
+    }
+    let rest = paragraph.join("\n");
+    if !rest.trim().is_empty() {
+        blocks.push(Block::Text(rest.trim().to_string()));
+    }
+    blocks
+}
+
+fn extract_csv(bytes: &[u8], delimiter: u8) -> Result<Vec<Block>, String> {
+    let content = String::from_utf8_lossy(bytes);
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let rows = reader.records()
+        .map(|record| record.map(|r| r.iter().map(|field| field.to_string()).collect()))
+        .collect::<Result<Vec<Vec<String>>, _>>()
+        .map_err(|e| format!("解析CSV失败: {}", e))?;
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(vec![Block::Text(render_table(&rows))])
+}
+
+/// 渲染为markdown表格，第一行作为表头
+fn render_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0).max(1);
+    let line = |row: &[String]| {
+        let cells: Vec<String> = (0..columns)
+            .map(|i| row.get(i).map(|c| c.replace('|', "\\|").replace(['\r', '\n'], " ")).unwrap_or_default())
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = vec![line(&rows[0]), format!("|{}", " --- |".repeat(columns))];
+    lines.extend(rows[1..].iter().map(|row| line(row)));
+    lines.join("\n")
+}
+
+/// 拼接内容块，同时记录每个页码或章节标记在结果中的字节偏移
+fn render(blocks: &[Block], markdown: bool) -> (String, Vec<(usize, String)>) {
+    let mut text = String::new();
+    let mut markers = Vec::new();
+    for block in blocks {
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        match block {
+            Block::Page(number) => {
+                markers.push((text.len(), format!("第 {} 页", number)));
+                text.push_str(&format!("--- 第 {} 页 ---", number));
+            }
+            Block::Heading { level, text: heading } => {
+                markers.push((text.len(), heading.clone()));
+                if markdown {
+                    text.push_str(&format!("{} {}", "#".repeat(*level), heading));
+                } else {
+                    text.push_str(heading);
+                }
+            }
+            Block::Text(content) => text.push_str(content),
+        }
+    }
+    (text, markers)
+}
+
+/// 按字符数分块，优先在空行处切分，其次在换行处
+fn chunk(text: &str, markers: &[(usize, String)], chunk_size: usize) -> Vec<TextChunk> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut start_char = 0;
+    while start < text.len() {
+        let rest = &text[start..];
+        let mut end = match (chunk_size > 0).then(|| rest.char_indices().nth(chunk_size)).flatten() {
+            Some((limit, _)) => {
+                // 只在后半段寻找切分点，避免块过小
+                let window = &rest[..limit];
+                let half = window.char_indices().nth(chunk_size / 2).map(|(i, _)| i).unwrap_or(0);
+                window[half..].rfind("\n\n").map(|i| half + i + 2)
+                    .or_else(|| window[half..].rfind('\n').map(|i| half + i + 1))
+                    .unwrap_or(limit)
+            }
+            None => rest.len(),
+        };
+        end = end.max(1);
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+
+        let content = &rest[..end];
+        let section = markers.iter()
+            .take_while(|(offset, _)| *offset <= start)
+            .last()
+            .map(|(_, label)| label.clone());
+        if !content.trim().is_empty() {
+            chunks.push(TextChunk {
+                index: chunks.len(),
+                section,
+                start_char,
+                content: content.to_string(),
+            });
+        }
+        start_char += content.chars().count();
+        start += end;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn docx(document_xml: &str) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("word/document.xml", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(document_xml.as_bytes()).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn paragraph(style: Option<&str>, text: &str) -> String {
+        let style = style.map(|s| format!(r#"<w:pPr><w:pStyle w:val="{}"/></w:pPr>"#, s)).unwrap_or_default();
+        format!("<w:p>{}<w:r><w:t>{}</w:t></w:r></w:p>", style, text)
+    }
+
+    fn options(chunk_size: usize) -> ExtractOptions {
+        ExtractOptions { chunk_size, ..ExtractOptions::default() }
+    }
+
+    #[test]
+    fn docx_headings_lists_and_tables() {
+        let cell = |text: &str| format!("<w:tc>{}</w:tc>", paragraph(None, text));
+        let body = [
+            paragraph(Some("Title"), "报告"),
+            paragraph(Some("Heading2"), "背景"),
+            paragraph(None, "正文 &amp; 说明"),
+            r#"<w:p><w:pPr><w:numPr/></w:pPr><w:r><w:t>要点</w:t></w:r></w:p>"#.to_string(),
+            format!("<w:tbl><w:tr>{}{}</w:tr><w:tr>{}{}</w:tr></w:tbl>", cell("名称"), cell("值"), cell("a|b"), cell("1")),
+        ].concat();
+        let xml = format!(r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>{}</w:body></w:document>"#, body);
+
+        let document = extract(Path::new("report.docx"), &docx(&xml), &options(0)).unwrap();
+        assert_eq!(document.kind, "docx");
+        assert_eq!(document.chunks.len(), 1);
+        assert_eq!(
+            document.chunks[0].content,
+            "# 报告\n\n## 背景\n\n正文 & 说明\n\n- 要点\n\n| 名称 | 值 |\n| --- | --- |\n| a\\|b | 1 |",
+        );
+
+        let text = extract(Path::new("report.docx"), &docx(&xml), &ExtractOptions { format: "text".to_string(), ..options(0) }).unwrap();
+        assert!(text.chunks[0].content.starts_with("报告\n\n背景\n\n"));
+    }
+
+    #[test]
+    fn docx_entry_size_is_limited() {
+        let bytes = docx(&"x".repeat(2048));
+        assert_eq!(read_zip_entry(&bytes, "word/document.xml", 2048).unwrap().len(), 2048);
+        assert!(read_zip_entry(&bytes, "word/document.xml", 1024).unwrap_err().contains("超过"));
+        assert!(read_zip_entry(&bytes, "word/missing.xml", 1024).is_err());
+        assert!(extract(Path::new("bad.docx"), b"PK not a zip", &options(0)).is_err());
+    }
+
+    #[test]
+    fn csv_and_html_rendering() {
+        let csv = extract(Path::new("data.csv"), "name,note\n张三,\"a, b\"\n李四\n".as_bytes(), &options(0)).unwrap();
+        assert_eq!(csv.chunks[0].content, "| name | note |\n| --- | --- |\n| 张三 | a, b |\n| 李四 |  |");
+        let tsv = extract(Path::new("data.tsv"), b"a\tb\n1\t2", &options(0)).unwrap();
+        assert_eq!(tsv.chunks[0].content, "| a | b |\n| --- | --- |\n| 1 | 2 |");
+
+        let html = "<html><body><h1>标题</h1><p>第一段</p><h2>小节</h2><p>第二段</p></body></html>";
+        let document = extract(Path::new("page.html"), html.as_bytes(), &options(0)).unwrap();
+        assert_eq!(document.kind, "html");
+        assert_eq!(document.chunks[0].content, "# 标题\n\n第一段\n\n## 小节\n\n第二段");
+    }
+
+    #[test]
+    fn chunks_split_on_blank_lines_and_track_sections() {
+        let blocks = vec![
+            Block::Heading { level: 1, text: "一".to_string() },
+            Block::Text("甲".repeat(6)),
+            Block::Heading { level: 1, text: "二".to_string() },
+            Block::Text("乙".repeat(6)),
+        ];
+        let (text, markers) = render(&blocks, true);
+        let chunks = chunk(&text, &markers, 13);
+
+        // 每块不超过13个字符，且在空行后切分
+        assert!(chunks.iter().all(|c| c.content.chars().count() <= 13));
+        assert_eq!(chunks.iter().map(|c| c.content.as_str()).collect::<String>(), text);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].content, "# 一\n\n甲甲甲甲甲甲\n\n");
+        assert_eq!(chunks[0].section.as_deref(), Some("一"));
+        assert!(chunks[1].content.starts_with("# 二"));
+        assert_eq!(chunks[1].section.as_deref(), Some("二"));
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[1].start_char, pair[0].start_char + pair[0].content.chars().count());
+            assert_eq!(pair[1].index, pair[0].index + 1);
+        }
+    }
+
+    #[test]
+    fn chunks_respect_multibyte_boundaries_and_truncation() {
+        let text = "汉字".repeat(10);
+        let chunks = chunk(&text, &[], 7);
+        assert_eq!(chunks.iter().map(|c| c.content.chars().count()).collect::<Vec<_>>(), vec![7, 7, 6]);
+        assert_eq!(chunks[2].start_char, 14);
+        assert_eq!(chunk(&text, &[], 0).len(), 1);
+
+        let document = extract(Path::new("a.txt"), text.as_bytes(), &ExtractOptions { max_chars: 5, ..options(0) }).unwrap();
+        assert!(document.truncated);
+        assert_eq!(document.total_chars, 20);
+        assert_eq!(document.chunks[0].content, "汉字汉字汉");
+        assert!(extract(Path::new("a.bin"), b"\x00\x01\x02", &options(0)).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use ignore::WalkBuilder;
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::file_policy::is_binary;
+use crate::workspace::ResolvedPath;
+
+/// 返回的单行内容最大字符数，避免压缩后的超长行撑爆结果
+const MAX_LINE_CHARS: usize = 500;
+
+/// 内容搜索选项
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GrepOptions {
+    pub case_sensitive: bool,
+    pub fixed_string: bool, // 按普通字符串而不是正则表达式匹配
+    pub context_lines: usize,
+    pub max_results: usize,
+    pub max_duration_ms: u64,
+    pub respect_gitignore: bool,
+}
+
+impl Default for GrepOptions {
+    fn default() -> Self {
+        Self {
+            case_sensitive: false,
+            fixed_string: false,
+            context_lines: 2,
+            max_results: 200,
+            max_duration_ms: 5000,
+            respect_gitignore: true,
+        }
+    }
+}
+
+/// 单个匹配结果，行号和列号从1开始，列号按字符计算
+#[derive(Debug, Serialize)]
+pub struct GrepMatch {
+    pub path: String,
+    pub line_number: usize,
+    pub column: usize,
+    pub line: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GrepResult {
+    pub matches: Vec<GrepMatch>,
+    pub files_scanned: usize,
+    pub truncated: bool, // 达到结果数量上限
+    pub timed_out: bool, // 达到扫描时间上限
+}
+
+/// 在工作区路径下搜索文件内容
+///
+/// 遵守 .gitignore 和工作区策略，跳过二进制文件和超过大小限制的文件。
+pub fn grep(resolved: &ResolvedPath, pattern: &str, options: &GrepOptions) -> Result<GrepResult, String> {
+    let pattern = if options.fixed_string { regex::escape(pattern) } else { pattern.to_string() };
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|e| format!("正则表达式错误: {}", e))?;
+
+    let deadline = Instant::now() + Duration::from_millis(options.max_duration_ms);
+    let max_results = options.max_results.max(1);
+
+    let root = resolved.root.clone();
+    let policy = resolved.policy.clone();
+    let walker = WalkBuilder::new(&resolved.path)
+        // 隐藏文件由工作区策略决定
+        .hidden(false)
+        .git_ignore(options.respect_gitignore)
+        .git_global(options.respect_gitignore)
+        .git_exclude(options.respect_gitignore)
+        .ignore(options.respect_gitignore)
+        .require_git(false)
+        .filter_entry(move |entry| {
+            let relative = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            policy.check_path(relative, is_dir).is_ok()
+        })
+        .build();
+
+    let mut result = GrepResult {
+        matches: Vec::new(),
+        files_scanned: 0,
+        truncated: false,
+        timed_out: false,
+    };
+
+    for entry in walker.flatten() {
+        if Instant::now() > deadline {
+            result.timed_out = true;
+            break;
+        }
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(&resolved.root).unwrap_or(entry.path());
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        if resolved.policy.check_size(relative, size).is_err() {
+            continue;
+        }
+
+        let Ok(bytes) = fs::read(entry.path()) else { continue };
+        if is_binary(&bytes) {
+            continue;
+        }
+        result.files_scanned += 1;
+
+        let content = String::from_utf8_lossy(&bytes);
+        let completed = search_content(&content, relative, &regex, options.context_lines, max_results, deadline, &mut result.matches);
+        if !completed {
+            result.timed_out = true;
+            break;
+        }
+        if result.matches.len() >= max_results {
+            result.truncated = true;
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+/// 搜索单个文件的内容，超过截止时间时返回 false
+fn search_content(
+    content: &str,
+    relative: &Path,
+    regex: &regex::Regex,
+    context_lines: usize,
+    max_results: usize,
+    deadline: Instant,
+    matches: &mut Vec<GrepMatch>,
+) -> bool {
+    let lines: Vec<&str> = content.lines().collect();
+    for (index, line) in lines.iter().enumerate() {
+        // 大文件可能耗时很久，逐行检查截止时间
+        if Instant::now() > deadline {
+            return false;
+        }
+        let Some(found) = regex.find(line) else { continue };
+
+        let before_start = index.saturating_sub(context_lines);
+        let after_end = (index + 1 + context_lines).min(lines.len());
+        matches.push(GrepMatch {
+            path: relative.to_string_lossy().to_string(),
+            line_number: index + 1,
+            column: line[..found.start()].chars().count() + 1,
+            line: truncate_line(line),
+            before: lines[before_start..index].iter().map(|l| truncate_line(l)).collect(),
+            after: lines[index + 1..after_end].iter().map(|l| truncate_line(l)).collect(),
+        });
+
+        if matches.len() >= max_results {
+            return true;
+        }
+    }
+    true
+}
+
+fn truncate_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::{resolve, test_root, WorkspaceAccess, MODE_READ_ONLY};
+
+    fn resolve_root(dir: &Path, policy: Option<&str>) -> ResolvedPath {
+        let mut root = test_root(dir, MODE_READ_ONLY);
+        root.policy = policy.map(str::to_string);
+        resolve(&root, "", WorkspaceAccess::Read).unwrap()
+    }
+
+    fn paths(result: &GrepResult) -> Vec<String> {
+        let mut paths: Vec<String> = result.matches.iter().map(|m| m.path.replace('\\', "/")).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn respects_gitignore_and_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join("target")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn needle() {}\n").unwrap();
+        fs::write(dir.path().join("src/secret.pem"), "needle\n").unwrap();
+        fs::write(dir.path().join("target/out.rs"), "needle\n").unwrap();
+        fs::write(dir.path().join("debug.log"), "needle\n").unwrap();
+        fs::write(dir.path().join("data.bin"), b"needle\x00\x01").unwrap();
+
+        let resolved = resolve_root(dir.path(), Some(r#"{"deny":["*.pem"]}"#));
+        let result = grep(&resolved, "NEEDLE", &GrepOptions::default()).unwrap();
+        assert_eq!(paths(&result), vec!["src/main.rs"]);
+        assert_eq!(result.matches[0].column, 4);
+
+        let options = GrepOptions { respect_gitignore: false, case_sensitive: true, ..GrepOptions::default() };
+        let result = grep(&resolved, "needle", &options).unwrap();
+        assert_eq!(paths(&result), vec!["debug.log", "src/main.rs", "target/out.rs"]);
+    }
+
+    #[test]
+    fn context_lines_and_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = (1..=10).map(|i| format!("line {}", i)).collect::<Vec<_>>().join("\n");
+        fs::write(dir.path().join("a.txt"), content).unwrap();
+        let resolved = resolve_root(dir.path(), None);
+
+        let options = GrepOptions { context_lines: 2, ..GrepOptions::default() };
+        let result = grep(&resolved, "line 1$", &options).unwrap();
+        assert_eq!(result.matches.len(), 1);
+        assert!(result.matches[0].before.is_empty());
+        assert_eq!(result.matches[0].after, vec!["line 2", "line 3"]);
+
+        let result = grep(&resolved, "line 9", &options).unwrap();
+        assert_eq!(result.matches[0].before, vec!["line 7", "line 8"]);
+        assert_eq!(result.matches[0].after, vec!["line 10"]);
+
+        let options = GrepOptions { max_results: 3, ..GrepOptions::default() };
+        let result = grep(&resolved, "line", &options).unwrap();
+        assert_eq!(result.matches.len(), 3);
+        assert!(result.truncated);
+
+        let result = grep(&resolved, "l.ne 1+", &GrepOptions { fixed_string: true, ..GrepOptions::default() }).unwrap();
+        assert!(result.matches.is_empty());
+        assert!(grep(&resolved, "(", &GrepOptions::default()).is_err());
+    }
+
+    #[test]
+    fn deadline_is_checked_within_a_file() {
+        let regex = regex::Regex::new("x").unwrap();
+        let mut matches = Vec::new();
+        let expired = Instant::now() - Duration::from_millis(1);
+        assert!(!search_content("x\nx\nx", Path::new("a.txt"), &regex, 0, 10, expired, &mut matches));
+        assert!(matches.is_empty());
+
+        let later = Instant::now() + Duration::from_secs(60);
+        assert!(search_content("x\ny\nx", Path::new("a.txt"), &regex, 0, 10, later, &mut matches));
+        assert_eq!(matches.len(), 2);
+    }
+}
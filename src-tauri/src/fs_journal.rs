@@ -0,0 +1,652 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const MANIFEST_FILE: &str = "entry.json";
+const RETENTION_FILE: &str = "retention.json";
+const ID_TIME_FORMAT: &str = "%Y%m%d%H%M%S%3f";
+
+/// 日志保留策略
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    pub max_age_days: u32,
+    pub max_entries: usize,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_days: 30,
+            max_entries: 200,
+        }
+    }
+}
+
+/// 操作前单个路径的状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub display_path: String, // 相对于工作区的路径，用于展示
+    pub path: PathBuf,
+    pub existed: bool,
+    pub is_dir: bool,
+    pub backup: Option<String>, // 备份在日志条目目录中的名称
+    #[serde(default)]
+    pub after: Option<PathState>, // 操作完成后的状态，撤销前据此确认没有后续修改
+    #[serde(default)]
+    pub moved_from: Option<PathBuf>, // 移动操作的目标路径记录源路径，撤销时移回而不是恢复备份
+}
+
+impl Snapshot {
+    /// 移动的目标撤销时整体移回，其中的后续修改会随之保留，只需比较类型而不必哈希整个目录树
+    fn current_state(&self) -> io::Result<PathState> {
+        if self.moved_from.is_some() {
+            PathState::shallow(&self.path)
+        } else {
+            PathState::of(&self.path)
+        }
+    }
+}
+
+/// 路径在某一时刻的状态
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathState {
+    pub exists: bool,
+    pub is_dir: bool,
+    pub hash: Option<String>, // 文件内容或整个目录树的SHA-256
+}
+
+impl PathState {
+    fn of(path: &Path) -> io::Result<Self> {
+        let mut state = Self::shallow(path)?;
+        if state.exists {
+            let mut hasher = Sha256::new();
+            hash_tree(path, &mut hasher)?;
+            state.hash = Some(format!("{:x}", hasher.finalize()));
+        }
+        Ok(state)
+    }
+
+    /// 只记录是否存在和类型，不计算哈希
+    fn shallow(path: &Path) -> io::Result<Self> {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) => Ok(Self { exists: true, is_dir: metadata.is_dir(), hash: None }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self { exists: false, is_dir: false, hash: None }),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// 一次文件系统操作的日志条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: String,
+    pub root_id: String,
+    pub operation: String, // "write", "edit", "delete_file", "delete_directory", "move", "copy"
+    pub created_at: DateTime<Utc>,
+    pub undone_at: Option<DateTime<Utc>>,
+    pub snapshots: Vec<Snapshot>,
+}
+
+/// 文件操作日志和回收站
+///
+/// 每个条目是日志目录下的一个子目录，包含 `entry.json` 和受影响文件的备份。
+/// 删除操作把文件移入条目目录而不是直接删除，撤销时按相反顺序恢复。
+pub struct FsJournal {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FsJournal {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, lock: Mutex::new(()) }
+    }
+
+    /// 在修改文件前备份目标路径的当前状态
+    pub fn record(&self, root_id: &str, operation: &str, targets: &[(&str, &Path)]) -> Result<JournalEntry, String> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entry = self.new_entry(root_id, operation)?;
+        let entry_dir = self.dir.join(&entry.id);
+
+        for (index, (display_path, path)) in targets.iter().enumerate() {
+            let snapshot = backup_snapshot(&entry_dir, index, display_path, path).inspect_err(|_| {
+                let _ = fs::remove_dir_all(&entry_dir);
+            })?;
+            entry.snapshots.push(snapshot);
+        }
+
+        self.save_entry(&entry).inspect_err(|_| {
+            let _ = fs::remove_dir_all(&entry_dir);
+        })?;
+        self.apply_saved_retention();
+        Ok(entry)
+    }
+
+    /// 移动文件或目录：只记录源和目标路径，撤销时把目标移回源路径
+    ///
+    /// 源路径不做备份，移动整个目录树时无需复制；目标已存在时会被覆盖，仍然备份。
+    pub fn record_move(&self, root_id: &str, source: (&str, &Path), target: (&str, &Path)) -> Result<JournalEntry, String> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entry = self.new_entry(root_id, "move")?;
+        let entry_dir = self.dir.join(&entry.id);
+
+        let (source_display, source_path) = source;
+        let (target_display, target_path) = target;
+        let snapshots = fs::symlink_metadata(source_path)
+            .map_err(|e| format!("读取文件信息失败: {}", e))
+            .and_then(|metadata| {
+                let mut moved = backup_snapshot(&entry_dir, 1, target_display, target_path)?;
+                moved.moved_from = Some(source_path.to_path_buf());
+                let source = Snapshot {
+                    display_path: source_display.to_string(),
+                    path: source_path.to_path_buf(),
+                    existed: true,
+                    is_dir: metadata.is_dir(),
+                    backup: None,
+                    after: None,
+                    moved_from: None,
+                };
+                Ok(vec![source, moved])
+            });
+        entry.snapshots = snapshots.inspect_err(|_| {
+            let _ = fs::remove_dir_all(&entry_dir);
+        })?;
+
+        self.save_entry(&entry).inspect_err(|_| {
+            let _ = fs::remove_dir_all(&entry_dir);
+        })?;
+        self.apply_saved_retention();
+        Ok(entry)
+    }
+
+    /// 删除文件或目录：移入回收站并记录日志
+    pub fn record_delete(&self, root_id: &str, operation: &str, display_path: &str, path: &Path) -> Result<JournalEntry, String> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entry = self.new_entry(root_id, operation)?;
+        let entry_dir = self.dir.join(&entry.id);
+        let backup = entry_dir.join("0");
+
+        let metadata = fs::symlink_metadata(path).map_err(|e| format!("读取文件信息失败: {}", e))?;
+        move_path(path, &backup).map_err(|e| {
+            let _ = fs::remove_dir_all(&entry_dir);
+            format!("移入回收站失败: {}", e)
+        })?;
+        entry.snapshots.push(Snapshot {
+            display_path: display_path.to_string(),
+            path: path.to_path_buf(),
+            existed: true,
+            is_dir: metadata.is_dir(),
+            backup: Some("0".to_string()),
+            after: Some(PathState { exists: false, is_dir: false, hash: None }),
+            moved_from: None,
+        });
+
+        if let Err(e) = self.save_entry(&entry) {
+            // 日志写入失败时还原，避免文件丢失
+            let _ = move_path(&backup, path);
+            let _ = fs::remove_dir_all(&entry_dir);
+            return Err(e);
+        }
+        self.apply_saved_retention();
+        Ok(entry)
+    }
+
+    /// 操作成功后记录每个路径的最新状态，没有该状态的条目不能撤销
+    pub fn complete(&self, id: &str) {
+        let _guard = self.lock.lock().unwrap();
+        let result = load_entry(&self.dir.join(id)).and_then(|mut entry| {
+            for snapshot in entry.snapshots.iter_mut() {
+                let state = snapshot.current_state()
+                    .map_err(|e| format!("读取 {} 的状态失败: {}", snapshot.display_path, e))?;
+                snapshot.after = Some(state);
+            }
+            self.save_entry(&entry)
+        });
+        if let Err(e) = result {
+            warn!("记录操作后的文件状态失败: {} - {}", id, e);
+        }
+    }
+
+    /// 读取单个日志条目
+    pub fn get(&self, id: &str) -> Result<JournalEntry, String> {
+        validate_id(id)?;
+        let _guard = self.lock.lock().unwrap();
+        load_entry(&self.dir.join(id)).map_err(|e| format!("未找到操作日志: {} - {}", id, e))
+    }
+
+    /// 操作失败时丢弃刚记录的条目
+    pub fn discard(&self, id: &str) {
+        let _guard = self.lock.lock().unwrap();
+        if let Err(e) = fs::remove_dir_all(self.dir.join(id)) {
+            warn!("丢弃操作日志失败: {} - {}", id, e);
+        }
+    }
+
+    /// 按时间倒序列出日志条目
+    pub fn list(&self, root_id: Option<&str>, limit: usize) -> Result<Vec<JournalEntry>, String> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entries: Vec<JournalEntry> = self.load_all()?
+            .into_iter()
+            .filter(|e| root_id.map_or(true, |id| e.root_id == id))
+            .collect();
+        entries.truncate(limit);
+        Ok(entries)
+    }
+
+    /// 撤销操作，按相反顺序把每个路径恢复到操作前的状态
+    ///
+    /// `resolve` 按当前的工作区配置重新解析每个路径，结果必须与记录时一致；
+    /// 任一路径在操作后又被修改时拒绝撤销，避免覆盖之后的内容。
+    pub fn undo(&self, id: &str, resolve: impl Fn(&Snapshot) -> Result<PathBuf, String>) -> Result<JournalEntry, String> {
+        validate_id(id)?;
+        let _guard = self.lock.lock().unwrap();
+        let entry_dir = self.dir.join(id);
+        let mut entry = load_entry(&entry_dir).map_err(|e| format!("未找到操作日志: {} - {}", id, e))?;
+        if entry.undone_at.is_some() {
+            return Err("该操作已经撤销".to_string());
+        }
+
+        // 修改任何文件前先检查所有路径
+        for snapshot in &entry.snapshots {
+            if resolve(snapshot)? != snapshot.path {
+                return Err(format!("工作区配置已变化，无法撤销 {}", snapshot.display_path));
+            }
+            let after = snapshot.after.as_ref()
+                .ok_or_else(|| format!("缺少 {} 在操作后的状态，无法撤销", snapshot.display_path))?;
+            let current = snapshot.current_state()
+                .map_err(|e| format!("读取 {} 的状态失败: {}", snapshot.display_path, e))?;
+            if current != *after {
+                return Err(format!("{} 在操作后已被修改，无法撤销", snapshot.display_path));
+            }
+        }
+
+        let moved_back: Vec<&PathBuf> = entry.snapshots.iter().filter_map(|s| s.moved_from.as_ref()).collect();
+        for snapshot in entry.snapshots.iter().rev() {
+            if let Some(source) = &snapshot.moved_from {
+                // 移动的目标直接移回源路径
+                move_path(&snapshot.path, source)
+                    .map_err(|e| format!("撤销失败，无法移回 {}: {}", snapshot.display_path, e))?;
+            } else if moved_back.contains(&&snapshot.path) {
+                // 源路径的内容已由目标移回
+            } else if let Ok(metadata) = fs::symlink_metadata(&snapshot.path) {
+                // 移除操作产生的内容
+                let removed = if metadata.is_dir() {
+                    fs::remove_dir_all(&snapshot.path)
+                } else {
+                    fs::remove_file(&snapshot.path)
+                };
+                removed.map_err(|e| format!("撤销失败，无法移除 {}: {}", snapshot.display_path, e))?;
+            }
+
+            if let Some(backup) = &snapshot.backup {
+                if let Some(parent) = snapshot.path.parent() {
+                    fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+                }
+                move_path(&entry_dir.join(backup), &snapshot.path)
+                    .map_err(|e| format!("撤销失败，无法恢复 {}: {}", snapshot.display_path, e))?;
+            }
+        }
+
+        entry.undone_at = Some(Utc::now());
+        self.save_entry(&entry)?;
+        info!("已撤销文件操作: {} ({})", entry.id, entry.operation);
+        Ok(entry)
+    }
+
+    /// 删除单个日志条目及其备份
+    pub fn purge_entry(&self, id: &str) -> Result<usize, String> {
+        validate_id(id)?;
+        let _guard = self.lock.lock().unwrap();
+        let entry_dir = self.dir.join(id);
+        if !entry_dir.is_dir() {
+            return Ok(0);
+        }
+        fs::remove_dir_all(&entry_dir).map_err(|e| format!("清理操作日志失败: {}", e))?;
+        Ok(1)
+    }
+
+    /// 保存保留策略并立即清理，返回删除的条目数
+    pub fn purge(&self, retention: Option<RetentionPolicy>) -> Result<usize, String> {
+        let _guard = self.lock.lock().unwrap();
+        let retention = match retention {
+            Some(retention) => {
+                fs::create_dir_all(&self.dir).map_err(|e| format!("创建日志目录失败: {}", e))?;
+                let json = serde_json::to_string_pretty(&retention).map_err(|e| e.to_string())?;
+                fs::write(self.dir.join(RETENTION_FILE), json).map_err(|e| format!("保存保留策略失败: {}", e))?;
+                retention
+            }
+            None => self.saved_retention(),
+        };
+        self.apply_retention(&retention)
+    }
+
+    fn saved_retention(&self) -> RetentionPolicy {
+        fs::read_to_string(self.dir.join(RETENTION_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// 记录新条目后按保存的策略清理，未超出限制时不读取任何条目
+    fn apply_saved_retention(&self) {
+        let retention = self.saved_retention();
+        if !self.exceeds_retention(&retention) {
+            return;
+        }
+        if let Err(e) = self.apply_retention(&retention) {
+            warn!("清理过期操作日志失败: {}", e);
+        }
+    }
+
+    /// 条目ID以创建时间开头，只需列出目录名即可判断数量或最早的条目是否超出限制
+    fn exceeds_retention(&self, retention: &RetentionPolicy) -> bool {
+        let Ok(read_dir) = fs::read_dir(&self.dir) else {
+            return false;
+        };
+        let cutoff = (Utc::now() - Duration::days(retention.max_age_days as i64)).format(ID_TIME_FORMAT).to_string();
+        let ids: Vec<String> = read_dir
+            .flatten()
+            .filter(|e| e.path().is_dir())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        ids.len() > retention.max_entries || ids.iter().any(|id| *id < cutoff)
+    }
+
+    /// 删除超过保留天数或超出数量上限的条目（保留最新的）
+    fn apply_retention(&self, retention: &RetentionPolicy) -> Result<usize, String> {
+        let cutoff = Utc::now() - Duration::days(retention.max_age_days as i64);
+        let mut removed = 0;
+        for (index, entry) in self.load_all()?.iter().enumerate() {
+            if index >= retention.max_entries || entry.created_at < cutoff {
+                if let Err(e) = fs::remove_dir_all(self.dir.join(&entry.id)) {
+                    warn!("删除操作日志失败: {} - {}", entry.id, e);
+                    continue;
+                }
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            info!("已清理 {} 条操作日志", removed);
+        }
+        Ok(removed)
+    }
+
+    fn new_entry(&self, root_id: &str, operation: &str) -> Result<JournalEntry, String> {
+        let now = Utc::now();
+        // 以时间开头，目录名即可按时间排序
+        let id = format!("{}-{}", now.format(ID_TIME_FORMAT), &Uuid::new_v4().simple().to_string()[..8]);
+        fs::create_dir_all(self.dir.join(&id)).map_err(|e| format!("创建操作日志失败: {}", e))?;
+        Ok(JournalEntry {
+            id,
+            root_id: root_id.to_string(),
+            operation: operation.to_string(),
+            created_at: now,
+            undone_at: None,
+            snapshots: Vec::new(),
+        })
+    }
+
+    fn save_entry(&self, entry: &JournalEntry) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(entry).map_err(|e| e.to_string())?;
+        fs::write(self.dir.join(&entry.id).join(MANIFEST_FILE), json)
+            .map_err(|e| format!("写入操作日志失败: {}", e))
+    }
+
+    /// 读取全部条目，按时间倒序
+    fn load_all(&self) -> Result<Vec<JournalEntry>, String> {
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("读取操作日志失败: {}", e)),
+        };
+
+        let mut entries: Vec<JournalEntry> = read_dir
+            .flatten()
+            .filter(|e| e.path().is_dir())
+            .filter_map(|e| load_entry(&e.path()).ok())
+            .collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+        Ok(entries)
+    }
+}
+
+/// 按路径名、类型和内容计算目录树的哈希，子项按名称排序
+fn hash_tree(path: &Path, hasher: &mut Sha256) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        hasher.update(b"dir\0");
+        let mut children: Vec<_> = fs::read_dir(path)?.collect::<io::Result<_>>()?;
+        children.sort_by_key(|entry| entry.file_name());
+        for child in children {
+            hasher.update(child.file_name().to_string_lossy().as_bytes());
+            hasher.update(b"\0");
+            hash_tree(&child.path(), hasher)?;
+        }
+        hasher.update(b"end\0");
+    } else if metadata.file_type().is_symlink() {
+        hasher.update(b"link\0");
+        hasher.update(fs::read_link(path)?.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+    } else {
+        hasher.update(b"file\0");
+        hasher.update(metadata.len().to_le_bytes());
+        io::copy(&mut fs::File::open(path)?, hasher)?;
+    }
+    Ok(())
+}
+
+/// 把路径的当前状态备份到条目目录中的 `index`，路径不存在时不备份
+fn backup_snapshot(entry_dir: &Path, index: usize, display_path: &str, path: &Path) -> Result<Snapshot, String> {
+    let metadata = fs::symlink_metadata(path).ok();
+    let backup = match &metadata {
+        Some(_) => {
+            let name = format!("{}", index);
+            copy_recursive(path, &entry_dir.join(&name))
+                .map_err(|e| format!("备份文件失败: {} - {}", display_path, e))?;
+            Some(name)
+        }
+        None => None,
+    };
+    Ok(Snapshot {
+        display_path: display_path.to_string(),
+        path: path.to_path_buf(),
+        existed: metadata.is_some(),
+        is_dir: metadata.is_some_and(|m| m.is_dir()),
+        backup,
+        after: None,
+        moved_from: None,
+    })
+}
+
+fn load_entry(entry_dir: &Path) -> Result<JournalEntry, String> {
+    let json = fs::read_to_string(entry_dir.join(MANIFEST_FILE)).map_err(|e| e.to_string())?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+/// 条目ID只能包含字母、数字和连字符，防止通过ID访问日志目录之外的路径
+fn validate_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(format!("无效的操作日志ID: {}", id));
+    }
+    Ok(())
+}
+
+/// 移动文件或目录，跨文件系统时退化为复制后删除
+fn move_path(source: &Path, target: &Path) -> io::Result<()> {
+    if fs::rename(source, target).is_ok() {
+        return Ok(());
+    }
+    copy_recursive(source, target)?;
+    if fs::symlink_metadata(source)?.is_dir() {
+        fs::remove_dir_all(source)
+    } else {
+        fs::remove_file(source)
+    }
+}
+
+/// 递归复制，符号链接按链接本身复制
+fn copy_recursive(source: &Path, target: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.is_dir() {
+        fs::create_dir_all(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else if metadata.file_type().is_symlink() {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(fs::read_link(source)?, target)
+        }
+        #[cfg(not(unix))]
+        {
+            fs::copy(source, target).map(|_| ())
+        }
+    } else {
+        fs::copy(source, target).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::WorkspaceRoot;
+    use crate::workspace::{resolve, test_root, WorkspaceAccess, MODE_READ_ONLY, MODE_READ_WRITE};
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        root: WorkspaceRoot,
+        files: PathBuf,
+        journal: FsJournal,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            fs::create_dir_all(dir.path().join("files")).unwrap();
+            let files = dir.path().join("files").canonicalize().unwrap();
+            let root = test_root(&files, MODE_READ_WRITE);
+            let journal = FsJournal::new(dir.path().join("journal"));
+            Self { _dir: dir, root, files, journal }
+        }
+
+        fn undo(&self, id: &str) -> Result<JournalEntry, String> {
+            self.journal.undo(id, |snapshot| {
+                resolve(&self.root, &snapshot.display_path, WorkspaceAccess::Write).map(|r| r.path)
+            })
+        }
+
+        fn write(&self, relative: &str, content: &str) -> String {
+            let path = self.files.join(relative);
+            let entry = self.journal.record("root", "write", &[(relative, &path)]).unwrap();
+            fs::write(&path, content).unwrap();
+            self.journal.complete(&entry.id);
+            entry.id
+        }
+    }
+
+    #[test]
+    fn undo_restores_previous_content() {
+        let fixture = Fixture::new();
+        fs::write(fixture.files.join("a.txt"), "v1").unwrap();
+        let id = fixture.write("a.txt", "v2");
+
+        fixture.undo(&id).unwrap();
+        assert_eq!(fs::read_to_string(fixture.files.join("a.txt")).unwrap(), "v1");
+        assert!(fixture.undo(&id).unwrap_err().contains("已经撤销"));
+
+        // 新建文件的撤销会删除该文件
+        let id = fixture.write("new.txt", "new");
+        fixture.undo(&id).unwrap();
+        assert!(!fixture.files.join("new.txt").exists());
+    }
+
+    #[test]
+    fn undo_is_refused_after_later_edits() {
+        let fixture = Fixture::new();
+        fs::write(fixture.files.join("a.txt"), "v1").unwrap();
+        let first = fixture.write("a.txt", "v2");
+        let second = fixture.write("a.txt", "v3");
+
+        // 撤销较早的操作会覆盖之后的修改，必须拒绝
+        assert!(fixture.undo(&first).unwrap_err().contains("已被修改"));
+        assert_eq!(fs::read_to_string(fixture.files.join("a.txt")).unwrap(), "v3");
+
+        // 按顺序撤销则可以
+        fixture.undo(&second).unwrap();
+        fixture.undo(&first).unwrap();
+        assert_eq!(fs::read_to_string(fixture.files.join("a.txt")).unwrap(), "v1");
+
+        // 未调用complete的条目没有操作后的状态，不能撤销
+        let entry = fixture.journal.record("root", "write", &[("a.txt", &fixture.files.join("a.txt"))]).unwrap();
+        assert!(fixture.undo(&entry.id).unwrap_err().contains("缺少"));
+    }
+
+    #[test]
+    fn undo_move_and_delete() {
+        let fixture = Fixture::new();
+        fs::create_dir_all(fixture.files.join("dir/sub")).unwrap();
+        fs::write(fixture.files.join("dir/sub/a.txt"), "a").unwrap();
+
+        let (source, target) = (fixture.files.join("dir"), fixture.files.join("moved"));
+        let entry = fixture.journal.record_move("root", ("dir", &source), ("moved", &target)).unwrap();
+        fs::rename(&source, &target).unwrap();
+        fixture.journal.complete(&entry.id);
+        // 移动不复制源目录，条目目录中只有清单
+        let entry_dir = fixture._dir.path().join("journal").join(&entry.id);
+        assert_eq!(fs::read_dir(&entry_dir).unwrap().count(), 1);
+
+        // 移动后的修改随目录一起移回
+        fs::write(target.join("sub/b.txt"), "b").unwrap();
+        fixture.undo(&entry.id).unwrap();
+        assert_eq!(fs::read_to_string(fixture.files.join("dir/sub/a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(fixture.files.join("dir/sub/b.txt")).unwrap(), "b");
+        assert!(!target.exists());
+
+        let entry = fixture.journal.record_delete("root", "delete_directory", "dir", &source).unwrap();
+        assert!(!source.exists());
+        // 删除后在原位置新建了同名目录，撤销不能覆盖它
+        fs::create_dir(&source).unwrap();
+        assert!(fixture.undo(&entry.id).unwrap_err().contains("已被修改"));
+        fs::remove_dir(&source).unwrap();
+        fixture.undo(&entry.id).unwrap();
+        assert_eq!(fs::read_to_string(fixture.files.join("dir/sub/a.txt")).unwrap(), "a");
+    }
+
+    #[test]
+    fn undo_re_resolves_through_the_workspace() {
+        let mut fixture = Fixture::new();
+        let id = fixture.write("a.txt", "v1");
+
+        fixture.root.mode = MODE_READ_ONLY.to_string();
+        assert!(fixture.undo(&id).unwrap_err().contains("只读"));
+
+        fixture.root.mode = MODE_READ_WRITE.to_string();
+        fixture.root.policy = Some(r#"{"deny":["*.txt"]}"#.to_string());
+        assert!(fixture.undo(&id).is_err());
+        assert!(fixture.files.join("a.txt").exists());
+    }
+
+    #[test]
+    fn retention_prunes_only_when_over_the_limit() {
+        let fixture = Fixture::new();
+        fixture.journal.purge(Some(RetentionPolicy { max_age_days: 30, max_entries: 2 })).unwrap();
+
+        let first = fixture.write("a.txt", "v1");
+        fixture.write("a.txt", "v2");
+        assert!(fixture.journal.get(&first).is_ok());
+
+        let third = fixture.write("a.txt", "v3");
+        let ids: Vec<String> = fixture.journal.list(None, 10).unwrap().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids[0], third);
+        assert!(!ids.contains(&first));
+    }
+}
@@ -0,0 +1,271 @@
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Component, Path};
+
+use crate::fs_edit::content_hash;
+use crate::workspace::ResolvedPath;
+
+/// 目录树选项
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TreeOptions {
+    pub max_depth: usize,
+    pub max_entries: usize,
+    pub respect_gitignore: bool,
+    pub ignore: Vec<String>, // 额外忽略的gitignore风格模式
+    pub include_sizes: bool,
+    pub include_hashes: bool,
+    pub format: String, // "json" or "text"
+}
+
+impl Default for TreeOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_entries: 500,
+            respect_gitignore: true,
+            ignore: vec!["node_modules".to_string()],
+            include_sizes: false,
+            include_hashes: false,
+            format: "json".to_string(),
+        }
+    }
+}
+
+/// 目录树节点
+#[derive(Debug, Serialize)]
+pub struct TreeNode {
+    pub name: String,
+    pub item_type: String, // "file" or "directory"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeNode>,
+    // 目录超出最大深度，内容未展开
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub collapsed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TreeResult {
+    pub root: TreeNode,
+    pub total_entries: usize,
+    pub truncated: bool, // 达到条目数量上限
+}
+
+/// 递归列出工作区目录
+///
+/// 遵守 .gitignore、额外的忽略模式和工作区策略，条目按目录优先、名称排序。
+pub fn build_tree(resolved: &ResolvedPath, options: &TreeOptions) -> Result<TreeResult, String> {
+    if !resolved.path.is_dir() {
+        return Err("指定路径不是目录".to_string());
+    }
+
+    let mut overrides = OverrideBuilder::new(&resolved.path);
+    for pattern in &options.ignore {
+        overrides.add(&format!("!{}", pattern)).map_err(|e| format!("无效的忽略模式 {}: {}", pattern, e))?;
+    }
+    let overrides = overrides.build().map_err(|e| format!("无效的忽略模式: {}", e))?;
+
+    let root = resolved.root.clone();
+    let policy = resolved.policy.clone();
+    let walker = WalkBuilder::new(&resolved.path)
+        // 隐藏文件由工作区策略决定
+        .hidden(false)
+        .git_ignore(options.respect_gitignore)
+        .git_global(options.respect_gitignore)
+        .git_exclude(options.respect_gitignore)
+        .ignore(options.respect_gitignore)
+        .require_git(false)
+        .overrides(overrides)
+        .max_depth(Some(options.max_depth.max(1)))
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(move |entry| {
+            let relative = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            policy.check_path(relative, is_dir).is_ok()
+        })
+        .build();
+
+    let mut result = TreeResult {
+        root: new_node(&resolved.path, true),
+        total_entries: 0,
+        truncated: false,
+    };
+
+    for entry in walker.flatten() {
+        if entry.depth() == 0 {
+            continue;
+        }
+        if result.total_entries >= options.max_entries {
+            result.truncated = true;
+            break;
+        }
+        result.total_entries += 1;
+
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        let mut node = new_node(entry.path(), is_dir);
+        if is_dir {
+            node.collapsed = entry.depth() >= options.max_depth.max(1) && has_children(entry.path());
+        } else {
+            let size = entry.metadata().map(|m| m.len()).ok();
+            if options.include_sizes {
+                node.size = size;
+            }
+            if options.include_hashes {
+                // 超过策略大小限制的文件不计算哈希
+                let relative = entry.path().strip_prefix(&resolved.root).unwrap_or(entry.path());
+                if size.is_some_and(|size| resolved.policy.check_size(relative, size).is_ok()) {
+                    node.hash = fs::read(entry.path()).ok().map(|bytes| content_hash(&bytes));
+                }
+            }
+        }
+
+        let relative = entry.path().strip_prefix(&resolved.path).unwrap_or(entry.path());
+        insert(&mut result.root, relative, node);
+    }
+
+    sort(&mut result.root);
+    Ok(result)
+}
+
+/// 渲染为紧凑的缩进文本，便于直接作为模型上下文
+pub fn render_text(result: &TreeResult) -> String {
+    let mut text = String::new();
+    render_node(&result.root, 0, &mut text);
+    if result.truncated {
+        let _ = writeln!(text, "… 已达到条目上限，仅显示 {} 项", result.total_entries);
+    }
+    text
+}
+
+fn render_node(node: &TreeNode, depth: usize, text: &mut String) {
+    let _ = write!(text, "{}{}", "  ".repeat(depth), node.name);
+    if node.item_type == "directory" {
+        text.push('/');
+    }
+    if node.collapsed {
+        text.push_str(" …");
+    }
+    if let Some(size) = node.size {
+        let _ = write!(text, " ({})", format_size(size));
+    }
+    if let Some(hash) = &node.hash {
+        let _ = write!(text, " sha256:{}", hash);
+    }
+    text.push('\n');
+    for child in &node.children {
+        render_node(child, depth + 1, text);
+    }
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn new_node(path: &Path, is_dir: bool) -> TreeNode {
+    TreeNode {
+        name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| ".".to_string()),
+        item_type: if is_dir { "directory".to_string() } else { "file".to_string() },
+        size: None,
+        hash: None,
+        children: Vec::new(),
+        collapsed: false,
+    }
+}
+
+fn has_children(path: &Path) -> bool {
+    fs::read_dir(path).map(|mut entries| entries.next().is_some()).unwrap_or(false)
+}
+
+/// 按相对路径把节点挂到树上，遍历顺序保证父目录先于子项出现
+fn insert(root: &mut TreeNode, relative: &Path, node: TreeNode) {
+    let names: Vec<String> = relative.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    let Some((_, parents)) = names.split_last() else { return };
+
+    let mut current = root;
+    for name in parents {
+        let index = match current.children.iter().rposition(|child| &child.name == name) {
+            Some(index) => index,
+            None => return,
+        };
+        current = &mut current.children[index];
+    }
+    current.children.push(node);
+}
+
+fn sort(node: &mut TreeNode) {
+    node.children.sort_by(|a, b| {
+        (a.item_type != "directory").cmp(&(b.item_type != "directory")).then_with(|| a.name.cmp(&b.name))
+    });
+    node.children.iter_mut().for_each(sort);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::{resolve, test_root, WorkspaceAccess, MODE_READ_ONLY};
+
+    fn resolve_root(dir: &Path) -> ResolvedPath {
+        resolve(&test_root(dir, MODE_READ_ONLY), "", WorkspaceAccess::Read).unwrap()
+    }
+
+    fn names(node: &TreeNode) -> Vec<&str> {
+        node.children.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn respects_gitignore_depth_and_entry_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["src/deep/nested", "target/debug", "node_modules/pkg"] {
+            fs::create_dir_all(dir.path().join(path)).unwrap();
+        }
+        fs::write(dir.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(dir.path().join("README.md"), "readme").unwrap();
+        fs::write(dir.path().join("app.log"), "log").unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.path().join("src/deep/nested/x.rs"), "").unwrap();
+        let resolved = resolve_root(dir.path());
+
+        let options = TreeOptions { max_depth: 2, include_sizes: true, ..TreeOptions::default() };
+        let result = build_tree(&resolved, &options).unwrap();
+        // .gitignore本身是隐藏文件，被默认策略过滤；目录排在文件前面
+        assert_eq!(names(&result.root), vec!["src", "README.md"]);
+        let src = &result.root.children[0];
+        assert_eq!(names(src), vec!["deep", "main.rs"]);
+        assert!(src.children[0].collapsed);
+        assert_eq!(src.children[1].size, Some(12));
+        assert!(!result.truncated);
+
+        let options = TreeOptions { respect_gitignore: false, ignore: Vec::new(), ..TreeOptions::default() };
+        let result = build_tree(&resolved, &options).unwrap();
+        assert_eq!(names(&result.root), vec!["node_modules", "src", "target", "README.md", "app.log"]);
+
+        let options = TreeOptions { max_entries: 3, ..TreeOptions::default() };
+        let result = build_tree(&resolved, &options).unwrap();
+        assert!(result.truncated);
+        assert_eq!(result.total_entries, 3);
+        assert!(render_text(&result).contains("已达到条目上限，仅显示 3 项"));
+    }
+}
@@ -0,0 +1,258 @@
+use notify_debouncer_full::notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
+use notify_debouncer_full::notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::file_policy::PolicyEngine;
+use crate::workspace::ResolvedPath;
+
+/// 默认去抖时间
+pub const DEFAULT_DEBOUNCE_MS: u64 = 500;
+
+/// 单个文件变化，路径相对于工作区根目录
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FsChange {
+    pub kind: String, // "created", "modified", "removed", "renamed"
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>, // 仅 renamed
+}
+
+/// 一批去抖后的变化，作为 `fs-change` 事件的负载
+#[derive(Debug, Clone, Serialize)]
+pub struct FsWatchEvent {
+    pub watch_id: String,
+    pub root_id: String,
+    pub changes: Vec<FsChange>,
+}
+
+/// 监听信息，用于前端展示
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchInfo {
+    pub watch_id: String,
+    pub root_id: String,
+    pub path: String,
+}
+
+struct Watch {
+    info: WatchInfo,
+    // 丢弃时停止监听
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+}
+
+type ChangeCallback = Arc<dyn Fn(FsWatchEvent) + Send + Sync>;
+
+/// 工作区目录监听管理
+pub struct WatchManager {
+    watches: Mutex<HashMap<String, Watch>>,
+    on_change: ChangeCallback,
+}
+
+impl WatchManager {
+    pub fn new(on_change: impl Fn(FsWatchEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            watches: Mutex::new(HashMap::new()),
+            on_change: Arc::new(on_change),
+        }
+    }
+
+    /// 递归监听工作区内的路径，返回监听ID
+    ///
+    /// 被工作区策略阻止的路径（隐藏文件、拒绝规则等）产生的变化不会上报。
+    pub fn watch(&self, root_id: &str, resolved: &ResolvedPath, debounce_ms: Option<u64>) -> Result<String, String> {
+        let watch_id = Uuid::new_v4().to_string();
+        let debounce = Duration::from_millis(debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS).max(50));
+
+        let handler = {
+            let watch_id = watch_id.clone();
+            let root_id = root_id.to_string();
+            let root = resolved.root.clone();
+            let policy = resolved.policy.clone();
+            let on_change = self.on_change.clone();
+            move |result: DebounceEventResult| match result {
+                Ok(events) => {
+                    let changes = collect_changes(&events, &root, &policy);
+                    if !changes.is_empty() {
+                        on_change(FsWatchEvent {
+                            watch_id: watch_id.clone(),
+                            root_id: root_id.clone(),
+                            changes,
+                        });
+                    }
+                }
+                Err(errors) => {
+                    for e in errors {
+                        warn!("目录监听出错: {} - {}", watch_id, e);
+                    }
+                }
+            }
+        };
+
+        let mut debouncer = new_debouncer(debounce, None, handler)
+            .map_err(|e| format!("创建目录监听失败: {}", e))?;
+        debouncer.watch(&resolved.path, RecursiveMode::Recursive)
+            .map_err(|e| format!("监听路径失败: {}", e))?;
+
+        let info = WatchInfo {
+            watch_id: watch_id.clone(),
+            root_id: root_id.to_string(),
+            path: resolved.relative().to_string_lossy().to_string(),
+        };
+        info!("开始监听: {} ({})", resolved.path.display(), watch_id);
+        self.watches.lock().unwrap().insert(watch_id.clone(), Watch { info, _debouncer: debouncer });
+        Ok(watch_id)
+    }
+
+    /// 停止监听，监听不存在时返回 false
+    pub fn unwatch(&self, watch_id: &str) -> bool {
+        let removed = self.watches.lock().unwrap().remove(watch_id);
+        if removed.is_some() {
+            info!("停止监听: {}", watch_id);
+        }
+        removed.is_some()
+    }
+
+    pub fn list(&self) -> Vec<WatchInfo> {
+        self.watches.lock().unwrap().values().map(|w| w.info.clone()).collect()
+    }
+}
+
+/// 把底层事件转换为相对路径的变化列表，过滤策略阻止的路径并合并重复项
+fn collect_changes(events: &[DebouncedEvent], root: &Path, policy: &PolicyEngine) -> Vec<FsChange> {
+    // `is_dir` 为事件标明的类型；已删除或移走的路径无法读取类型，
+    // 此时同时按文件和目录检查，只有两者都允许的路径才上报
+    let relative = |path: &PathBuf, is_dir: Option<bool>| -> Option<String> {
+        let relative = path.strip_prefix(root).ok()?;
+        if relative.as_os_str().is_empty() {
+            return None;
+        }
+        match is_dir.or_else(|| std::fs::symlink_metadata(path).ok().map(|m| m.is_dir())) {
+            Some(is_dir) => policy.check_path(relative, is_dir).ok()?,
+            None => {
+                policy.check_path(relative, false).ok()?;
+                policy.check_path(relative, true).ok()?;
+            }
+        }
+        Some(relative.to_string_lossy().to_string())
+    };
+
+    let mut changes: Vec<FsChange> = Vec::new();
+    for event in events {
+        let change = match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                match (relative(&event.paths[0], None), relative(&event.paths[1], None)) {
+                    (Some(from), Some(to)) => Some(change("renamed", to, Some(from))),
+                    // 移入或移出可见范围时按新建或删除处理
+                    (None, Some(to)) => Some(change("created", to, None)),
+                    (Some(from), None) => Some(change("removed", from, None)),
+                    (None, None) => None,
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                event.paths.first().and_then(|p| relative(p, None)).map(|p| change("removed", p, None))
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                event.paths.first().and_then(|p| relative(p, None)).map(|p| change("created", p, None))
+            }
+            EventKind::Create(kind) => {
+                let is_dir = match kind {
+                    CreateKind::Folder => Some(true),
+                    CreateKind::File => Some(false),
+                    _ => None,
+                };
+                event.paths.first().and_then(|p| relative(p, is_dir)).map(|p| change("created", p, None))
+            }
+            EventKind::Modify(_) => event.paths.first().and_then(|p| relative(p, None)).map(|p| change("modified", p, None)),
+            EventKind::Remove(kind) => {
+                let is_dir = match kind {
+                    RemoveKind::Folder => Some(true),
+                    RemoveKind::File => Some(false),
+                    _ => None,
+                };
+                event.paths.first().and_then(|p| relative(p, is_dir)).map(|p| change("removed", p, None))
+            }
+            _ => None,
+        };
+        if let Some(change) = change {
+            if !changes.contains(&change) {
+                changes.push(change);
+            }
+        }
+    }
+    changes
+}
+
+fn change(kind: &str, path: String, old_path: Option<String>) -> FsChange {
+    FsChange { kind: kind.to_string(), path, old_path }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::{resolve, test_root, WorkspaceAccess, MODE_READ_ONLY};
+    use notify_debouncer_full::notify::Event;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    #[test]
+    fn reports_filtered_changes_until_unwatched() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = test_root(dir.path(), MODE_READ_ONLY);
+        let resolved = resolve(&root, "", WorkspaceAccess::Read).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let manager = WatchManager::new(move |event| {
+            let _ = tx.lock().unwrap().send(event);
+        });
+        let watch_id = manager.watch("root", &resolved, Some(50)).unwrap();
+        assert_eq!(manager.list().len(), 1);
+
+        // 默认策略阻止隐藏文件，其变化不应上报
+        std::fs::write(dir.path().join(".secret"), "x").unwrap();
+        std::fs::write(dir.path().join("visible.txt"), "x").unwrap();
+
+        let mut paths = Vec::new();
+        while !paths.iter().any(|p| p == "visible.txt") {
+            let event = rx.recv_timeout(Duration::from_secs(5)).expect("没有收到文件变化事件");
+            assert_eq!((event.watch_id.as_str(), event.root_id.as_str()), (watch_id.as_str(), "root"));
+            paths.extend(event.changes.into_iter().map(|c| c.path));
+        }
+        assert!(!paths.iter().any(|p| p == ".secret"));
+
+        assert!(manager.unwatch(&watch_id));
+        assert!(!manager.unwatch(&watch_id));
+        assert!(manager.list().is_empty());
+        while rx.try_recv().is_ok() {}
+        std::fs::write(dir.path().join("after.txt"), "x").unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn removed_paths_are_filtered_by_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = PolicyEngine::from_json(Some(r#"{"allow": ["*.rs"], "deny": ["secret"]}"#)).unwrap();
+        let event = |kind: EventKind, path: &str| {
+            DebouncedEvent::new(Event::new(kind).add_path(dir.path().join(path)), Instant::now())
+        };
+        let events = vec![
+            event(EventKind::Remove(RemoveKind::Folder), "src"),
+            event(EventKind::Remove(RemoveKind::File), "notes.txt"),
+            event(EventKind::Remove(RemoveKind::Any), "lib.rs"),
+            event(EventKind::Remove(RemoveKind::Any), "docs"),
+            event(EventKind::Remove(RemoveKind::Any), ".git"),
+            event(EventKind::Modify(ModifyKind::Name(RenameMode::From)), "secret"),
+            event(EventKind::Modify(ModifyKind::Name(RenameMode::From)), "secret/key.rs"),
+        ];
+
+        let paths: Vec<String> = collect_changes(&events, dir.path(), &policy).into_iter().map(|c| c.path).collect();
+        // 类型未知的 docs 可能是不在允许列表中的文件，不能上报
+        assert_eq!(paths, vec!["src", "lib.rs"]);
+    }
+}
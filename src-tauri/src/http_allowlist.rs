@@ -0,0 +1,353 @@
+use reqwest::Url;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_json::Value;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+use crate::database::AIProvider;
+use crate::proxy_router::HostPattern;
+
+/// 用户添加的允许域名，JSON字符串数组，支持 `example.com`、`*.example.com` 和单个IP地址
+pub const SETTING_ALLOWED_DOMAINS: &str = "http_allowed_domains";
+/// 是否允许访问本机和局域网地址，值为 "true" 时开启
+pub const SETTING_ALLOW_PRIVATE_NETWORK: &str = "http_allow_private_network";
+
+/// HTTP桥接命令的错误，序列化为 `{ kind, message }` 供前端区分处理
+#[derive(Debug, Clone, PartialEq)]
+pub enum HttpBridgeError {
+    InvalidUrl(String),
+    DomainNotAllowed(String),
+    PrivateNetwork(String),
+    Request(String),
+}
+
+impl fmt::Display for HttpBridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUrl(message) | Self::Request(message) => write!(f, "{}", message),
+            Self::DomainNotAllowed(host) => write!(f, "域名 {} 不在允许列表中", host),
+            Self::PrivateNetwork(host) => write!(f, "未开启局域网访问，禁止请求本机或内网地址: {}", host),
+        }
+    }
+}
+
+impl HttpBridgeError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InvalidUrl(_) => "invalid_url",
+            Self::DomainNotAllowed(_) => "domain_not_allowed",
+            Self::PrivateNetwork(_) => "private_network",
+            Self::Request(_) => "request",
+        }
+    }
+}
+
+impl Serialize for HttpBridgeError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HttpBridgeError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<String> for HttpBridgeError {
+    fn from(message: String) -> Self {
+        Self::Request(message)
+    }
+}
+
+/// 生效的允许列表，用于前端展示
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct AllowlistInfo {
+    pub provider_hosts: Vec<String>, // 来自提供商地址和自动获取配置中的地址
+    pub user_domains: Vec<String>,
+    pub allow_private_network: bool,
+}
+
+/// HTTP桥接的允许列表
+///
+/// 只允许请求已配置的提供商地址、自动获取配置中的地址和用户添加的域名；
+/// 本机和局域网地址还需要用户显式开启局域网访问。
+pub struct Allowlist {
+    info: AllowlistInfo,
+    patterns: Vec<HostPattern>,
+}
+
+impl Allowlist {
+    pub fn new(providers: &[AIProvider], user_domains: Vec<String>, allow_private_network: bool) -> Self {
+        let provider_hosts = provider_hosts(providers);
+
+        let mut patterns = Vec::new();
+        for host in &provider_hosts {
+            match HostPattern::parse(host) {
+                Ok(pattern) => patterns.push(pattern),
+                Err(e) => warn!("忽略无效的提供商主机 {}: {}", host, e),
+            }
+        }
+        // 旧版本可能保存过 `*` 或网段，加载时同样按用户域名的规则过滤
+        for entry in &user_domains {
+            match parse_user_domain(entry) {
+                Ok(pattern) => patterns.push(pattern),
+                Err(e) => warn!("忽略无效的允许域名 {}: {}", entry, e),
+            }
+        }
+
+        Self {
+            info: AllowlistInfo { provider_hosts, user_domains, allow_private_network },
+            patterns,
+        }
+    }
+
+    pub fn info(&self) -> &AllowlistInfo {
+        &self.info
+    }
+
+    /// 检查URL的协议和主机，不解析域名
+    pub fn check(&self, url: &Url) -> Result<(), HttpBridgeError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(HttpBridgeError::InvalidUrl(format!("不支持的协议: {}", url.scheme())));
+        }
+        let host = normalized_host(url).ok_or_else(|| HttpBridgeError::InvalidUrl(format!("URL缺少主机: {}", url)))?;
+        let ip = host.parse::<IpAddr>().ok();
+
+        if !self.info.allow_private_network && is_private_host(&host, ip) {
+            return Err(HttpBridgeError::PrivateNetwork(host));
+        }
+        if !self.patterns.iter().any(|pattern| pattern.matches(&host, ip)) {
+            return Err(HttpBridgeError::DomainNotAllowed(host));
+        }
+        Ok(())
+    }
+}
+
+/// 缓存的允许列表，提供商或相关设置变更后失效并在下次请求时重新加载
+#[derive(Default)]
+pub struct HttpAllowlist {
+    current: RwLock<Option<Arc<Allowlist>>>,
+    stale: AtomicBool,
+}
+
+impl HttpAllowlist {
+    /// 当前有效的允许列表，未加载或已失效时返回 None
+    pub fn get(&self) -> Option<Arc<Allowlist>> {
+        if self.stale.load(Ordering::Acquire) {
+            return None;
+        }
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn set(&self, allowlist: Allowlist) -> Arc<Allowlist> {
+        let allowlist = Arc::new(allowlist);
+        info!(
+            "HTTP允许列表已加载: {} 个提供商主机, {} 个用户域名, 局域网访问 {}",
+            allowlist.info.provider_hosts.len(),
+            allowlist.info.user_domains.len(),
+            if allowlist.info.allow_private_network { "已开启" } else { "未开启" }
+        );
+        *self.current.write().unwrap() = Some(allowlist.clone());
+        self.stale.store(false, Ordering::Release);
+        allowlist
+    }
+
+    pub fn invalidate(&self) {
+        self.stale.store(true, Ordering::Release);
+    }
+
+    /// 域名解析出的地址能否连接，未开启局域网访问时排除本机和内网地址
+    ///
+    /// 在HTTP客户端的DNS解析器中调用，检查的地址就是实际连接的地址，避免DNS重绑定。
+    pub fn allows_address(&self, ip: IpAddr) -> bool {
+        let allow_private_network = self.current.read().unwrap().as_ref().is_some_and(|a| a.info.allow_private_network);
+        allow_private_network || !is_private_ip(ip)
+    }
+
+    /// 检查重定向目标，使用最近一次加载的列表（失效后也沿用，直到重新加载）
+    pub fn check_redirect(&self, url: &Url) -> Result<(), HttpBridgeError> {
+        let current = self.current.read().unwrap().clone();
+        let result = match current {
+            Some(allowlist) => allowlist.check(url),
+            None => Err(HttpBridgeError::DomainNotAllowed(normalized_host(url).unwrap_or_default())),
+        };
+        if let Err(e) = &result {
+            warn!("拦截HTTP重定向: {} ({})", url, e);
+        }
+        result
+    }
+}
+
+/// 提供商地址和自动获取配置中的地址对应的主机，去重并保持顺序
+pub fn provider_hosts(providers: &[AIProvider]) -> Vec<String> {
+    let mut hosts = Vec::new();
+    for provider in providers {
+        let mut urls = vec![provider.api_endpoint.clone()];
+        if let Some(config) = provider.auto_fetch_config.as_deref().and_then(|c| serde_json::from_str(c).ok()) {
+            collect_urls(&config, &mut urls);
+        }
+        for host in urls.iter().filter_map(|url| Url::parse(url.trim()).ok()).filter_map(|url| normalized_host(&url)) {
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+    }
+    hosts
+}
+
+/// 检查用户添加的允许域名
+pub fn validate_user_domains(domains: &[String]) -> Result<(), String> {
+    domains.iter().try_for_each(|entry| parse_user_domain(entry).map(|_| ()))
+}
+
+/// 解析用户添加的允许域名，不接受 `*`、顶级域名通配和包含多个地址的网段
+fn parse_user_domain(entry: &str) -> Result<HostPattern, String> {
+    match HostPattern::parse(entry)? {
+        HostPattern::Any => Err("不能使用 * 放行所有地址".to_string()),
+        HostPattern::Network(network) if network.prefix_len() < network.max_prefix_len() => {
+            Err(format!("不能添加网段 {}，请填写单个IP地址", entry.trim()))
+        }
+        HostPattern::Domain(domain) if !domain.contains('.') => Err(format!("域名范围过大: {}", entry.trim())),
+        pattern => Ok(pattern),
+    }
+}
+
+pub fn normalized_host(url: &Url) -> Option<String> {
+    url.host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']').trim_end_matches('.').to_ascii_lowercase())
+        .filter(|host| !host.is_empty())
+}
+
+fn is_private_host(host: &str, ip: Option<IpAddr>) -> bool {
+    match ip {
+        Some(ip) => is_private_ip(ip),
+        None => host == "localhost" || host.ends_with(".localhost") || host.ends_with(".local"),
+    }
+}
+
+/// 本机、私有网段、链路本地（含云服务器元数据地址）和运营商级NAT地址
+fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || (a == 100 && (b & 0xc0) == 64)
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || v6.to_ipv4_mapped().is_some_and(|v4| is_private_ip(IpAddr::V4(v4)))
+        }
+    }
+}
+
+/// 递归收集自动获取配置中的网络地址
+fn collect_urls(value: &Value, urls: &mut Vec<String>) {
+    match value {
+        Value::String(text) if text.starts_with("http://") || text.starts_with("https://") => urls.push(text.clone()),
+        Value::Array(items) => items.iter().for_each(|item| collect_urls(item, urls)),
+        Value::Object(map) => map.values().for_each(|item| collect_urls(item, urls)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn provider(endpoint: &str, auto_fetch: Option<&str>) -> AIProvider {
+        AIProvider {
+            id: "p".to_string(),
+            name: "p".to_string(),
+            api_endpoint: endpoint.to_string(),
+            api_key: String::new(),
+            models: "[]".to_string(),
+            default_model_id: None,
+            custom_config: None,
+            use_custom_config: None,
+            auto_fetch_config: auto_fetch.map(str::to_string),
+            preset_type: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn check(allowlist: &Allowlist, url: &str) -> Result<(), HttpBridgeError> {
+        allowlist.check(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn allows_configured_hosts_only() {
+        let providers = vec![
+            provider("https://api.openai.com/v1", None),
+            provider(
+                "https://api.deepseek.com",
+                Some(r#"{"modelsApi": {"endpoint": "https://models.deepseek.com/v1/models"}, "balanceApi": {"enabled": false}}"#),
+            ),
+        ];
+        let allowlist = Allowlist::new(&providers, vec!["*.example.org".to_string()], false);
+        assert_eq!(allowlist.info().provider_hosts, ["api.openai.com", "api.deepseek.com", "models.deepseek.com"]);
+
+        assert!(check(&allowlist, "https://api.openai.com/v1/chat/completions").is_ok());
+        assert!(check(&allowlist, "https://models.deepseek.com/v1/models").is_ok());
+        assert!(check(&allowlist, "https://cdn.example.org/a.png").is_ok());
+        assert_eq!(check(&allowlist, "https://evil.test/"), Err(HttpBridgeError::DomainNotAllowed("evil.test".to_string())));
+        assert!(matches!(check(&allowlist, "file:///etc/passwd"), Err(HttpBridgeError::InvalidUrl(_))));
+    }
+
+    #[test]
+    fn private_network_needs_opt_in() {
+        let providers = vec![provider("http://localhost:11434", None), provider("http://192.168.1.20:8000/v1", None)];
+        let blocked = Allowlist::new(&providers, vec!["169.254.169.254".to_string()], false);
+        for url in ["http://localhost:11434/api/tags", "http://192.168.1.20:8000/v1", "http://169.254.169.254/latest", "http://[::ffff:127.0.0.1]/"] {
+            assert!(matches!(check(&blocked, url), Err(HttpBridgeError::PrivateNetwork(_))), "{}", url);
+        }
+
+        let allowed = Allowlist::new(&providers, Vec::new(), true);
+        assert!(check(&allowed, "http://localhost:11434/api/tags").is_ok());
+        assert!(check(&allowed, "http://192.168.1.20:8000/v1").is_ok());
+        // 开启局域网访问后仍然只允许列表中的地址
+        assert!(matches!(check(&allowed, "http://10.0.0.1/"), Err(HttpBridgeError::DomainNotAllowed(_))));
+    }
+
+    #[test]
+    fn rejects_wide_user_domains() {
+        for entry in ["*", "10.0.0.0/8", "0.0.0.0/0", "::/0", "*.com", "com"] {
+            assert!(validate_user_domains(&[entry.to_string()]).is_err(), "{}", entry);
+        }
+        let valid = ["example.com", "*.example.org", "10.0.0.5", "10.0.0.5/32", "fd00::1"].map(str::to_string);
+        assert!(validate_user_domains(&valid).is_ok());
+
+        // 已保存的无效条目在加载时被忽略
+        let allowlist = Allowlist::new(&[], vec!["*".to_string(), "0.0.0.0/0".to_string()], true);
+        assert!(matches!(check(&allowlist, "https://evil.test/"), Err(HttpBridgeError::DomainNotAllowed(_))));
+        assert!(matches!(check(&allowlist, "http://10.0.0.1/"), Err(HttpBridgeError::DomainNotAllowed(_))));
+    }
+
+    #[test]
+    fn filters_private_addresses_until_opt_in() {
+        let http_allowlist = HttpAllowlist::default();
+        let public: IpAddr = "93.184.216.34".parse().unwrap();
+        let metadata: IpAddr = "169.254.169.254".parse().unwrap();
+        assert!(http_allowlist.allows_address(public));
+        assert!(!http_allowlist.allows_address(metadata));
+
+        http_allowlist.set(Allowlist::new(&[], Vec::new(), true));
+        assert!(http_allowlist.allows_address(metadata));
+    }
+
+    #[test]
+    fn errors_serialize_with_kind() {
+        let error = serde_json::to_value(HttpBridgeError::DomainNotAllowed("evil.test".to_string())).unwrap();
+        assert_eq!(error, serde_json::json!({"kind": "domain_not_allowed", "message": "域名 evil.test 不在允许列表中"}));
+    }
+}
@@ -0,0 +1,185 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use encoding_rs::{Encoding, UTF_8};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, Method, RequestBuilder};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::file_policy::is_binary;
+use crate::fs_binary::{decode_base64, sniff_mime};
+
+/// multipart/form-data 的一个字段，`value` 和 `data` 二选一
+#[derive(Debug, Deserialize)]
+pub struct MultipartField {
+    pub name: String,
+    pub value: Option<String>, // 文本字段
+    pub data: Option<String>, // 文件内容，base64编码或data URL
+    pub file_name: Option<String>,
+    pub content_type: Option<String>, // 未设置时按文件名和内容识别
+}
+
+/// 按方法名创建请求，支持 GET、POST、PUT、DELETE、PATCH、HEAD 和 OPTIONS
+pub fn build_request(client: &Client, method: &str, url: &str) -> Result<RequestBuilder, String> {
+    let method = match method.to_uppercase().as_str() {
+        "GET" => Method::GET,
+        "POST" => Method::POST,
+        "PUT" => Method::PUT,
+        "DELETE" => Method::DELETE,
+        "PATCH" => Method::PATCH,
+        "HEAD" => Method::HEAD,
+        "OPTIONS" => Method::OPTIONS,
+        other => return Err(format!("不支持的HTTP方法: {}", other)),
+    };
+    Ok(client.request(method, url))
+}
+
+/// 解码请求体，`encoding` 为 "base64" 时按base64解码，否则按文本发送
+pub fn decode_body(body: String, encoding: Option<&str>) -> Result<Vec<u8>, String> {
+    match encoding.unwrap_or("text") {
+        "text" => Ok(body.into_bytes()),
+        "base64" => decode_base64(&body).map_err(|e| format!("请求体{}", e)),
+        other => Err(format!("不支持的请求体编码: {}", other)),
+    }
+}
+
+/// 构建multipart表单，文件字段的内容类型未指定时自动识别
+pub fn build_form(fields: Vec<MultipartField>) -> Result<Form, String> {
+    let mut form = Form::new();
+    for field in fields {
+        let invalid_type = |e: reqwest::Error| format!("字段 {} 的内容类型无效: {}", field.name, e);
+        let part = match (field.value, field.data) {
+            // 文本字段也允许指定内容类型，例如 application/json
+            (Some(value), None) => match &field.content_type {
+                Some(content_type) => Part::text(value).mime_str(content_type).map_err(invalid_type)?,
+                None => Part::text(value),
+            },
+            (None, Some(data)) => {
+                let bytes = decode_base64(&data).map_err(|e| format!("字段 {} 的{}", field.name, e))?;
+                let file_name = field.file_name.clone().unwrap_or_else(|| field.name.clone());
+                let content_type = field.content_type.clone()
+                    .unwrap_or_else(|| sniff_mime(Path::new(&file_name), &bytes));
+                Part::bytes(bytes).file_name(file_name).mime_str(&content_type).map_err(invalid_type)?
+            }
+            _ => return Err(format!("字段 {} 必须且只能设置 value 或 data 之一", field.name)),
+        };
+        form = form.part(field.name, part);
+    }
+    Ok(form)
+}
+
+/// 内容类型是否为文本（text/*、JSON、XML、JavaScript、表单等）
+pub fn is_text_content_type(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    essence.starts_with("text/")
+        || essence.ends_with("json")
+        || essence.ends_with("xml")
+        || essence.ends_with("javascript")
+        || essence == "application/x-www-form-urlencoded"
+}
+
+/// 响应体的返回形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseType {
+    Text,
+    Base64,
+    #[default]
+    Auto, // 按内容类型判断，没有内容类型时按内容识别
+}
+
+/// 按响应类型编码响应体，返回 (内容, 编码)
+pub fn encode_response(bytes: &[u8], content_type: Option<&str>, response_type: ResponseType) -> (String, &'static str) {
+    let as_text = match response_type {
+        ResponseType::Text => true,
+        ResponseType::Base64 => false,
+        ResponseType::Auto => match content_type {
+            Some(content_type) => is_text_content_type(content_type),
+            None => !is_binary(bytes),
+        },
+    };
+    if as_text {
+        (decode_text(bytes, content_type), "text")
+    } else {
+        (BASE64.encode(bytes), "base64")
+    }
+}
+
+/// 按内容类型中的charset解码文本，未声明或无法识别时按UTF-8，BOM优先
+fn decode_text(bytes: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .and_then(|content_type| {
+            content_type.split(';').skip(1).find_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                key.trim().eq_ignore_ascii_case("charset").then(|| value.trim().trim_matches('"'))
+            })
+        })
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn methods_and_bodies() {
+        let client = Client::new();
+        for method in ["get", "POST", "Put", "delete", "PATCH", "head", "OPTIONS"] {
+            let request = build_request(&client, method, "https://example.com/").unwrap().build().unwrap();
+            assert_eq!(request.method().as_str(), method.to_uppercase());
+        }
+        assert!(build_request(&client, "TRACE", "https://example.com/").is_err());
+
+        assert_eq!(decode_body("hi".to_string(), None).unwrap(), b"hi");
+        assert_eq!(decode_body("aGk=".to_string(), Some("base64")).unwrap(), b"hi");
+        assert_eq!(decode_body("data:audio/wav;base64,aGk=".to_string(), Some("base64")).unwrap(), b"hi");
+        assert!(decode_body("hi".to_string(), Some("hex")).is_err());
+    }
+
+    #[test]
+    fn response_encoding() {
+        assert!(is_text_content_type("application/json; charset=utf-8"));
+        assert!(is_text_content_type("application/problem+json"));
+        assert!(is_text_content_type("text/event-stream"));
+        assert!(!is_text_content_type("image/png"));
+
+        let png = [0x89, b'P', b'N', b'G', 0, 0, 0, 0];
+        let auto = ResponseType::default();
+        assert_eq!(encode_response(&png, Some("image/png"), auto), ("iVBORwAAAAA=".to_string(), "base64"));
+        assert_eq!(encode_response(b"{}", Some("application/json"), auto).1, "text");
+        assert_eq!(encode_response(&png, None, auto).1, "base64");
+        assert_eq!(encode_response(b"plain", None, auto).1, "text");
+        assert_eq!(encode_response(b"{}", Some("application/json"), ResponseType::Base64), ("e30=".to_string(), "base64"));
+        assert_eq!(encode_response(&png, Some("image/png"), ResponseType::Text).1, "text");
+    }
+
+    #[test]
+    fn text_uses_declared_charset() {
+        let auto = ResponseType::default();
+        // "中文" 的GBK编码
+        let gbk = [0xd6, 0xd0, 0xce, 0xc4];
+        assert_eq!(encode_response(&gbk, Some("text/plain; charset=GBK"), auto).0, "中文");
+        assert_eq!(encode_response(&gbk, Some("text/html; charset=\"gb2312\""), auto).0, "中文");
+        assert_eq!(encode_response(b"caf\xe9", Some("text/plain;charset=iso-8859-1"), auto).0, "café");
+        assert_eq!(encode_response("中文".as_bytes(), Some("application/json"), auto).0, "中文");
+        assert_eq!(encode_response("中文".as_bytes(), Some("text/plain; charset=unknown"), auto).0, "中文");
+        // BOM优先于声明的字符集
+        assert_eq!(encode_response(b"\xef\xbb\xbfok", Some("text/plain; charset=GBK"), auto).0, "ok");
+    }
+
+    #[test]
+    fn multipart_fields_are_validated() {
+        let field = |value: Option<&str>, data: Option<&str>| MultipartField {
+            name: "file".to_string(),
+            value: value.map(str::to_string),
+            data: data.map(str::to_string),
+            file_name: Some("speech.wav".to_string()),
+            content_type: None,
+        };
+        assert!(build_form(vec![field(Some("whisper-1"), None), field(None, Some("UklGRg=="))]).is_ok());
+        assert!(build_form(vec![field(Some("a"), Some("UklGRg=="))]).is_err());
+        assert!(build_form(vec![field(None, None)]).is_err());
+        assert!(build_form(vec![field(None, Some("not base64!"))]).is_err());
+    }
+}
@@ -0,0 +1,449 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use reqwest::{Certificate, Client, Identity, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::{proxy_router, ProxyConfig};
+
+/// 最多缓存的客户端数量，超出时淘汰最久未使用的客户端
+const MAX_CLIENTS: usize = 16;
+
+const USER_AGENT: &str = "AiChat/1.0";
+
+/// 普通请求默认的总超时
+const DEFAULT_TOTAL_TIMEOUT_SECS: u64 = 300;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 30;
+/// 流式响应默认的空闲超时
+const DEFAULT_STREAM_IDLE_TIMEOUT_SECS: u64 = 120;
+
+/// 提供商的连接设置，由前端随请求传入，超时单位为秒，0 表示不限制
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ConnectionSettings {
+    pub connect_timeout_secs: Option<u64>, // 默认 30 秒
+    pub read_timeout_secs: Option<u64>, // 单次读取的空闲超时，默认不限制
+    pub total_timeout_secs: Option<u64>, // 默认普通请求 300 秒，流式请求不限制
+    pub stream_idle_timeout_secs: Option<u64>, // 流式响应两个数据块之间的最长间隔，默认 120 秒
+    pub tls: TlsSettings,
+}
+
+/// TLS设置，证书和私钥可以是文件路径或PEM文本
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct TlsSettings {
+    pub ca_certs: Vec<String>, // 额外信任的根证书，用于使用私有CA的内部网关
+    pub client_cert: Option<String>, // PEM证书链，或PKCS#12文件（.p12/.pfx）
+    pub client_key: Option<String>, // PKCS#8 PEM私钥，使用PKCS#12时不需要
+    pub client_cert_password: Option<String>, // PKCS#12文件的密码
+    pub insecure: bool, // 跳过证书校验，仅用于调试
+}
+
+impl ConnectionSettings {
+    /// 转换为客户端设置，`streaming` 为 true 时默认不限制总时长
+    pub fn client_options(&self, streaming: bool) -> ClientOptions {
+        let total_default = if streaming { None } else { Some(DEFAULT_TOTAL_TIMEOUT_SECS) };
+        ClientOptions {
+            connect_timeout: seconds(self.connect_timeout_secs.or(Some(DEFAULT_CONNECT_TIMEOUT_SECS))),
+            read_timeout: seconds(self.read_timeout_secs),
+            timeout: seconds(self.total_timeout_secs.or(total_default)),
+            tls: self.tls.clone(),
+        }
+    }
+
+    /// 流式响应的空闲超时，None 表示不限制
+    pub fn stream_idle_timeout(&self) -> Option<Duration> {
+        seconds(self.stream_idle_timeout_secs.or(Some(DEFAULT_STREAM_IDLE_TIMEOUT_SECS)))
+    }
+}
+
+fn seconds(value: Option<u64>) -> Option<Duration> {
+    value.filter(|secs| *secs > 0).map(Duration::from_secs)
+}
+
+/// 影响客户端行为的设置，与代理配置一起作为缓存键
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ClientOptions {
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    pub timeout: Option<Duration>, // 总超时
+    pub tls: TlsSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ClientKey {
+    proxy: Option<String>, // 序列化后的代理配置
+    provider_id: Option<String>, // 仅在该提供商有代理覆盖时参与区分
+    options: ClientOptions,
+}
+
+impl ClientKey {
+    fn new(proxy_config: Option<&ProxyConfig>, provider_id: Option<&str>, options: &ClientOptions) -> Self {
+        let has_override = |id: &str| {
+            proxy_config
+                .and_then(|c| c.routing.as_ref())
+                .is_some_and(|r| r.provider_overrides.contains_key(id))
+        };
+        Self {
+            proxy: proxy_config.and_then(|c| serde_json::to_string(c).ok()),
+            provider_id: provider_id.filter(|id| has_override(id)).map(|id| id.to_string()),
+            options: options.clone(),
+        }
+    }
+}
+
+struct PooledEntry {
+    client: Client,
+    last_used: Instant,
+}
+
+/// 从缓存池取得的客户端，`reused` 表示客户端来自缓存
+pub struct PooledClient {
+    pub client: Client,
+    pub reused: bool,
+}
+
+/// 连接池统计，`warm` 为使用缓存客户端的请求，`cold` 为新建客户端后的首个请求
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PoolMetrics {
+    pub clients: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub invalidations: u64,
+    pub cold_requests: u64,
+    pub warm_requests: u64,
+    pub avg_cold_ms: f64, // 从发出请求到收到响应头的平均耗时
+    pub avg_warm_ms: f64,
+}
+
+#[derive(Default)]
+struct PoolState {
+    clients: HashMap<ClientKey, PooledEntry>,
+    metrics: PoolMetrics,
+    cold_total_ms: f64,
+    warm_total_ms: f64,
+}
+
+/// 重定向检查，返回错误时停止跟随重定向
+pub type RedirectCheck = Arc<dyn Fn(&Url) -> Result<(), String> + Send + Sync>;
+
+/// 域名解析结果检查，返回 false 的地址不会被连接
+pub type AddressCheck = Arc<dyn Fn(IpAddr) -> bool + Send + Sync>;
+
+/// 池中客户端共用的请求检查
+#[derive(Clone, Default)]
+pub struct ClientGuards {
+    pub redirect_check: Option<RedirectCheck>,
+    pub address_check: Option<AddressCheck>,
+}
+
+/// 最多跟随的重定向次数，与reqwest默认值一致
+const MAX_REDIRECTS: usize = 10;
+
+/// 共享的HTTP客户端缓存
+///
+/// 代理、超时等设置相同的请求复用同一个 `reqwest::Client`，
+/// 从而复用其中的连接池和TLS会话。
+#[derive(Default)]
+pub struct HttpClientPool {
+    state: Mutex<PoolState>,
+    guards: ClientGuards,
+}
+
+impl HttpClientPool {
+    /// 创建缓存池，池中客户端跟随每次重定向前都会调用 `redirect_check`，
+    /// 连接前用 `address_check` 过滤域名解析出的地址
+    pub fn new(
+        redirect_check: impl Fn(&Url) -> Result<(), String> + Send + Sync + 'static,
+        address_check: impl Fn(IpAddr) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            state: Mutex::default(),
+            guards: ClientGuards {
+                redirect_check: Some(Arc::new(redirect_check)),
+                address_check: Some(Arc::new(address_check)),
+            },
+        }
+    }
+
+    /// 取得匹配设置的客户端，不存在时新建并缓存
+    pub fn get(&self, proxy_config: Option<&ProxyConfig>, provider_id: Option<&str>, options: &ClientOptions) -> Result<PooledClient, String> {
+        let key = ClientKey::new(proxy_config, provider_id, options);
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(entry) = state.clients.get_mut(&key) {
+            entry.last_used = Instant::now();
+            let client = entry.client.clone();
+            state.metrics.hits += 1;
+            return Ok(PooledClient { client, reused: true });
+        }
+
+        let client = build_client(proxy_config, provider_id, options, &self.guards)?;
+        if state.clients.len() >= MAX_CLIENTS {
+            let oldest = state.clients.iter().min_by_key(|(_, e)| e.last_used).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                state.clients.remove(&oldest);
+                state.metrics.evictions += 1;
+            }
+        }
+        state.clients.insert(key, PooledEntry { client: client.clone(), last_used: Instant::now() });
+        state.metrics.misses += 1;
+        debug!("新建HTTP客户端，当前缓存 {} 个", state.clients.len());
+        Ok(PooledClient { client, reused: false })
+    }
+
+    /// 记录请求从发出到收到响应头的耗时
+    pub fn record_latency(&self, reused: bool, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let mut state = self.state.lock().unwrap();
+        if reused {
+            state.metrics.warm_requests += 1;
+            state.warm_total_ms += ms;
+        } else {
+            state.metrics.cold_requests += 1;
+            state.cold_total_ms += ms;
+        }
+    }
+
+    /// 清空缓存，代理设置变更后调用
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.clients.is_empty() {
+            info!("代理设置已变更，清空 {} 个缓存的HTTP客户端", state.clients.len());
+            state.clients.clear();
+        }
+        state.metrics.invalidations += 1;
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        let state = self.state.lock().unwrap();
+        let average = |total: f64, count: u64| if count == 0 { 0.0 } else { total / count as f64 };
+        PoolMetrics {
+            clients: state.clients.len(),
+            avg_cold_ms: average(state.cold_total_ms, state.metrics.cold_requests),
+            avg_warm_ms: average(state.warm_total_ms, state.metrics.warm_requests),
+            ..state.metrics.clone()
+        }
+    }
+}
+
+/// 按代理配置和客户端设置新建HTTP客户端，不经过缓存
+pub fn build_client(
+    proxy_config: Option<&ProxyConfig>,
+    provider_id: Option<&str>,
+    options: &ClientOptions,
+    guards: &ClientGuards,
+) -> Result<Client, String> {
+    let mut builder = Client::builder().user_agent(USER_AGENT);
+    if let Some(check) = guards.redirect_check.clone() {
+        builder = builder.redirect(Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("重定向次数过多");
+            }
+            match check(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        }));
+    }
+    if let Some(check) = guards.address_check.clone() {
+        // 代理服务器本身可能在本机或局域网，不做过滤。
+        // 配置代理后目标域名由代理服务器解析，本地只解析代理地址，
+        // 因此DNS过滤实际上不再生效，只剩请求前对URL的允许列表检查
+        let exempt = proxy_router::proxy_hosts(proxy_config);
+        builder = builder.dns_resolver(Arc::new(GuardedResolver { check, exempt }));
+    }
+    if let Some(timeout) = options.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = options.read_timeout {
+        builder = builder.read_timeout(timeout);
+    }
+    if let Some(timeout) = options.timeout {
+        builder = builder.timeout(timeout);
+    }
+
+    let tls = &options.tls;
+    for ca in &tls.ca_certs {
+        let pem = load_pem(ca).map_err(|e| format!("读取CA证书失败: {}", e))?;
+        let certs = Certificate::from_pem_bundle(&pem).map_err(|e| format!("CA证书无效: {}", e))?;
+        if certs.is_empty() {
+            return Err(format!("CA证书无效: 未找到证书 {}", ca));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(identity) = load_identity(tls)? {
+        builder = builder.identity(identity);
+    }
+    if tls.insecure {
+        warn!("已关闭TLS证书校验，连接可能被中间人攻击");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    if let Some(proxy) = proxy_router::build_proxy(proxy_config, provider_id)? {
+        builder = builder.proxy(proxy);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// 过滤解析结果的DNS解析器，连接使用的地址就是检查过的地址
+struct GuardedResolver {
+    check: AddressCheck,
+    exempt: Vec<String>,
+}
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_ascii_lowercase();
+        let check = (!self.exempt.contains(&host)).then(|| self.check.clone());
+        Box::pin(async move {
+            let mut addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if let Some(check) = check {
+                let total = addrs.len();
+                addrs.retain(|addr| check(addr.ip()));
+                if addrs.is_empty() && total > 0 {
+                    warn!("拦截解析到内网地址的域名: {}", host);
+                    return Err(format!("未开启局域网访问，{} 解析到本机或内网地址", host).into());
+                }
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// 读取PEM文本，参数本身是PEM时直接使用，否则按文件路径读取
+fn load_pem(source: &str) -> Result<Vec<u8>, String> {
+    if source.trim_start().starts_with("-----BEGIN") {
+        return Ok(source.as_bytes().to_vec());
+    }
+    std::fs::read(source.trim()).map_err(|e| format!("{}: {}", source, e))
+}
+
+fn load_identity(tls: &TlsSettings) -> Result<Option<Identity>, String> {
+    let Some(cert) = tls.client_cert.as_deref() else { return Ok(None) };
+    let is_pkcs12 = {
+        let lower = cert.trim().to_ascii_lowercase();
+        lower.ends_with(".p12") || lower.ends_with(".pfx")
+    };
+
+    let identity = if is_pkcs12 {
+        let der = std::fs::read(cert.trim()).map_err(|e| format!("读取客户端证书失败: {}: {}", cert, e))?;
+        Identity::from_pkcs12_der(&der, tls.client_cert_password.as_deref().unwrap_or_default())
+    } else {
+        let key = tls.client_key.as_deref().ok_or("使用PEM客户端证书时需要提供私钥")?;
+        let cert = load_pem(cert).map_err(|e| format!("读取客户端证书失败: {}", e))?;
+        let key = load_pem(key).map_err(|e| format!("读取客户端私钥失败: {}", e))?;
+        Identity::from_pkcs8_pem(&cert, &key)
+    };
+    identity.map(Some).map_err(|e| format!("客户端证书无效: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy_router::ProxyRouting;
+    use std::collections::BTreeMap;
+
+    fn proxy(routing: Option<ProxyRouting>) -> ProxyConfig {
+        ProxyConfig {
+            enabled: true,
+            proxy_type: "http".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 7890,
+            requires_auth: false,
+            username: None,
+            password: None,
+            routing,
+        }
+    }
+
+    #[test]
+    fn reuses_clients_with_same_settings() {
+        let pool = HttpClientPool::default();
+        let config = proxy(None);
+        let options = ConnectionSettings::default().client_options(false);
+
+        assert!(!pool.get(Some(&config), Some("openai"), &options).unwrap().reused);
+        // 没有代理覆盖时提供商不影响客户端选择
+        assert!(pool.get(Some(&config), Some("claude"), &options).unwrap().reused);
+        assert!(!pool.get(Some(&config), None, &ConnectionSettings::default().client_options(true)).unwrap().reused);
+        assert!(!pool.get(None, None, &options).unwrap().reused);
+
+        let metrics = pool.metrics();
+        assert_eq!((metrics.clients, metrics.hits, metrics.misses), (3, 1, 3));
+    }
+
+    #[test]
+    fn provider_overrides_and_invalidation() {
+        let pool = HttpClientPool::default();
+        let routing = ProxyRouting {
+            provider_overrides: BTreeMap::from([("local".to_string(), "DIRECT".to_string())]),
+            ..ProxyRouting::default()
+        };
+        let config = proxy(Some(routing));
+        let options = ClientOptions::default();
+
+        pool.get(Some(&config), Some("openai"), &options).unwrap();
+        assert!(!pool.get(Some(&config), Some("local"), &options).unwrap().reused);
+        assert!(pool.get(Some(&config), Some("other"), &options).unwrap().reused);
+
+        pool.invalidate();
+        assert!(!pool.get(Some(&config), Some("openai"), &options).unwrap().reused);
+        pool.record_latency(true, Duration::from_millis(10));
+        pool.record_latency(true, Duration::from_millis(30));
+
+        let metrics = pool.metrics();
+        assert_eq!((metrics.clients, metrics.invalidations), (1, 1));
+        assert_eq!((metrics.warm_requests, metrics.avg_warm_ms.round()), (2, 20.0));
+    }
+
+    #[test]
+    fn connection_settings_defaults() {
+        let settings = ConnectionSettings::default();
+        let normal = settings.client_options(false);
+        assert_eq!(normal.timeout, Some(Duration::from_secs(300)));
+        assert_eq!(normal.connect_timeout, Some(Duration::from_secs(30)));
+        assert_eq!(normal.read_timeout, None);
+        assert_eq!(settings.client_options(true).timeout, None);
+        assert_eq!(settings.stream_idle_timeout(), Some(Duration::from_secs(120)));
+
+        let settings: ConnectionSettings = serde_json::from_str(
+            r#"{"total_timeout_secs": 0, "read_timeout_secs": 15, "stream_idle_timeout_secs": 0, "tls": {"insecure": true}}"#,
+        ).unwrap();
+        let options = settings.client_options(false);
+        assert_eq!((options.timeout, options.read_timeout), (None, Some(Duration::from_secs(15))));
+        assert_eq!(settings.stream_idle_timeout(), None);
+        assert!(build_client(None, None, &options, &ClientGuards::default()).is_ok());
+    }
+
+    #[test]
+    fn invalid_tls_material_is_rejected() {
+        let missing = TlsSettings { ca_certs: vec!["/nonexistent/ca.pem".to_string()], ..TlsSettings::default() };
+        let options = ClientOptions { tls: missing, ..ClientOptions::default() };
+        assert!(build_client(None, None, &options, &ClientGuards::default()).unwrap_err().contains("CA证书"));
+
+        let no_key = TlsSettings { client_cert: Some("-----BEGIN CERTIFICATE-----".to_string()), ..TlsSettings::default() };
+        let options = ClientOptions { tls: no_key, ..ClientOptions::default() };
+        assert!(build_client(None, None, &options, &ClientGuards::default()).unwrap_err().contains("私钥"));
+    }
+
+    #[tokio::test]
+    async fn resolver_filters_rejected_addresses() {
+        let resolve = |exempt: &[&str]| {
+            let resolver = GuardedResolver {
+                check: Arc::new(|ip: IpAddr| !ip.is_loopback()),
+                exempt: exempt.iter().map(|host| host.to_string()).collect(),
+            };
+            resolver.resolve("localhost".parse().unwrap())
+        };
+        let error = resolve(&[]).await.err().unwrap();
+        assert!(error.to_string().contains("localhost"));
+        assert!(resolve(&["localhost"]).await.unwrap().next().is_some());
+    }
+}
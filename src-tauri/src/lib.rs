@@ -6,25 +6,66 @@ use tracing_subscriber::{fmt, prelude::*};
 use tracing_subscriber::filter::EnvFilter;
 // 导入serde用于序列化和反序列化
 use serde::{Deserialize, Serialize};
-// 导入reqwest用于HTTP请求
-use reqwest::{Client, Proxy};
 // 导入std库用于HashMap、错误处理和流处理
 use std::collections::HashMap;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use futures_util::stream::StreamExt;
 use tauri::{AppHandle, Emitter, Manager};
 // 导入文件系统相关模块
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::io;
 use regex::Regex;
 // chrono用于时间格式化，但实际使用的是std::time
 
+mod crypto;
 mod database;
+mod file_policy;
+mod fs_binary;
+mod fs_edit;
+mod fs_extract;
+mod fs_grep;
+mod fs_journal;
+mod fs_tree;
+mod fs_watch;
+mod http_allowlist;
+mod http_body;
+mod http_pool;
+mod mcp_client;
+mod migrations;
+mod permissions;
+mod providers;
+mod proxy_router;
+mod retry;
 mod storage_service;
+mod stream_decoder;
+mod stream_registry;
+mod workspace;
 
 use database::Database;
+use mcp_client::{McpClientManager, McpServerSpec};
+use permissions::{
+    McpPermissions, PermissionBroker, PermissionDecision, PermissionRequest, ACTION_FS_DELETE, ACTION_FS_MOVE,
+    ACTION_FS_WRITE, ACTION_HTTP_ALLOWLIST, ACTION_MCP_TOOL, ACTION_WORKSPACE_ADD, ACTION_WORKSPACE_WRITE, DEFAULT_AGENT_ID,
+    SUBJECT_AGENT, SUBJECT_MCP_SERVER, SUBJECT_SETTINGS,
+};
+use providers::{ProviderEvent, ProviderStreamConfig, StreamParser};
+use proxy_router::ProxyRouting;
+use retry::{send_with_retry, RetryAttempt, RetryPolicy};
 use storage_service::StorageService;
+use stream_decoder::StreamChunkDecoder;
+use stream_registry::StreamRegistry;
+use file_policy::PolicyEngine;
+use fs_edit::{FileEdit, ReadRange};
+use fs_extract::ExtractOptions;
+use fs_grep::GrepOptions;
+use fs_journal::{FsJournal, RetentionPolicy};
+use fs_tree::TreeOptions;
+use fs_watch::WatchManager;
+use http_allowlist::{Allowlist, HttpAllowlist, HttpBridgeError, SETTING_ALLOWED_DOMAINS, SETTING_ALLOW_PRIVATE_NETWORK};
+use http_body::{MultipartField, ResponseType};
+use http_pool::{ClientGuards, ConnectionSettings, HttpClientPool, PooledClient};
+use workspace::{ResolvedPath, WorkspaceAccess};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -38,6 +79,7 @@ pub struct ProxyConfig {
     pub requires_auth: bool,
     pub username: Option<String>,
     pub password: Option<String>,
+    pub routing: Option<ProxyRouting>, // 例外列表、规则和按提供商的代理覆盖
 }
 
 /// HTTP请求参数结构体
@@ -47,7 +89,15 @@ pub struct HttpRequestParams {
     pub method: String,
     pub headers: Option<HashMap<String, String>>,
     pub body: Option<String>,
+    pub body_encoding: Option<String>, // "text"（默认）或 "base64"，用于音频、图片等二进制请求体
+    pub multipart: Option<Vec<MultipartField>>, // 设置后以 multipart/form-data 发送，不能与 body 同时使用
+    #[serde(default)]
+    pub response_type: ResponseType, // "text"、"base64" 或 "auto"（默认，按内容类型判断）
     pub proxy_config: Option<ProxyConfig>,
+    pub provider_id: Option<String>, // 用于匹配按提供商的代理覆盖
+    pub connection: Option<ConnectionSettings>, // 提供商的超时和TLS设置
+    pub retry_policy: Option<RetryPolicy>, // 未设置时不重试
+    pub request_id: Option<String>, // 用于关联重试事件
 }
 
 /// HTTP响应结构体
@@ -56,6 +106,8 @@ pub struct HttpResponse {
     pub status: u16,
     pub headers: HashMap<String, String>,
     pub body: String,
+    pub body_encoding: String, // "text" or "base64"
+    pub content_type: Option<String>,
     pub success: bool,
     pub error: Option<String>,
 }
@@ -68,16 +120,33 @@ pub struct StreamRequestParams {
     pub headers: Option<HashMap<String, String>>,
     pub body: Option<String>,
     pub proxy_config: Option<ProxyConfig>,
+    pub provider_id: Option<String>, // 用于匹配按提供商的代理覆盖
+    pub connection: Option<ConnectionSettings>, // 提供商的超时、流式空闲超时和TLS设置
     pub stream_id: String, // 用于标识流式请求的唯一ID
+    pub provider: Option<ProviderStreamConfig>, // 设置后在后端解析流并发送类型化事件
+    pub retry_policy: Option<RetryPolicy>, // 未设置时不重试，开始接收数据后不再重试
 }
 
 /// 流式响应事件结构体
 #[derive(Debug, Serialize, Clone)]
 pub struct StreamEvent {
     pub stream_id: String,
-    pub event_type: String, // "data", "end", "error"
+    pub event_type: String, // "data", "event", "retry", "end", "error", "cancelled"
     pub data: Option<String>,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<ProviderEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryAttempt>,
+}
+
+/// 普通HTTP请求的重试事件
+#[derive(Debug, Serialize, Clone)]
+pub struct HttpRetryEvent {
+    pub request_id: Option<String>,
+    pub url: String,
+    #[serde(flatten)]
+    pub retry: RetryAttempt,
 }
 
 /// 文件系统操作结果结构体
@@ -117,106 +186,132 @@ pub struct SearchOptions {
     pub file_only: Option<bool>,
 }
 
-/// 创建HTTP客户端，支持代理配置
-fn create_http_client(proxy_config: Option<&ProxyConfig>) -> Result<Client, Box<dyn std::error::Error + Send + Sync>> {
-    let mut client_builder = Client::builder()
-        .timeout(Duration::from_secs(300)) // 增加到5分钟超时
-        .user_agent("AiChat/1.0");
-
-    if let Some(config) = proxy_config {
-        if config.enabled {
-            // 清理host中可能包含的协议前缀
-            let clean_host = config.host
-                .strip_prefix("http://")
-                .or_else(|| config.host.strip_prefix("https://"))
-                .or_else(|| config.host.strip_prefix("socks5://"))
-                .unwrap_or(&config.host);
-
-            let proxy_url = match config.proxy_type.as_str() {
-                "http" => format!("http://{}:{}", clean_host, config.port),
-                "https" => format!("https://{}:{}", clean_host, config.port),
-                "socks5" => format!("socks5://{}:{}", clean_host, config.port),
-                _ => return Err("不支持的代理类型".into()),
-            };
-
-            info!("使用代理: {} (类型: {})", proxy_url, config.proxy_type);
+/// 取得HTTP允许列表，失效或尚未加载时从提供商配置和设置重新加载
+async fn load_http_allowlist(state: &AppState) -> Result<Arc<Allowlist>, String> {
+    if let Some(allowlist) = state.http_allowlist.get() {
+        return Ok(allowlist);
+    }
 
-            let mut proxy = Proxy::all(&proxy_url)?;
+    let storage = state.storage_service.lock().await;
+    let providers = storage.get_providers_redacted().await.map_err(|e| e.to_string())?;
+    let user_domains = match storage.get_setting(SETTING_ALLOWED_DOMAINS).await.map_err(|e| e.to_string())? {
+        Some(json) if !json.trim().is_empty() => serde_json::from_str::<Vec<String>>(&json)
+            .map_err(|e| format!("允许域名设置格式无效: {}", e))?,
+        _ => Vec::new(),
+    };
+    let allow_private_network = storage.get_setting(SETTING_ALLOW_PRIVATE_NETWORK).await
+        .map_err(|e| e.to_string())?
+        .is_some_and(|value| value.trim() == "true");
 
-            // 如果需要认证
-            if config.requires_auth {
-                if let (Some(username), Some(password)) = (&config.username, &config.password) {
-                    proxy = proxy.basic_auth(username, password);
-                    info!("代理认证已配置");
-                }
-            }
+    Ok(state.http_allowlist.set(Allowlist::new(&providers, user_domains, allow_private_network)))
+}
 
-            client_builder = client_builder.proxy(proxy);
-        }
+/// 检查HTTP桥接请求的地址，被拦截时记录日志并返回类型化错误
+async fn check_http_url(state: &AppState, url: &str) -> Result<(), HttpBridgeError> {
+    let allowlist = load_http_allowlist(state).await?;
+    let parsed = reqwest::Url::parse(url).map_err(|e| HttpBridgeError::InvalidUrl(format!("无效的URL {}: {}", url, e)));
+    let result = match parsed {
+        Ok(parsed) => allowlist.check(&parsed),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = &result {
+        warn!("拦截HTTP请求: {} ({})", url, e);
     }
+    result
+}
 
-    Ok(client_builder.build()?)
-}
-
-/// 创建流式HTTP客户端，支持代理配置，无超时限制
-fn create_stream_http_client(proxy_config: Option<&ProxyConfig>) -> Result<Client, Box<dyn std::error::Error + Send + Sync>> {
-    let mut client_builder = Client::builder()
-        .user_agent("AiChat/1.0");
-    // 流式请求不设置超时，因为需要持续接收数据
-
-    if let Some(config) = proxy_config {
-        if config.enabled {
-            // 清理host中可能包含的协议前缀
-            let clean_host = config.host
-                .strip_prefix("http://")
-                .or_else(|| config.host.strip_prefix("https://"))
-                .or_else(|| config.host.strip_prefix("socks5://"))
-                .unwrap_or(&config.host);
-
-            let proxy_url = match config.proxy_type.as_str() {
-                "http" => format!("http://{}:{}", clean_host, config.port),
-                "https" => format!("https://{}:{}", clean_host, config.port),
-                "socks5" => format!("socks5://{}:{}", clean_host, config.port),
-                _ => return Err("不支持的代理类型".into()),
-            };
-
-            info!("使用流式代理: {} (类型: {})", proxy_url, config.proxy_type);
+/// 把请求中的脱敏密钥替换为提供商的真实密钥
+///
+/// 前端只能取得脱敏的提供商列表，构造请求时以脱敏值占位。
+/// 只有请求发往该提供商自己的地址时才替换，避免密钥被发到其他地址。
+async fn inject_provider_key(
+    state: &AppState,
+    provider_id: Option<&str>,
+    url: &mut String,
+    headers: Option<&mut HashMap<String, String>>,
+    body: Option<&mut String>,
+) -> Result<(), HttpBridgeError> {
+    let Some(provider_id) = provider_id else { return Ok(()) };
+    let provider = {
+        let storage = state.storage_service.lock().await;
+        storage.get_providers().await.map_err(|e| e.to_string())?.into_iter().find(|p| p.id == provider_id)
+    };
+    let Some(provider) = provider else { return Ok(()) };
+    let placeholder = crypto::redact(&provider.api_key);
+    if placeholder.is_empty() {
+        return Ok(());
+    }
 
-            let mut proxy = Proxy::all(&proxy_url)?;
+    let host = reqwest::Url::parse(url).ok().and_then(|url| http_allowlist::normalized_host(&url));
+    let mut targets: Vec<&mut String> = vec![url];
+    targets.extend(headers.into_iter().flat_map(|headers| headers.values_mut()));
+    targets.extend(body);
+    targets.retain(|value| value.contains(&placeholder));
+    if targets.is_empty() {
+        return Ok(());
+    }
 
-            // 如果需要认证
-            if config.requires_auth {
-                if let (Some(username), Some(password)) = (&config.username, &config.password) {
-                    proxy = proxy.basic_auth(username, password);
-                    info!("流式代理认证已配置");
-                }
-            }
+    if !host.is_some_and(|host| http_allowlist::provider_hosts(std::slice::from_ref(&provider)).contains(&host)) {
+        warn!("拒绝将提供商 {} 的密钥发送到其他地址", provider.name);
+        return Err(HttpBridgeError::Request(format!("提供商 {} 的密钥只能发送到该提供商的地址", provider.name)));
+    }
+    for value in targets {
+        *value = value.replace(&placeholder, &provider.api_key);
+    }
+    Ok(())
+}
 
-            client_builder = client_builder.proxy(proxy);
+/// 放宽HTTP允许列表前请求用户确认
+///
+/// 每次变更单独确认，选择始终允许也不保存长期授权，以免之后的修改跳过确认。
+///
+/// 注意：确认弹窗由前端页面显示和答复，而允许列表要约束的正是同一个页面，
+/// 页面中被注入的脚本可以自行答复。因此这只是防止误操作的提示，不是安全边界；
+/// 需要抵御页面本身被攻破时，应改为由后端弹出原生对话框确认。
+async fn confirm_allowlist_change(
+    state: &AppState,
+    setting: &str,
+    target: &str,
+    summary: String,
+    details: serde_json::Value,
+) -> Result<(), String> {
+    let request = PermissionRequest::new(SUBJECT_SETTINGS, setting, ACTION_HTTP_ALLOWLIST, target, summary.clone(), details);
+    match state.permission_broker.ask(request).await {
+        PermissionDecision::AllowOnce | PermissionDecision::AllowAlways => Ok(()),
+        PermissionDecision::Deny => {
+            warn!("用户拒绝了操作: {}", summary);
+            Err(format!("用户拒绝了操作: {}", summary))
         }
     }
+}
 
-    Ok(client_builder.build()?)
+/// 当前生效的允许列表，设置无法读取时按空列表处理，此时所有放宽都需要确认
+async fn current_allowlist_info(state: &AppState) -> http_allowlist::AllowlistInfo {
+    load_http_allowlist(state).await.map(|allowlist| allowlist.info().clone()).unwrap_or_default()
 }
 
 /// 发送HTTP请求的Tauri命令
 #[tauri::command]
-async fn send_http_request(params: HttpRequestParams) -> Result<HttpResponse, String> {
+async fn send_http_request(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    mut params: HttpRequestParams,
+) -> Result<HttpResponse, HttpBridgeError> {
     info!("发送HTTP请求到: {}", params.url);
     debug!("请求方法: {}", params.method);
 
-    // 创建HTTP客户端
-    let client = create_http_client(params.proxy_config.as_ref())
+    // 只允许请求允许列表中的地址
+    check_http_url(&state, &params.url).await?;
+    inject_provider_key(&state, params.provider_id.as_deref(), &mut params.url, params.headers.as_mut(), params.body.as_mut()).await?;
+
+    // 从缓存池取得HTTP客户端
+    let options = params.connection.unwrap_or_default().client_options(false);
+    let PooledClient { client, reused } = state.http_pool
+        .get(params.proxy_config.as_ref(), params.provider_id.as_deref(), &options)
         .map_err(|e| format!("创建HTTP客户端失败: {}", e))?;
 
     // 构建请求
-    let mut request_builder = match params.method.to_uppercase().as_str() {
-        "GET" => client.get(&params.url),
-        "POST" => client.post(&params.url),
-        "PUT" => client.put(&params.url),
-        "DELETE" => client.delete(&params.url),
-        _ => return Err("不支持的HTTP方法".to_string()),
-    };
+    let mut request_builder = http_body::build_request(&client, &params.method, &params.url)?;
 
     // 添加请求头
     if let Some(headers) = params.headers {
@@ -226,13 +321,31 @@ async fn send_http_request(params: HttpRequestParams) -> Result<HttpResponse, St
     }
 
     // 添加请求体
-    if let Some(body) = params.body {
-        request_builder = request_builder.body(body);
+    match (params.body, params.multipart) {
+        (Some(_), Some(_)) => return Err("body 和 multipart 不能同时设置".to_string().into()),
+        (Some(body), None) => {
+            request_builder = request_builder.body(http_body::decode_body(body, params.body_encoding.as_deref())?);
+        }
+        // multipart表单无法克隆，不会重试
+        (None, Some(fields)) => request_builder = request_builder.multipart(http_body::build_form(fields)?),
+        (None, None) => {}
     }
 
-    // 发送请求
-    match request_builder.send().await {
+    // 按重试策略发送请求
+    let policy = params.retry_policy.unwrap_or_else(RetryPolicy::none);
+    let started = Instant::now();
+    let send_result = send_with_retry(&policy, request_builder, |retry| {
+        warn!("HTTP请求 {} 将重试 ({}/{}): {}", params.url, retry.attempt, retry.max_attempts, retry.reason);
+        let _ = app.emit("http-retry", HttpRetryEvent {
+            request_id: params.request_id.clone(),
+            url: params.url.clone(),
+            retry: retry.clone(),
+        });
+    }).await;
+
+    match send_result {
         Ok(response) => {
+            state.http_pool.record_latency(reused, started.elapsed());
             let status = response.status().as_u16();
             
             // 提取响应头
@@ -243,51 +356,124 @@ async fn send_http_request(params: HttpRequestParams) -> Result<HttpResponse, St
                 }
             }
 
-            // 获取响应体
-            match response.text().await {
-                Ok(body) => {
+            let content_type = response.headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string());
+
+            // 获取响应体，二进制内容按base64返回
+            match response.bytes().await {
+                Ok(bytes) => {
                     info!("HTTP请求成功，状态码: {}", status);
+                    let (body, body_encoding) =
+                        http_body::encode_response(&bytes, content_type.as_deref(), params.response_type);
                     Ok(HttpResponse {
                         status,
                         headers,
                         body,
+                        body_encoding: body_encoding.to_string(),
+                        content_type,
                         success: status >= 200 && status < 300,
                         error: None,
                     })
                 }
                 Err(e) => {
                     error!("读取响应体失败: {}", e);
-                    Err(format!("读取响应体失败: {}", e))
+                    Err(format!("读取响应体失败: {}", e).into())
                 }
             }
         }
         Err(e) => {
             error!("HTTP请求失败: {}", e);
-            Err(format!("HTTP请求失败: {}", e))
+            Err(format!("HTTP请求失败: {}", e).into())
+        }
+    }
+}
+
+/// 发送提供商解析出的类型化流式事件
+fn emit_provider_event(app: &AppHandle, stream_id: &str, event: ProviderEvent) {
+    let _ = app.emit("stream-event", StreamEvent {
+        stream_id: stream_id.to_string(),
+        event_type: "event".to_string(),
+        data: None,
+        error: None,
+        payload: Some(event),
+        retry: None,
+    });
+}
+
+/// 转发解码后的流式文本，指定了提供商时解析为类型化事件，否则作为原始数据发送
+fn forward_stream_text(app: &AppHandle, stream_id: &str, parser: Option<&mut StreamParser>, text: &str) {
+    match parser {
+        Some(parser) => {
+            for event in parser.feed(text) {
+                emit_provider_event(app, stream_id, event);
+            }
+        }
+        None => {
+            let _ = app.emit("stream-event", StreamEvent {
+                stream_id: stream_id.to_string(),
+                event_type: "data".to_string(),
+                data: Some(text.to_string()),
+                error: None,
+                payload: None,
+                retry: None,
+            });
         }
     }
 }
 
+/// 读取下一个流式数据块，超过空闲超时未收到数据时返回 None
+async fn next_chunk<S: futures_util::Stream + Unpin>(stream: &mut S, idle_timeout: Option<Duration>) -> Option<Option<S::Item>> {
+    match idle_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, stream.next()).await.ok(),
+        None => Some(stream.next().await),
+    }
+}
+
+/// 发送流式请求已取消事件
+fn emit_stream_cancelled(app: &AppHandle, stream_id: &str) {
+    let _ = app.emit("stream-event", StreamEvent {
+        stream_id: stream_id.to_string(),
+        event_type: "cancelled".to_string(),
+        data: None,
+        error: None,
+        payload: None,
+        retry: None,
+    });
+}
+
 /// 发送流式HTTP请求的Tauri命令
 #[tauri::command]
-async fn send_stream_request(app: AppHandle, params: StreamRequestParams) -> Result<String, String> {
+async fn send_stream_request(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    mut params: StreamRequestParams,
+) -> Result<String, HttpBridgeError> {
     info!("发送流式HTTP请求到: {}", params.url);
     debug!("请求方法: {}, Stream ID: {}", params.method, params.stream_id);
 
+    // 只允许请求允许列表中的地址
+    check_http_url(&state, &params.url).await?;
+    inject_provider_key(&state, params.provider_id.as_deref(), &mut params.url, params.headers.as_mut(), params.body.as_mut()).await?;
+
     let stream_id = params.stream_id.clone();
+
+    // 指定了提供商时由后端解析流式响应
+    let parser = params.provider.as_ref()
+        .map(StreamParser::new)
+        .transpose()?;
     
-    // 创建流式HTTP客户端（无超时限制）
-    let client = create_stream_http_client(params.proxy_config.as_ref())
+    // 从缓存池取得流式HTTP客户端，流式请求需要持续接收数据，默认不限制总时长，
+    // 由空闲超时检测停滞的连接
+    let connection = params.connection.clone().unwrap_or_default();
+    let idle_timeout = connection.stream_idle_timeout();
+    let PooledClient { client, reused } = state.http_pool
+        .get(params.proxy_config.as_ref(), params.provider_id.as_deref(), &connection.client_options(true))
         .map_err(|e| format!("创建流式HTTP客户端失败: {}", e))?;
 
     // 构建请求
-    let mut request_builder = match params.method.to_uppercase().as_str() {
-        "GET" => client.get(&params.url),
-        "POST" => client.post(&params.url),
-        "PUT" => client.put(&params.url),
-        "DELETE" => client.delete(&params.url),
-        _ => return Err("不支持的HTTP方法".to_string()),
-    };
+    let mut request_builder = http_body::build_request(&client, &params.method, &params.url)?;
 
     // 添加请求头
     if let Some(headers) = params.headers {
@@ -301,9 +487,38 @@ async fn send_stream_request(app: AppHandle, params: StreamRequestParams) -> Res
         request_builder = request_builder.body(body);
     }
 
-    // 发送请求并处理流式响应
-    match request_builder.send().await {
+    // 注册到活动流式请求表，守卫释放时自动注销
+    let guard = state.stream_registry.register(&stream_id, &params.url)?;
+    let token = guard.token().clone();
+
+    // 按重试策略发送请求，只在收到响应头之前重试
+    let policy = params.retry_policy.clone().unwrap_or_else(RetryPolicy::none);
+    let started = Instant::now();
+    let send = send_with_retry(&policy, request_builder, |retry| {
+        warn!("流式请求 {} 将重试 ({}/{}): {}", stream_id, retry.attempt, retry.max_attempts, retry.reason);
+        let _ = app.emit("stream-event", StreamEvent {
+            stream_id: stream_id.clone(),
+            event_type: "retry".to_string(),
+            data: None,
+            error: None,
+            payload: None,
+            retry: Some(retry.clone()),
+        });
+    });
+
+    // 发送请求并处理流式响应，等待响应头和重试期间也可取消
+    let send_result = tokio::select! {
+        result = send => result,
+        _ = token.cancelled() => {
+            info!("流式请求 {} 在建立连接时被取消", stream_id);
+            emit_stream_cancelled(&app, &stream_id);
+            return Ok(format!("流式请求已取消，Stream ID: {}", stream_id));
+        }
+    };
+
+    match send_result {
         Ok(response) => {
+            state.http_pool.record_latency(reused, started.elapsed());
             let status = response.status().as_u16();
             info!("流式HTTP请求成功建立，状态码: {}", status);
 
@@ -314,32 +529,56 @@ async fn send_stream_request(app: AppHandle, params: StreamRequestParams) -> Res
                     event_type: "error".to_string(),
                     data: None,
                     error: Some(error_msg.clone()),
+                    payload: None,
+                    retry: None,
                 });
-                return Err(error_msg);
+                return Err(error_msg.into());
             }
 
             // 异步处理流式响应
             let app_clone = app.clone();
             let stream_id_clone = stream_id.clone();
+            let mut parser = parser;
             
             tokio::spawn(async move {
+                let _guard = guard;
                 let mut stream = response.bytes_stream();
                 let mut chunk_count = 0;
+                // 缓存跨数据块截断的UTF-8字符和SSE行
+                let mut decoder = StreamChunkDecoder::default();
                 
-                while let Some(chunk_result) = stream.next().await {
-                    chunk_count += 1;
-                    match chunk_result {
-                        Ok(chunk) => {
-                            if let Ok(text) = std::str::from_utf8(&chunk) {
-                                // 发送数据事件
+                loop {
+                    // 取消时丢弃响应流，连接随之关闭
+                    let chunk_result = tokio::select! {
+                        biased;
+                        _ = token.cancelled() => {
+                            info!("流式请求 {} 已取消，共收到 {} 个数据块", stream_id_clone, chunk_count);
+                            emit_stream_cancelled(&app_clone, &stream_id_clone);
+                            return;
+                        }
+                        next = next_chunk(&mut stream, idle_timeout) => match next {
+                            Some(Some(chunk_result)) => chunk_result,
+                            Some(None) => break,
+                            None => {
+                                let timeout = idle_timeout.unwrap_or_default().as_secs();
+                                warn!("流式请求 {} 超过 {} 秒未收到数据，已中止", stream_id_clone, timeout);
                                 let _ = app_clone.emit("stream-event", StreamEvent {
                                     stream_id: stream_id_clone.clone(),
-                                    event_type: "data".to_string(),
-                                    data: Some(text.to_string()),
-                                    error: None,
+                                    event_type: "error".to_string(),
+                                    data: None,
+                                    error: Some(format!("流式响应超时: 超过 {} 秒未收到数据", timeout)),
+                                    payload: None,
+                                    retry: None,
                                 });
-                            } else {
-                                warn!("数据块不是有效的UTF-8文本");
+                                break;
+                            }
+                        },
+                    };
+                    chunk_count += 1;
+                    match chunk_result {
+                        Ok(chunk) => {
+                            if let Some(text) = decoder.push(&chunk) {
+                                forward_stream_text(&app_clone, &stream_id_clone, parser.as_mut(), &text);
                             }
                         }
                         Err(e) => {
@@ -349,6 +588,8 @@ async fn send_stream_request(app: AppHandle, params: StreamRequestParams) -> Res
                                 event_type: "error".to_string(),
                                 data: None,
                                 error: Some(format!("读取流式数据失败: {}", e)),
+                                payload: None,
+                                retry: None,
                             });
                             break;
                         }
@@ -356,6 +597,15 @@ async fn send_stream_request(app: AppHandle, params: StreamRequestParams) -> Res
                 }
                 
                 info!("流式数据接收完成，总共收到 {} 个数据块", chunk_count);
+
+                if let Some(text) = decoder.finish() {
+                    forward_stream_text(&app_clone, &stream_id_clone, parser.as_mut(), &text);
+                }
+                if let Some(parser) = parser.as_mut() {
+                    for event in parser.finish() {
+                        emit_provider_event(&app_clone, &stream_id_clone, event);
+                    }
+                }
                 
                 // 发送结束事件
                 let _ = app_clone.emit("stream-event", StreamEvent {
@@ -363,6 +613,8 @@ async fn send_stream_request(app: AppHandle, params: StreamRequestParams) -> Res
                     event_type: "end".to_string(),
                     data: None,
                     error: None,
+                    payload: None,
+                    retry: None,
                 });
                 
                 info!("流式请求 {} 处理完成", stream_id_clone);
@@ -378,43 +630,43 @@ async fn send_stream_request(app: AppHandle, params: StreamRequestParams) -> Res
                 event_type: "error".to_string(),
                 data: None,
                 error: Some(error_msg.clone()),
+                payload: None,
+                retry: None,
             });
-            Err(error_msg)
+            Err(error_msg.into())
         }
     }
 }
 
-/// 文件系统安全检查
-fn is_path_safe(path: &str) -> bool {
-    let path = Path::new(path);
-    
-    // 检查是否包含危险的路径组件
-    for component in path.components() {
-        match component {
-            std::path::Component::ParentDir => return false, // 不允许 ".."
-            std::path::Component::RootDir => return false,   // 不允许绝对路径
-            _ => {}
-        }
-    }
-    
-    // 检查文件扩展名
-    if let Some(extension) = path.extension() {
-        let ext = extension.to_string_lossy().to_lowercase();
-        let allowed_extensions = [
-            "txt", "md", "json", "js", "ts", "jsx", "tsx",
-            "css", "scss", "html", "xml", "yaml", "yml",
-            "py", "java", "cpp", "c", "h", "cs", "php",
-            "rb", "go", "rs", "swift", "kt", "scala",
-            "sql", "sh", "bat", "ps1", "dockerfile",
-            "gitignore", "env", "config", "ini", "toml"
-        ];
-        
-        if !allowed_extensions.contains(&ext.as_str()) && !ext.is_empty() {
-            return false;
-        }
+/// 取消流式请求的Tauri命令，请求不存在或已结束时返回false
+#[tauri::command]
+async fn cancel_stream(state: tauri::State<'_, AppState>, stream_id: String) -> Result<bool, String> {
+    let cancelled = state.stream_registry.cancel(&stream_id);
+    if cancelled {
+        info!("已请求取消流式请求: {}", stream_id);
+    } else {
+        debug!("要取消的流式请求不存在: {}", stream_id);
     }
-    
-    true
+    Ok(cancelled)
+}
+
+/// 列出活动流式请求的Tauri命令
+#[tauri::command]
+async fn list_active_streams(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    serde_json::to_string(&state.stream_registry.list()).map_err(|e| e.to_string())
+}
+
+/// 获取生效的HTTP允许列表的Tauri命令
+#[tauri::command]
+async fn http_get_allowlist(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let allowlist = load_http_allowlist(&state).await?;
+    serde_json::to_string(allowlist.info()).map_err(|e| e.to_string())
+}
+
+/// 获取HTTP客户端缓存统计的Tauri命令，用于比较复用连接前后的请求耗时
+#[tauri::command]
+async fn http_pool_metrics(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    serde_json::to_string(&state.http_pool.metrics()).map_err(|e| e.to_string())
 }
 
 /// 获取权限字符串（跨平台兼容）
@@ -434,40 +686,93 @@ fn get_permissions_string(metadata: &fs::Metadata) -> String {
     }
 }
 
-/// 获取安全的绝对路径
-fn get_safe_path(path: &str) -> Result<PathBuf, String> {
-    if !is_path_safe(path) {
-        return Err("路径不安全或文件类型不被支持".to_string());
-    }
-    
-    let current_dir = std::env::current_dir()
-        .map_err(|e| format!("无法获取当前目录: {}", e))?;
-    
-    let full_path = if Path::new(path).is_absolute() {
-        return Err("不允许使用绝对路径".to_string());
-    } else {
-        current_dir.join(path)
+/// 在指定工作区内解析路径并按工作区策略检查
+async fn resolve_workspace_path(
+    state: &AppState,
+    root_id: &str,
+    path: &str,
+    access: WorkspaceAccess,
+) -> Result<ResolvedPath, String> {
+    let root = load_workspace_root(state, root_id).await?;
+    workspace::resolve(&root, path, access)
+}
+
+async fn load_workspace_root(state: &AppState, root_id: &str) -> Result<database::WorkspaceRoot, String> {
+    let storage = state.storage_service.lock().await;
+    storage.get_workspace_root(root_id).await.map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("未找到工作区: {}", root_id))
+}
+
+/// 需要用户审批的操作：已有长期授权时直接放行，否则发出审批请求并等待答复
+async fn authorize(state: &AppState, request: PermissionRequest) -> Result<(), String> {
+    let granted = {
+        let storage = state.storage_service.lock().await;
+        storage.has_permission_grant(&request.subject_type, &request.subject_id, &request.action, &request.target)
+            .await
+            .map_err(|e| e.to_string())?
     };
-    
-    // 确保路径在当前目录下
-    let canonical_current = current_dir.canonicalize()
-        .map_err(|e| format!("无法规范化当前目录: {}", e))?;
-    
-    if let Ok(canonical_path) = full_path.canonicalize() {
-        if !canonical_path.starts_with(&canonical_current) {
-            return Err("路径超出允许范围".to_string());
+    if granted {
+        debug!("已有长期授权: {} {} {}", request.subject_id, request.action, request.target);
+        return Ok(());
+    }
+
+    match state.permission_broker.ask(request.clone()).await {
+        PermissionDecision::AllowOnce => Ok(()),
+        PermissionDecision::AllowAlways => {
+            let grant = database::PermissionGrant {
+                id: uuid::Uuid::new_v4().to_string(),
+                subject_type: request.subject_type,
+                subject_id: request.subject_id,
+                action: request.action,
+                target: request.target,
+                created_at: chrono::Utc::now(),
+            };
+            let storage = state.storage_service.lock().await;
+            storage.save_permission_grant(&grant).await.map_err(|e| e.to_string())
+        }
+        PermissionDecision::Deny => {
+            warn!("用户拒绝了操作: {}", request.summary);
+            Err(format!("用户拒绝了操作: {}", request.summary))
         }
     }
-    
-    Ok(full_path)
+}
+
+/// 文件系统修改操作的审批，按智能体和工作区授权
+async fn authorize_fs(
+    state: &AppState,
+    agent_id: Option<&str>,
+    action: &str,
+    root_id: &str,
+    summary: String,
+    paths: &[&str],
+) -> Result<(), String> {
+    let agent_id = agent_id.filter(|id| !id.is_empty()).unwrap_or(DEFAULT_AGENT_ID);
+    let details = serde_json::json!({ "root_id": root_id, "paths": paths });
+    authorize(state, PermissionRequest::new(SUBJECT_AGENT, agent_id, action, root_id, summary, details)).await
+}
+
+/// 解析需要写权限的工作区路径，并请求用户审批
+async fn resolve_authorized_path(
+    state: &AppState,
+    agent_id: Option<&str>,
+    action: &str,
+    root_id: &str,
+    path: &str,
+    summary: String,
+) -> Result<ResolvedPath, String> {
+    let resolved = resolve_workspace_path(state, root_id, path, WorkspaceAccess::Write).await?;
+    authorize_fs(state, agent_id, action, root_id, summary, &[path]).await?;
+    Ok(resolved)
 }
 
 /// 读取文件内容的Tauri命令
 #[tauri::command]
-async fn fs_read_file(path: String) -> Result<FileSystemResult, String> {
+async fn fs_read_file(state: tauri::State<'_, AppState>, root_id: String, path: String) -> Result<FileSystemResult, String> {
     info!("读取文件: {}", path);
     
-    match get_safe_path(&path) {
+    let resolved = resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await
+        .and_then(|r| r.check_readable_file().map(|_| r.path));
+    match resolved {
         Ok(safe_path) => {
             match fs::read_to_string(&safe_path) {
                 Ok(content) => {
@@ -499,21 +804,261 @@ async fn fs_read_file(path: String) -> Result<FileSystemResult, String> {
     }
 }
 
+/// 按行或字节范围读取文件的Tauri命令
+///
+/// 不指定范围时返回整个文件；结果中的 `hash` 始终为整个文件的哈希，可用于 `fs_edit_file`。
+#[tauri::command]
+async fn fs_read_file_range(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    range: Option<ReadRange>,
+) -> Result<FileSystemResult, String> {
+    info!("部分读取文件: {} 范围: {:?}", path, range);
+
+    let resolved = resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await
+        .and_then(|r| r.check_readable_file().map(|_| r.path));
+    let content = match resolved.and_then(|p| fs::read_to_string(&p).map_err(|e| format!("读取文件失败: {}", e))) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("部分读取文件失败: {} - {}", path, e);
+            return Ok(FileSystemResult {
+                success: false,
+                data: None,
+                error: Some(e),
+            });
+        }
+    };
+
+    let result = fs_edit::read_range(&content, &range.unwrap_or_default());
+    let data = serde_json::to_string(&result)
+        .map_err(|e| format!("序列化失败: {}", e))?;
+    Ok(FileSystemResult {
+        success: true,
+        data: Some(data),
+        error: None,
+    })
+}
+
+/// 以base64读取二进制文件的Tauri命令，返回内容、识别出的MIME类型、大小和哈希
+#[tauri::command]
+async fn fs_read_file_binary(state: tauri::State<'_, AppState>, root_id: String, path: String) -> Result<FileSystemResult, String> {
+    info!("读取二进制文件: {}", path);
+
+    let result = async {
+        let resolved = resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await?;
+        let metadata = fs::metadata(&resolved.path).map_err(|e| format!("读取文件信息失败: {}", e))?;
+        if !metadata.is_file() {
+            return Err("指定路径不是文件".to_string());
+        }
+        resolved.check_binary_size(metadata.len())?;
+
+        let bytes = fs::read(&resolved.path).map_err(|e| format!("读取文件失败: {}", e))?;
+        let content = fs_binary::BinaryContent::new(&resolved.path, &bytes);
+        info!("二进制文件读取成功: {} ({} 字节, {})", path, content.size, content.mime_type);
+        serde_json::to_string(&content).map_err(|e| format!("序列化失败: {}", e))
+    }.await;
+
+    match result {
+        Ok(data) => Ok(FileSystemResult {
+            success: true,
+            data: Some(data),
+            error: None,
+        }),
+        Err(e) => {
+            error!("二进制文件读取失败: {} - {}", path, e);
+            Ok(FileSystemResult {
+                success: false,
+                data: None,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+/// 提取文档文本的Tauri命令，支持PDF、DOCX、HTML、CSV和纯文本文件
+///
+/// 结果带有页码和章节标记，并按 `options.chunk_size` 分块。
+#[tauri::command]
+async fn fs_extract_text(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    options: Option<ExtractOptions>,
+) -> Result<FileSystemResult, String> {
+    info!("提取文档文本: {}", path);
+
+    let result = async {
+        let resolved = resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await?;
+        let metadata = fs::metadata(&resolved.path).map_err(|e| format!("读取文件信息失败: {}", e))?;
+        if !metadata.is_file() {
+            return Err("指定路径不是文件".to_string());
+        }
+        resolved.check_binary_size(metadata.len())?;
+
+        // 解析文档是CPU密集的阻塞操作，放到阻塞线程池执行
+        let options = options.unwrap_or_default();
+        let document = tokio::task::spawn_blocking(move || {
+            let bytes = fs::read(&resolved.path).map_err(|e| format!("读取文件失败: {}", e))?;
+            fs_extract::extract(&resolved.path, &bytes, &options)
+        })
+        .await
+        .map_err(|e| format!("提取任务失败: {}", e))??;
+
+        info!("文档文本提取成功: {} ({}, {} 字符, {} 块)", path, document.kind, document.total_chars, document.chunks.len());
+        serde_json::to_string(&document).map_err(|e| format!("序列化失败: {}", e))
+    }.await;
+
+    match result {
+        Ok(data) => Ok(FileSystemResult {
+            success: true,
+            data: Some(data),
+            error: None,
+        }),
+        Err(e) => {
+            error!("文档文本提取失败: {} - {}", path, e);
+            Ok(FileSystemResult {
+                success: false,
+                data: None,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+/// 写入base64编码的二进制内容的Tauri命令，`data` 也可以是data URL
+#[tauri::command]
+async fn fs_write_file_binary(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    data: String,
+    agent_id: Option<String>,
+) -> Result<FileSystemResult, String> {
+    info!("写入二进制文件: {}", path);
+
+    let result = async {
+        let bytes = fs_binary::decode_base64(&data)?;
+        let resolved = resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Write).await?;
+        resolved.check_binary_size(bytes.len() as u64)?;
+        authorize_fs(&state, agent_id.as_deref(), ACTION_FS_WRITE, &root_id, format!("写入文件 {}", path), &[&path]).await?;
+
+        if let Some(parent) = resolved.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("创建父目录失败: {}", e))?;
+        }
+        let entry = state.journal.record(&root_id, "write", &[(&path, &resolved.path)])?;
+        fs_edit::write_atomic(&resolved.path, &bytes).map_err(|e| {
+            state.journal.discard(&entry.id);
+            format!("写入文件失败: {}", e)
+        })?;
+        state.journal.complete(&entry.id);
+
+        info!("二进制文件写入成功: {} ({} 字节)", path, bytes.len());
+        Ok(serde_json::json!({
+            "hash": fs_edit::content_hash(&bytes),
+            "bytes": bytes.len(),
+            "mime_type": fs_binary::sniff_mime(&resolved.path, &bytes),
+            "journal_id": entry.id,
+        }).to_string())
+    }.await;
+
+    match result {
+        Ok(data) => Ok(FileSystemResult {
+            success: true,
+            data: Some(data),
+            error: None,
+        }),
+        Err(e) => {
+            error!("二进制文件写入失败: {} - {}", path, e);
+            Ok(FileSystemResult {
+                success: false,
+                data: None,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+/// 编辑文件的Tauri命令，文件哈希与 `expected_hash` 不一致时拒绝修改
+#[tauri::command]
+async fn fs_edit_file(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    expected_hash: String,
+    edits: Vec<FileEdit>,
+    agent_id: Option<String>,
+) -> Result<FileSystemResult, String> {
+    info!("编辑文件: {} ({} 个编辑)", path, edits.len());
+
+    let result = async {
+        let resolved = resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Write).await?;
+        resolved.check_readable_file()?;
+        let content = fs::read_to_string(&resolved.path)
+            .map_err(|e| format!("读取文件失败: {}", e))?;
+
+        if fs_edit::content_hash(content.as_bytes()) != expected_hash {
+            return Err("文件在读取后已被修改，请重新读取后再编辑".to_string());
+        }
+
+        let updated = fs_edit::apply_edits(&content, &edits)?;
+        resolved.check_writable_content(updated.as_bytes())?;
+        authorize_fs(&state, agent_id.as_deref(), ACTION_FS_WRITE, &root_id, format!("编辑文件 {}", path), &[&path]).await?;
+        let entry = state.journal.record(&root_id, "edit", &[(&path, &resolved.path)])?;
+        fs_edit::write_atomic(&resolved.path, updated.as_bytes()).map_err(|e| {
+            state.journal.discard(&entry.id);
+            format!("写入文件失败: {}", e)
+        })?;
+        state.journal.complete(&entry.id);
+
+        Ok(serde_json::json!({
+            "hash": fs_edit::content_hash(updated.as_bytes()),
+            "bytes": updated.len(),
+            "journal_id": entry.id,
+        }).to_string())
+    }.await;
+
+    match result {
+        Ok(data) => {
+            info!("文件编辑成功: {}", path);
+            Ok(FileSystemResult {
+                success: true,
+                data: Some(data),
+                error: None,
+            })
+        }
+        Err(e) => {
+            error!("文件编辑失败: {} - {}", path, e);
+            Ok(FileSystemResult {
+                success: false,
+                data: None,
+                error: Some(e),
+            })
+        }
+    }
+}
+
 /// 写入文件内容的Tauri命令
 #[tauri::command]
-async fn fs_write_file(path: String, content: String) -> Result<FileSystemResult, String> {
+async fn fs_write_file(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    content: String,
+    agent_id: Option<String>,
+) -> Result<FileSystemResult, String> {
     info!("写入文件: {} ({} 字符)", path, content.len());
     
-    // 检查内容大小（10MB限制）
-    if content.len() > 10 * 1024 * 1024 {
-        return Ok(FileSystemResult {
-            success: false,
-            data: None,
-            error: Some("文件内容过大，超过10MB限制".to_string()),
-        });
-    }
-    
-    match get_safe_path(&path) {
+    // 按工作区策略检查内容大小和类型，再请求用户审批
+    let resolved = match resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Write).await
+        .and_then(|r| r.check_writable_content(content.as_bytes()).map(|_| r.path))
+    {
+        Ok(safe_path) => authorize_fs(&state, agent_id.as_deref(), ACTION_FS_WRITE, &root_id, format!("写入文件 {}", path), &[&path])
+            .await
+            .map(|_| safe_path),
+        Err(e) => Err(e),
+    };
+    match resolved {
         Ok(safe_path) => {
             // 确保父目录存在
             if let Some(parent) = safe_path.parent() {
@@ -526,16 +1071,33 @@ async fn fs_write_file(path: String, content: String) -> Result<FileSystemResult
                 }
             }
             
-            match fs::write(&safe_path, &content) {
+            // 写入前备份原文件，以便撤销
+            let entry = match state.journal.record(&root_id, "write", &[(&path, &safe_path)]) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    return Ok(FileSystemResult {
+                        success: false,
+                        data: None,
+                        error: Some(e),
+                    });
+                }
+            };
+            
+            match fs_edit::write_atomic(&safe_path, content.as_bytes()) {
                 Ok(_) => {
-                    info!("文件写入成功: {}", path);
+                    state.journal.complete(&entry.id);
+                    info!("文件写入成功: {} (日志 {})", path, entry.id);
                     Ok(FileSystemResult {
                         success: true,
-                        data: Some(format!("文件已写入: {} ({} 字符)", path, content.len())),
+                        data: Some(serde_json::json!({
+                            "message": format!("文件已写入: {} ({} 字符)", path, content.len()),
+                            "journal_id": entry.id,
+                        }).to_string()),
                         error: None,
                     })
                 }
                 Err(e) => {
+                    state.journal.discard(&entry.id);
                     error!("文件写入失败: {} - {}", path, e);
                     Ok(FileSystemResult {
                         success: false,
@@ -558,12 +1120,12 @@ async fn fs_write_file(path: String, content: String) -> Result<FileSystemResult
 
 /// 列出目录内容的Tauri命令
 #[tauri::command]
-async fn fs_list_directory(path: String) -> Result<FileSystemResult, String> {
+async fn fs_list_directory(state: tauri::State<'_, AppState>, root_id: String, path: String) -> Result<FileSystemResult, String> {
     info!("列出目录: {}", path);
     
-    match get_safe_path(&path) {
-        Ok(safe_path) => {
-            match fs::read_dir(&safe_path) {
+    match resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await {
+        Ok(resolved) => {
+            match fs::read_dir(&resolved.path) {
                 Ok(entries) => {
                     let mut items = Vec::new();
                     
@@ -573,6 +1135,12 @@ async fn fs_list_directory(path: String) -> Result<FileSystemResult, String> {
                             let name = entry.file_name().to_string_lossy().to_string();
                             
                             if let Ok(metadata) = metadata {
+                                // 跳过被工作区策略屏蔽的项目
+                                let relative = resolved.relative().join(&name);
+                                if resolved.policy.check_path(&relative, metadata.is_dir()).is_err() {
+                                    continue;
+                                }
+
                                 let item = DirectoryItem {
                                     name,
                                     item_type: if metadata.is_dir() { "directory".to_string() } else { "file".to_string() },
@@ -621,12 +1189,70 @@ async fn fs_list_directory(path: String) -> Result<FileSystemResult, String> {
     }
 }
 
+/// 递归列出目录树的Tauri命令
+///
+/// `options.format` 为 "text" 时返回缩进文本，否则返回JSON。
+#[tauri::command]
+async fn fs_tree(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    options: Option<TreeOptions>,
+) -> Result<FileSystemResult, String> {
+    info!("列出目录树: {}", path);
+
+    let resolved = match resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            error!("路径验证失败: {} - {}", path, e);
+            return Ok(FileSystemResult {
+                success: false,
+                data: None,
+                error: Some(e),
+            });
+        }
+    };
+
+    let options = options.unwrap_or_default();
+    let as_text = options.format == "text";
+    let tree = tokio::task::spawn_blocking(move || fs_tree::build_tree(&resolved, &options))
+        .await
+        .map_err(|e| format!("列出目录树任务失败: {}", e))?;
+
+    match tree {
+        Ok(tree) => {
+            info!("目录树列出成功: {} ({} 项)", path, tree.total_entries);
+            let data = if as_text {
+                fs_tree::render_text(&tree)
+            } else {
+                serde_json::to_string(&tree).map_err(|e| format!("序列化失败: {}", e))?
+            };
+            Ok(FileSystemResult {
+                success: true,
+                data: Some(data),
+                error: None,
+            })
+        }
+        Err(e) => Ok(FileSystemResult {
+            success: false,
+            data: None,
+            error: Some(e),
+        }),
+    }
+}
+
 /// 创建目录的Tauri命令
 #[tauri::command]
-async fn fs_create_directory(path: String) -> Result<FileSystemResult, String> {
+async fn fs_create_directory(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    agent_id: Option<String>,
+) -> Result<FileSystemResult, String> {
     info!("创建目录: {}", path);
     
-    match get_safe_path(&path) {
+    let resolved = resolve_authorized_path(&state, agent_id.as_deref(), ACTION_FS_WRITE, &root_id, &path, format!("创建目录 {}", path)).await;
+    match resolved.map(|r| r.path) {
         Ok(safe_path) => {
             match fs::create_dir_all(&safe_path) {
                 Ok(_) => {
@@ -660,10 +1286,16 @@ async fn fs_create_directory(path: String) -> Result<FileSystemResult, String> {
 
 /// 删除文件的Tauri命令
 #[tauri::command]
-async fn fs_delete_file(path: String) -> Result<FileSystemResult, String> {
+async fn fs_delete_file(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    agent_id: Option<String>,
+) -> Result<FileSystemResult, String> {
     info!("删除文件: {}", path);
     
-    match get_safe_path(&path) {
+    let resolved = resolve_authorized_path(&state, agent_id.as_deref(), ACTION_FS_DELETE, &root_id, &path, format!("删除文件 {}", path)).await;
+    match resolved.map(|r| r.path) {
         Ok(safe_path) => {
             if !safe_path.is_file() {
                 return Ok(FileSystemResult {
@@ -673,12 +1305,16 @@ async fn fs_delete_file(path: String) -> Result<FileSystemResult, String> {
                 });
             }
             
-            match fs::remove_file(&safe_path) {
-                Ok(_) => {
-                    info!("文件删除成功: {}", path);
+            // 移入回收站而不是直接删除，可通过操作日志撤销
+            match state.journal.record_delete(&root_id, "delete_file", &path, &safe_path) {
+                Ok(entry) => {
+                    info!("文件删除成功: {} (日志 {})", path, entry.id);
                     Ok(FileSystemResult {
                         success: true,
-                        data: Some(format!("文件已删除: {}", path)),
+                        data: Some(serde_json::json!({
+                            "message": format!("文件已删除: {}", path),
+                            "journal_id": entry.id,
+                        }).to_string()),
                         error: None,
                     })
                 }
@@ -705,10 +1341,16 @@ async fn fs_delete_file(path: String) -> Result<FileSystemResult, String> {
 
 /// 删除目录的Tauri命令
 #[tauri::command]
-async fn fs_delete_directory(path: String) -> Result<FileSystemResult, String> {
+async fn fs_delete_directory(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    agent_id: Option<String>,
+) -> Result<FileSystemResult, String> {
     info!("删除目录: {}", path);
     
-    match get_safe_path(&path) {
+    let resolved = resolve_authorized_path(&state, agent_id.as_deref(), ACTION_FS_DELETE, &root_id, &path, format!("删除目录 {}", path)).await;
+    match resolved.map(|r| r.path) {
         Ok(safe_path) => {
             if !safe_path.is_dir() {
                 return Ok(FileSystemResult {
@@ -718,12 +1360,16 @@ async fn fs_delete_directory(path: String) -> Result<FileSystemResult, String> {
                 });
             }
             
-            match fs::remove_dir_all(&safe_path) {
-                Ok(_) => {
-                    info!("目录删除成功: {}", path);
+            // 移入回收站而不是直接删除，可通过操作日志撤销
+            match state.journal.record_delete(&root_id, "delete_directory", &path, &safe_path) {
+                Ok(entry) => {
+                    info!("目录删除成功: {} (日志 {})", path, entry.id);
                     Ok(FileSystemResult {
                         success: true,
-                        data: Some(format!("目录已删除: {}", path)),
+                        data: Some(serde_json::json!({
+                            "message": format!("目录已删除: {}", path),
+                            "journal_id": entry.id,
+                        }).to_string()),
                         error: None,
                     })
                 }
@@ -750,11 +1396,17 @@ async fn fs_delete_directory(path: String) -> Result<FileSystemResult, String> {
 
 /// 移动/重命名文件或目录的Tauri命令
 #[tauri::command]
-async fn fs_move_item(source: String, target: String) -> Result<FileSystemResult, String> {
+async fn fs_move_item(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    source: String,
+    target: String,
+    agent_id: Option<String>,
+) -> Result<FileSystemResult, String> {
     info!("移动项目: {} -> {}", source, target);
     
-    let source_path = match get_safe_path(&source) {
-        Ok(path) => path,
+    let source_path = match resolve_workspace_path(&state, &root_id, &source, WorkspaceAccess::Write).await {
+        Ok(resolved) => resolved.path,
         Err(e) => return Ok(FileSystemResult {
             success: false,
             data: None,
@@ -762,8 +1414,8 @@ async fn fs_move_item(source: String, target: String) -> Result<FileSystemResult
         }),
     };
     
-    let target_path = match get_safe_path(&target) {
-        Ok(path) => path,
+    let target_path = match resolve_workspace_path(&state, &root_id, &target, WorkspaceAccess::Write).await {
+        Ok(resolved) => resolved.path,
         Err(e) => return Ok(FileSystemResult {
             success: false,
             data: None,
@@ -771,16 +1423,39 @@ async fn fs_move_item(source: String, target: String) -> Result<FileSystemResult
         }),
     };
     
+    let summary = format!("移动 {} 到 {}", source, target);
+    if let Err(e) = authorize_fs(&state, agent_id.as_deref(), ACTION_FS_MOVE, &root_id, summary, &[&source, &target]).await {
+        return Ok(FileSystemResult {
+            success: false,
+            data: None,
+            error: Some(e),
+        });
+    }
+    
+    let entry = match state.journal.record_move(&root_id, (&source, &source_path), (&target, &target_path)) {
+        Ok(entry) => entry,
+        Err(e) => return Ok(FileSystemResult {
+            success: false,
+            data: None,
+            error: Some(e),
+        }),
+    };
+    
     match fs::rename(&source_path, &target_path) {
         Ok(_) => {
-            info!("移动成功: {} -> {}", source, target);
+            state.journal.complete(&entry.id);
+            info!("移动成功: {} -> {} (日志 {})", source, target, entry.id);
             Ok(FileSystemResult {
                 success: true,
-                data: Some(format!("已移动: {} -> {}", source, target)),
+                data: Some(serde_json::json!({
+                    "message": format!("已移动: {} -> {}", source, target),
+                    "journal_id": entry.id,
+                }).to_string()),
                 error: None,
             })
         }
         Err(e) => {
+            state.journal.discard(&entry.id);
             error!("移动失败: {} -> {} - {}", source, target, e);
             Ok(FileSystemResult {
                 success: false,
@@ -793,11 +1468,17 @@ async fn fs_move_item(source: String, target: String) -> Result<FileSystemResult
 
 /// 复制文件的Tauri命令
 #[tauri::command]
-async fn fs_copy_file(source: String, target: String) -> Result<FileSystemResult, String> {
+async fn fs_copy_file(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    source: String,
+    target: String,
+    agent_id: Option<String>,
+) -> Result<FileSystemResult, String> {
     info!("复制文件: {} -> {}", source, target);
     
-    let source_path = match get_safe_path(&source) {
-        Ok(path) => path,
+    let source_path = match resolve_workspace_path(&state, &root_id, &source, WorkspaceAccess::Read).await {
+        Ok(resolved) => resolved.path,
         Err(e) => return Ok(FileSystemResult {
             success: false,
             data: None,
@@ -805,8 +1486,8 @@ async fn fs_copy_file(source: String, target: String) -> Result<FileSystemResult
         }),
     };
     
-    let target_path = match get_safe_path(&target) {
-        Ok(path) => path,
+    let target_path = match resolve_workspace_path(&state, &root_id, &target, WorkspaceAccess::Write).await {
+        Ok(resolved) => resolved.path,
         Err(e) => return Ok(FileSystemResult {
             success: false,
             data: None,
@@ -833,16 +1514,40 @@ async fn fs_copy_file(source: String, target: String) -> Result<FileSystemResult
         }
     }
     
+    let summary = format!("复制 {} 到 {}", source, target);
+    if let Err(e) = authorize_fs(&state, agent_id.as_deref(), ACTION_FS_WRITE, &root_id, summary, &[&source, &target]).await {
+        return Ok(FileSystemResult {
+            success: false,
+            data: None,
+            error: Some(e),
+        });
+    }
+    
+    // 目标文件可能被覆盖，复制前备份
+    let entry = match state.journal.record(&root_id, "copy", &[(&target, &target_path)]) {
+        Ok(entry) => entry,
+        Err(e) => return Ok(FileSystemResult {
+            success: false,
+            data: None,
+            error: Some(e),
+        }),
+    };
+    
     match fs::copy(&source_path, &target_path) {
         Ok(_) => {
-            info!("复制成功: {} -> {}", source, target);
+            state.journal.complete(&entry.id);
+            info!("复制成功: {} -> {} (日志 {})", source, target, entry.id);
             Ok(FileSystemResult {
                 success: true,
-                data: Some(format!("文件已复制: {} -> {}", source, target)),
+                data: Some(serde_json::json!({
+                    "message": format!("文件已复制: {} -> {}", source, target),
+                    "journal_id": entry.id,
+                }).to_string()),
                 error: None,
             })
         }
         Err(e) => {
+            state.journal.discard(&entry.id);
             error!("复制失败: {} -> {} - {}", source, target, e);
             Ok(FileSystemResult {
                 success: false,
@@ -853,12 +1558,132 @@ async fn fs_copy_file(source: String, target: String) -> Result<FileSystemResult
     }
 }
 
+/// 监听工作区路径变化的Tauri命令，成功时返回监听ID
+///
+/// 变化去抖后通过 `fs-change` 事件发送，包含 created、modified、removed、renamed 四种类型。
+#[tauri::command]
+async fn fs_watch(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    debounce_ms: Option<u64>,
+) -> Result<FileSystemResult, String> {
+    info!("监听路径: {}", path);
+    
+    let result = match resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await {
+        Ok(resolved) => state.watch_manager.watch(&root_id, &resolved, debounce_ms),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(watch_id) => Ok(FileSystemResult {
+            success: true,
+            data: Some(watch_id),
+            error: None,
+        }),
+        Err(e) => {
+            error!("监听路径失败: {} - {}", path, e);
+            Ok(FileSystemResult {
+                success: false,
+                data: None,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+/// 停止监听的Tauri命令
+#[tauri::command]
+async fn fs_unwatch(state: tauri::State<'_, AppState>, watch_id: String) -> Result<bool, String> {
+    Ok(state.watch_manager.unwatch(&watch_id))
+}
+
+#[tauri::command]
+async fn fs_list_watches(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    serde_json::to_string(&state.watch_manager.list()).map_err(|e| e.to_string())
+}
+
+/// 列出文件操作日志的Tauri命令，按时间倒序
+#[tauri::command]
+async fn fs_journal_list(state: tauri::State<'_, AppState>, root_id: Option<String>, limit: Option<usize>) -> Result<String, String> {
+    let entries = state.journal.list(root_id.as_deref(), limit.unwrap_or(50))?;
+    serde_json::to_string(&entries).map_err(|e| format!("序列化失败: {}", e))
+}
+
+/// 撤销文件操作的Tauri命令
+#[tauri::command]
+async fn fs_journal_undo(
+    state: tauri::State<'_, AppState>,
+    entry_id: String,
+    agent_id: Option<String>,
+) -> Result<FileSystemResult, String> {
+    info!("撤销文件操作: {}", entry_id);
+
+    // 按当前的工作区配置重新解析路径，只读模式或策略变化后不能再撤销
+    let result = async {
+        let entry = state.journal.get(&entry_id)?;
+        let root = load_workspace_root(&state, &entry.root_id).await?;
+
+        // 撤销会删除操作中新建的路径，此时按删除审批，否则按写入审批
+        let action = if entry.snapshots.iter().any(|s| !s.existed) { ACTION_FS_DELETE } else { ACTION_FS_WRITE };
+        let paths: Vec<&str> = entry.snapshots.iter().map(|s| s.display_path.as_str()).collect();
+        let summary = format!("撤销文件操作 {} ({})", entry.operation, paths.join(", "));
+        authorize_fs(&state, agent_id.as_deref(), action, &entry.root_id, summary, &paths).await?;
+
+        state.journal.undo(&entry_id, |snapshot| {
+            workspace::resolve(&root, &snapshot.display_path, WorkspaceAccess::Write).map(|r| r.path)
+        })
+    }.await;
+
+    match result {
+        Ok(entry) => Ok(FileSystemResult {
+            success: true,
+            data: Some(serde_json::to_string(&entry).map_err(|e| format!("序列化失败: {}", e))?),
+            error: None,
+        }),
+        Err(e) => {
+            error!("撤销文件操作失败: {} - {}", entry_id, e);
+            Ok(FileSystemResult {
+                success: false,
+                data: None,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+/// 清理操作日志和回收站的Tauri命令
+///
+/// 指定 `entry_id` 时只删除该条目；否则保存新的保留策略（如有）并按策略清理。
+#[tauri::command]
+async fn fs_journal_purge(
+    state: tauri::State<'_, AppState>,
+    entry_id: Option<String>,
+    retention: Option<RetentionPolicy>,
+    agent_id: Option<String>,
+) -> Result<usize, String> {
+    // 清理会永久删除回收站中的备份，按删除审批；按策略清理涉及所有工作区
+    let removed = match entry_id {
+        Some(id) => {
+            let entry = state.journal.get(&id)?;
+            let summary = format!("永久删除文件操作日志 {} ({})", entry.id, entry.operation);
+            authorize_fs(&state, agent_id.as_deref(), ACTION_FS_DELETE, &entry.root_id, summary, &[]).await?;
+            state.journal.purge_entry(&id)?
+        }
+        None => {
+            authorize_fs(&state, agent_id.as_deref(), ACTION_FS_DELETE, "*", "按保留策略清理文件操作日志".to_string(), &[]).await?;
+            state.journal.purge(retention)?
+        }
+    };
+    info!("已清理 {} 条文件操作日志", removed);
+    Ok(removed)
+}
+
 /// 获取文件或目录信息的Tauri命令
 #[tauri::command]
-async fn fs_get_item_info(path: String) -> Result<FileSystemResult, String> {
+async fn fs_get_item_info(state: tauri::State<'_, AppState>, root_id: String, path: String) -> Result<FileSystemResult, String> {
     info!("获取项目信息: {}", path);
     
-    match get_safe_path(&path) {
+    match resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await.map(|r| r.path) {
         Ok(safe_path) => {
             match fs::metadata(&safe_path) {
                 Ok(metadata) => {
@@ -928,15 +1753,21 @@ async fn fs_get_item_info(path: String) -> Result<FileSystemResult, String> {
 
 /// 搜索文件的Tauri命令
 #[tauri::command]
-async fn fs_search_files(path: String, pattern: String, options: SearchOptions) -> Result<FileSystemResult, String> {
+async fn fs_search_files(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    pattern: String,
+    options: SearchOptions,
+) -> Result<FileSystemResult, String> {
     info!("搜索文件: {} 模式: {}", path, pattern);
     
     let recursive = options.recursive.unwrap_or(true);
     let case_sensitive = options.case_sensitive.unwrap_or(false);
     let file_only = options.file_only.unwrap_or(true);
     
-    match get_safe_path(&path) {
-        Ok(safe_path) => {
+    match resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await {
+        Ok(resolved) => {
             let regex = match if case_sensitive {
                 Regex::new(&pattern)
             } else {
@@ -959,6 +1790,7 @@ async fn fs_search_files(path: String, pattern: String, options: SearchOptions)
                 file_only: bool,
                 results: &mut Vec<String>,
                 base_path: &Path,
+                policy: &PolicyEngine,
             ) -> io::Result<()> {
                 if let Ok(entries) = fs::read_dir(dir) {
                     for entry in entries {
@@ -967,6 +1799,12 @@ async fn fs_search_files(path: String, pattern: String, options: SearchOptions)
                             let name = entry.file_name().to_string_lossy().to_string();
                             
                             if let Ok(metadata) = entry.metadata() {
+                                // 跳过被工作区策略屏蔽的项目，屏蔽的目录不再递归
+                                let relative = path.strip_prefix(base_path).unwrap_or(&path);
+                                if policy.check_path(relative, metadata.is_dir()).is_err() {
+                                    continue;
+                                }
+
                                 if metadata.is_file() && regex.is_match(&name) {
                                     if let Ok(relative_path) = path.strip_prefix(base_path) {
                                         results.push(relative_path.to_string_lossy().to_string());
@@ -979,7 +1817,7 @@ async fn fs_search_files(path: String, pattern: String, options: SearchOptions)
                                     }
                                     
                                     if recursive {
-                                        let _ = search_dir(&path, regex, recursive, file_only, results, base_path);
+                                        let _ = search_dir(&path, regex, recursive, file_only, results, base_path, policy);
                                     }
                                 }
                             }
@@ -989,10 +1827,8 @@ async fn fs_search_files(path: String, pattern: String, options: SearchOptions)
                 Ok(())
             }
             
-            let base_path = std::env::current_dir()
-                .map_err(|e| format!("无法获取当前目录: {}", e))?;
-            
-            if let Err(e) = search_dir(&safe_path, &regex, recursive, file_only, &mut results, &base_path) {
+            // 结果路径相对于工作区根目录
+            if let Err(e) = search_dir(&resolved.path, &regex, recursive, file_only, &mut results, &resolved.root, &resolved.policy) {
                 return Ok(FileSystemResult {
                     success: false,
                     data: None,
@@ -1021,12 +1857,62 @@ async fn fs_search_files(path: String, pattern: String, options: SearchOptions)
     }
 }
 
+/// 搜索文件内容的Tauri命令
+#[tauri::command]
+async fn fs_grep(
+    state: tauri::State<'_, AppState>,
+    root_id: String,
+    path: String,
+    pattern: String,
+    options: Option<GrepOptions>,
+) -> Result<FileSystemResult, String> {
+    info!("搜索文件内容: {} 模式: {}", path, pattern);
+
+    let resolved = match resolve_workspace_path(&state, &root_id, &path, WorkspaceAccess::Read).await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            error!("路径验证失败: {} - {}", path, e);
+            return Ok(FileSystemResult {
+                success: false,
+                data: None,
+                error: Some(e),
+            });
+        }
+    };
+
+    // 扫描文件是阻塞IO，放到阻塞线程池执行
+    let options = options.unwrap_or_default();
+    let search = tokio::task::spawn_blocking(move || fs_grep::grep(&resolved, &pattern, &options))
+        .await
+        .map_err(|e| format!("搜索任务失败: {}", e))?;
+
+    match search {
+        Ok(result) => {
+            info!("内容搜索完成: {} 扫描 {} 个文件，找到 {} 处匹配", path, result.files_scanned, result.matches.len());
+            let data = serde_json::to_string(&result)
+                .map_err(|e| format!("序列化失败: {}", e))?;
+            Ok(FileSystemResult {
+                success: true,
+                data: Some(data),
+                error: None,
+            })
+        }
+        Err(e) => Ok(FileSystemResult {
+            success: false,
+            data: None,
+            error: Some(e),
+        }),
+    }
+}
+
 /// 测试代理连接的Tauri命令
 #[tauri::command]
 async fn test_proxy_connection(proxy_config: ProxyConfig) -> Result<String, String> {
     info!("测试代理连接: {}:{} (类型: {})", proxy_config.host, proxy_config.port, proxy_config.proxy_type);
 
-    let client = create_http_client(Some(&proxy_config))
+    // 不使用缓存的客户端，确保按待测试的配置重新建立连接
+    let options = ConnectionSettings::default().client_options(false);
+    let client = http_pool::build_client(Some(&proxy_config), None, &options, &ClientGuards::default())
         .map_err(|e| format!("创建代理客户端失败: {}", e))?;
 
     // 使用Google来测试代理（验证是否能访问被墙的网站）
@@ -1127,14 +2013,24 @@ fn init_tracing() {
 // 全局状态管理
 struct AppState {
     storage_service: Arc<Mutex<StorageService>>,
+    mcp_manager: Arc<McpClientManager>,
+    stream_registry: Arc<StreamRegistry>,
+    http_pool: Arc<HttpClientPool>,
+    http_allowlist: Arc<HttpAllowlist>,
+    journal: Arc<FsJournal>,
+    permission_broker: Arc<PermissionBroker>,
+    watch_manager: Arc<WatchManager>,
 }
 
 // 存储相关的Tauri命令
 
+/// 获取脱敏后的提供商列表，api_key只保留末尾几位
+///
+/// 前端不再取得明文密钥，发送请求时由 `send_http_request` 按提供商ID替换脱敏值。
 #[tauri::command]
-async fn storage_get_providers(state: tauri::State<'_, AppState>) -> Result<String, String> {
+async fn storage_get_providers_redacted(state: tauri::State<'_, AppState>) -> Result<String, String> {
     let storage = state.storage_service.lock().await;
-    match storage.get_providers().await {
+    match storage.get_providers_redacted().await {
         Ok(providers) => Ok(serde_json::to_string(&providers).map_err(|e| e.to_string())?),
         Err(e) => Err(e.to_string()),
     }
@@ -1143,14 +2039,31 @@ async fn storage_get_providers(state: tauri::State<'_, AppState>) -> Result<Stri
 #[tauri::command]
 async fn storage_save_provider(state: tauri::State<'_, AppState>, provider_json: String) -> Result<(), String> {
     let provider: database::AIProvider = serde_json::from_str(&provider_json).map_err(|e| e.to_string())?;
+
+    // 新的提供商地址会加入HTTP允许列表，需要用户确认
+    let current = current_allowlist_info(&state).await;
+    let added: Vec<String> = http_allowlist::provider_hosts(std::slice::from_ref(&provider))
+        .into_iter()
+        .filter(|host| !current.provider_hosts.contains(host))
+        .collect();
+    if !added.is_empty() {
+        let summary = format!("允许HTTP请求访问提供商 {} 的地址: {}", provider.name, added.join(", "));
+        let details = serde_json::json!({ "provider_id": provider.id, "hosts": added });
+        confirm_allowlist_change(&state, "providers", &added.join(","), summary, details).await?;
+    }
+
     let storage = state.storage_service.lock().await;
-    storage.save_provider(&provider).await.map_err(|e| e.to_string())
+    storage.save_provider(&provider).await.map_err(|e| e.to_string())?;
+    state.http_allowlist.invalidate();
+    Ok(())
 }
 
 #[tauri::command]
 async fn storage_delete_provider(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
     let storage = state.storage_service.lock().await;
-    storage.delete_provider(&id).await.map_err(|e| e.to_string())
+    storage.delete_provider(&id).await.map_err(|e| e.to_string())?;
+    state.http_allowlist.invalidate();
+    Ok(())
 }
 
 #[tauri::command]
@@ -1166,8 +2079,13 @@ async fn storage_get_proxy_settings(state: tauri::State<'_, AppState>) -> Result
 #[tauri::command]
 async fn storage_save_proxy_settings(state: tauri::State<'_, AppState>, settings_json: String) -> Result<(), String> {
     let settings: database::ProxySettings = serde_json::from_str(&settings_json).map_err(|e| e.to_string())?;
+    if let Some(routing) = settings.routing.as_deref().filter(|r| !r.trim().is_empty()) {
+        serde_json::from_str::<ProxyRouting>(routing).map_err(|e| format!("代理路由配置无效: {}", e))?;
+    }
     let storage = state.storage_service.lock().await;
-    storage.save_proxy_settings(&settings).await.map_err(|e| e.to_string())
+    storage.save_proxy_settings(&settings).await.map_err(|e| e.to_string())?;
+    state.http_pool.invalidate();
+    Ok(())
 }
 
 #[tauri::command]
@@ -1178,8 +2096,28 @@ async fn storage_get_setting(state: tauri::State<'_, AppState>, key: String) ->
 
 #[tauri::command]
 async fn storage_save_setting(state: tauri::State<'_, AppState>, key: String, value: String) -> Result<(), String> {
+    // 放宽HTTP允许列表或开启局域网访问需要用户确认，不能只靠写入设置
+    if key == SETTING_ALLOWED_DOMAINS {
+        let domains: Vec<String> = serde_json::from_str(&value).map_err(|e| format!("允许域名设置格式无效: {}", e))?;
+        http_allowlist::validate_user_domains(&domains)?;
+        let current = current_allowlist_info(&state).await;
+        let added: Vec<&String> = domains.iter().filter(|domain| !current.user_domains.contains(domain)).collect();
+        if !added.is_empty() {
+            let added = added.iter().map(|domain| domain.trim()).collect::<Vec<_>>().join(", ");
+            let details = serde_json::json!({ "domains": domains });
+            confirm_allowlist_change(&state, &key, &added, format!("允许HTTP请求访问: {}", added), details).await?;
+        }
+    }
+    if key == SETTING_ALLOW_PRIVATE_NETWORK && value.trim() == "true" && !current_allowlist_info(&state).await.allow_private_network {
+        let summary = "允许HTTP请求访问本机和局域网地址".to_string();
+        confirm_allowlist_change(&state, &key, "private_network", summary, serde_json::json!({})).await?;
+    }
     let storage = state.storage_service.lock().await;
-    storage.save_setting(&key, &value).await.map_err(|e| e.to_string())
+    storage.save_setting(&key, &value).await.map_err(|e| e.to_string())?;
+    if key == SETTING_ALLOWED_DOMAINS || key == SETTING_ALLOW_PRIVATE_NETWORK {
+        state.http_allowlist.invalidate();
+    }
+    Ok(())
 }
 
 #[tauri::command]
@@ -1227,6 +2165,56 @@ async fn storage_delete_agent(state: tauri::State<'_, AppState>, id: String) ->
     storage.delete_agent(&id).await.map_err(|e| e.to_string())
 }
 
+// Agent会话相关命令
+#[tauri::command]
+async fn storage_get_agent_sessions(state: tauri::State<'_, AppState>, agent_id: Option<String>) -> Result<String, String> {
+    let storage = state.storage_service.lock().await;
+    let result = match agent_id {
+        Some(agent_id) => storage.get_agent_sessions_by_agent(&agent_id).await,
+        None => storage.get_agent_sessions().await,
+    };
+    match result {
+        Ok(sessions) => Ok(serde_json::to_string(&sessions).map_err(|e| e.to_string())?),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn storage_save_agent_session(state: tauri::State<'_, AppState>, session_json: String) -> Result<(), String> {
+    let session: database::AgentSession = serde_json::from_str(&session_json).map_err(|e| e.to_string())?;
+    let storage = state.storage_service.lock().await;
+    storage.save_agent_session(&session).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn storage_delete_agent_session(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    let storage = state.storage_service.lock().await;
+    storage.delete_agent_session(&id).await.map_err(|e| e.to_string())
+}
+
+// Agent会话消息相关命令
+#[tauri::command]
+async fn storage_get_messages(state: tauri::State<'_, AppState>, session_id: String) -> Result<String, String> {
+    let storage = state.storage_service.lock().await;
+    match storage.get_messages(&session_id).await {
+        Ok(messages) => Ok(serde_json::to_string(&messages).map_err(|e| e.to_string())?),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn storage_save_message(state: tauri::State<'_, AppState>, message_json: String) -> Result<(), String> {
+    let message: database::Message = serde_json::from_str(&message_json).map_err(|e| e.to_string())?;
+    let storage = state.storage_service.lock().await;
+    storage.save_message(&message).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn storage_delete_message(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    let storage = state.storage_service.lock().await;
+    storage.delete_message(&id).await.map_err(|e| e.to_string())
+}
+
 // Scene相关命令
 #[tauri::command]
 async fn storage_get_scenes(state: tauri::State<'_, AppState>) -> Result<String, String> {
@@ -1250,6 +2238,79 @@ async fn storage_delete_scene(state: tauri::State<'_, AppState>, id: String) ->
     storage.delete_scene(&id).await.map_err(|e| e.to_string())
 }
 
+// Scene会话相关命令
+#[tauri::command]
+async fn storage_get_scene_sessions(state: tauri::State<'_, AppState>, scene_id: Option<String>) -> Result<String, String> {
+    let storage = state.storage_service.lock().await;
+    let result = match scene_id {
+        Some(scene_id) => storage.get_scene_sessions_by_scene(&scene_id).await,
+        None => storage.get_scene_sessions().await,
+    };
+    match result {
+        Ok(sessions) => Ok(serde_json::to_string(&sessions).map_err(|e| e.to_string())?),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn storage_save_scene_session(state: tauri::State<'_, AppState>, session_json: String) -> Result<(), String> {
+    let session: database::SceneSession = serde_json::from_str(&session_json).map_err(|e| e.to_string())?;
+    let storage = state.storage_service.lock().await;
+    storage.save_scene_session(&session).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn storage_set_scene_session_active(state: tauri::State<'_, AppState>, id: String, is_active: bool) -> Result<(), String> {
+    let storage = state.storage_service.lock().await;
+    storage.set_scene_session_active(&id, is_active).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn storage_delete_scene_session(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    let storage = state.storage_service.lock().await;
+    storage.delete_scene_session(&id).await.map_err(|e| e.to_string())
+}
+
+// Scene会话消息相关命令
+#[tauri::command]
+async fn storage_get_scene_messages(state: tauri::State<'_, AppState>, session_id: String) -> Result<String, String> {
+    let storage = state.storage_service.lock().await;
+    match storage.get_scene_messages(&session_id).await {
+        Ok(messages) => Ok(serde_json::to_string(&messages).map_err(|e| e.to_string())?),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn storage_save_scene_message(state: tauri::State<'_, AppState>, message_json: String) -> Result<(), String> {
+    let message: database::SceneMessage = serde_json::from_str(&message_json).map_err(|e| e.to_string())?;
+    let storage = state.storage_service.lock().await;
+    storage.save_scene_message(&message).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn storage_update_scene_message(state: tauri::State<'_, AppState>, id: String, content: String) -> Result<(), String> {
+    let storage = state.storage_service.lock().await;
+    storage.update_scene_message_content(&id, &content).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn storage_delete_scene_message(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    let storage = state.storage_service.lock().await;
+    storage.delete_scene_message(&id).await.map_err(|e| e.to_string())
+}
+
+// 消息全文搜索命令
+#[tauri::command]
+async fn storage_search_messages(state: tauri::State<'_, AppState>, query: String, limit: Option<i64>) -> Result<String, String> {
+    let limit = limit.unwrap_or(50).clamp(1, 200);
+    let storage = state.storage_service.lock().await;
+    match storage.search_messages(&query, limit).await {
+        Ok(hits) => Ok(serde_json::to_string(&hits).map_err(|e| e.to_string())?),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 // MCP服务器配置相关命令
 #[tauri::command]
 async fn storage_get_mcp_configs(state: tauri::State<'_, AppState>) -> Result<String, String> {
@@ -1273,6 +2334,153 @@ async fn storage_delete_mcp_config(state: tauri::State<'_, AppState>, id: String
     storage.delete_mcp_server_config(&id).await.map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+async fn storage_get_workspace_roots(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let storage = state.storage_service.lock().await;
+    match storage.get_workspace_roots().await {
+        Ok(roots) => Ok(serde_json::to_string(&roots).map_err(|e| e.to_string())?),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn storage_save_workspace_root(state: tauri::State<'_, AppState>, root_json: String) -> Result<String, String> {
+    let mut root: database::WorkspaceRoot = serde_json::from_str(&root_json).map_err(|e| e.to_string())?;
+    // 保存规范化后的路径，避免之后通过符号链接改变工作区位置
+    root.path = workspace::normalize_root(&root)?
+        .to_string_lossy()
+        .to_string();
+
+    // 新目录和放宽为读写都会扩大智能体可访问的范围，需要用户审批；改名或改为只读则不需要
+    let existing = {
+        let storage = state.storage_service.lock().await;
+        storage.get_workspace_root(&root.id).await.map_err(|e| e.to_string())?
+    };
+    let new_path = existing.as_ref().map_or(true, |e| e.path != root.path);
+    let widened = root.mode == workspace::MODE_READ_WRITE
+        && (new_path || existing.as_ref().is_some_and(|e| e.mode != workspace::MODE_READ_WRITE));
+    let action = if widened {
+        Some((ACTION_WORKSPACE_WRITE, format!("允许读写工作区 {}", root.path)))
+    } else if new_path {
+        Some((ACTION_WORKSPACE_ADD, format!("添加只读工作区 {}", root.path)))
+    } else {
+        None
+    };
+    if let Some((action, summary)) = action {
+        let details = serde_json::json!({ "name": root.name, "path": root.path, "mode": root.mode });
+        authorize(&state, PermissionRequest::new(SUBJECT_SETTINGS, "workspace_roots", action, &root.path, summary, details)).await?;
+    }
+
+    let storage = state.storage_service.lock().await;
+    storage.save_workspace_root(&root).await.map_err(|e| e.to_string())?;
+    serde_json::to_string(&root).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn storage_delete_workspace_root(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    let storage = state.storage_service.lock().await;
+    storage.delete_workspace_root(&id).await.map_err(|e| e.to_string())
+}
+
+// 审批相关命令
+
+/// 答复审批请求，`decision` 为 "allow_once"、"allow_always" 或 "deny"
+#[tauri::command]
+async fn permission_respond(state: tauri::State<'_, AppState>, request_id: String, decision: PermissionDecision) -> Result<bool, String> {
+    Ok(state.permission_broker.respond(&request_id, decision))
+}
+
+#[tauri::command]
+async fn permission_list_pending(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    serde_json::to_string(&state.permission_broker.pending()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn storage_get_permission_grants(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let storage = state.storage_service.lock().await;
+    let grants = storage.get_permission_grants().await.map_err(|e| e.to_string())?;
+    serde_json::to_string(&grants).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn storage_delete_permission_grant(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    let storage = state.storage_service.lock().await;
+    storage.delete_permission_grant(&id).await.map_err(|e| e.to_string())
+}
+
+// 外部MCP服务器相关命令
+
+/// 启动已配置的外部MCP服务器并完成握手，状态变化通过 `mcp-status` 事件通知前端
+#[tauri::command]
+async fn mcp_start_server(state: tauri::State<'_, AppState>, server_id: String) -> Result<String, String> {
+    let configs = {
+        let storage = state.storage_service.lock().await;
+        storage.get_mcp_server_configs().await.map_err(|e| e.to_string())?
+    };
+    let config = configs.iter().find(|c| c.id == server_id)
+        .ok_or_else(|| format!("未找到MCP服务器配置: {}", server_id))?;
+    let spec = McpServerSpec::from_config(config)?;
+
+    let status = state.mcp_manager.start(&server_id, spec).await?;
+    serde_json::to_string(&status).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn mcp_stop_server(state: tauri::State<'_, AppState>, server_id: String) -> Result<bool, String> {
+    Ok(state.mcp_manager.stop(&server_id).await)
+}
+
+#[tauri::command]
+async fn mcp_get_server_statuses(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let statuses = state.mcp_manager.statuses().await;
+    serde_json::to_string(&statuses).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn mcp_list_tools(state: tauri::State<'_, AppState>, server_id: String) -> Result<String, String> {
+    let tools = state.mcp_manager.list_tools(&server_id).await?;
+    serde_json::to_string(&tools).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn mcp_list_resources(state: tauri::State<'_, AppState>, server_id: String) -> Result<String, String> {
+    let resources = state.mcp_manager.list_resources(&server_id).await?;
+    serde_json::to_string(&resources).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn mcp_list_prompts(state: tauri::State<'_, AppState>, server_id: String) -> Result<String, String> {
+    let prompts = state.mcp_manager.list_prompts(&server_id).await?;
+    serde_json::to_string(&prompts).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn mcp_call_tool(state: tauri::State<'_, AppState>, server_id: String, name: String, arguments: serde_json::Value) -> Result<String, String> {
+    let configs = {
+        let storage = state.storage_service.lock().await;
+        storage.get_mcp_server_configs().await.map_err(|e| e.to_string())?
+    };
+    let config = configs.iter().find(|c| c.id == server_id)
+        .ok_or_else(|| format!("未找到MCP服务器配置: {}", server_id))?;
+
+    // 先按服务器配置的权限检查，再请求用户审批
+    McpPermissions::from_json(&config.permissions)?
+        .check_tool_call(&config.name, &name, &arguments)
+        .inspect_err(|e| warn!("MCP工具调用被拒绝: {}", e))?;
+    let request = PermissionRequest::new(
+        SUBJECT_MCP_SERVER,
+        &server_id,
+        ACTION_MCP_TOOL,
+        &name,
+        format!("调用 {} 的工具 {}", config.name, name),
+        serde_json::json!({ "arguments": arguments }),
+    );
+    authorize(&state, request).await?;
+
+    let result = state.mcp_manager.call_tool(&server_id, &name, arguments).await?;
+    serde_json::to_string(&result).map_err(|e| e.to_string())
+}
+
 /// 应用程序入口点
 /// 
 /// 此函数是Tauri应用的主入口点，负责初始化日志系统、
@@ -1298,9 +2506,47 @@ pub fn run() {
                 StorageService::new(Arc::new(db))
             });
             
+            // MCP服务器状态变化转发为前端事件
+            let app_handle = app.app_handle().clone();
+            let mcp_manager = McpClientManager::new(move |status| {
+                let _ = app_handle.emit("mcp-status", status);
+            });
+            
+            // 审批请求通过 `permission-request` 事件发给前端，5分钟未答复按拒绝处理
+            let app_handle = app.app_handle().clone();
+            let permission_broker = PermissionBroker::new(Duration::from_secs(300), move |request| {
+                let _ = app_handle.emit("permission-request", request);
+            });
+            
+            // 工作区文件变化去抖后通过 `fs-change` 事件通知前端
+            let app_handle = app.app_handle().clone();
+            let watch_manager = WatchManager::new(move |event| {
+                let _ = app_handle.emit("fs-change", event);
+            });
+            
+            // HTTP客户端跟随重定向前同样检查允许列表，连接前过滤域名解析出的内网地址
+            let http_allowlist = Arc::new(HttpAllowlist::default());
+            let http_pool = HttpClientPool::new(
+                {
+                    let http_allowlist = http_allowlist.clone();
+                    move |url| http_allowlist.check_redirect(url).map_err(|e| e.to_string())
+                },
+                {
+                    let http_allowlist = http_allowlist.clone();
+                    move |ip| http_allowlist.allows_address(ip)
+                },
+            );
+            
             // 创建应用状态
             let app_state = AppState {
                 storage_service: Arc::new(Mutex::new(storage_service)),
+                mcp_manager: Arc::new(mcp_manager),
+                stream_registry: Arc::new(StreamRegistry::default()),
+                http_pool: Arc::new(http_pool),
+                http_allowlist,
+                journal: Arc::new(FsJournal::new(Database::data_dir().join("journal"))),
+                permission_broker: Arc::new(permission_broker),
+                watch_manager: Arc::new(watch_manager),
             };
             
             // 管理应用状态
@@ -1317,10 +2563,20 @@ pub fn run() {
             log_debug,
             send_http_request,
             send_stream_request,
+            cancel_stream,
+            list_active_streams,
+            http_pool_metrics,
+            http_get_allowlist,
             test_proxy_connection,
             fs_read_file,
             fs_write_file,
+            fs_read_file_range,
+            fs_edit_file,
+            fs_read_file_binary,
+            fs_write_file_binary,
+            fs_extract_text,
             fs_list_directory,
+            fs_tree,
             fs_create_directory,
             fs_delete_file,
             fs_delete_directory,
@@ -1328,7 +2584,14 @@ pub fn run() {
             fs_copy_file,
             fs_get_item_info,
             fs_search_files,
-            storage_get_providers,
+            fs_grep,
+            fs_watch,
+            fs_unwatch,
+            fs_list_watches,
+            fs_journal_list,
+            fs_journal_undo,
+            fs_journal_purge,
+            storage_get_providers_redacted,
             storage_save_provider,
             storage_delete_provider,
             storage_get_proxy_settings,
@@ -1341,12 +2604,41 @@ pub fn run() {
             storage_get_agents,
             storage_save_agent,
             storage_delete_agent,
+            storage_get_agent_sessions,
+            storage_save_agent_session,
+            storage_delete_agent_session,
+            storage_get_messages,
+            storage_save_message,
+            storage_delete_message,
             storage_get_scenes,
             storage_save_scene,
             storage_delete_scene,
+            storage_get_scene_sessions,
+            storage_save_scene_session,
+            storage_set_scene_session_active,
+            storage_delete_scene_session,
+            storage_get_scene_messages,
+            storage_save_scene_message,
+            storage_update_scene_message,
+            storage_delete_scene_message,
+            storage_search_messages,
             storage_get_mcp_configs,
             storage_save_mcp_config,
-            storage_delete_mcp_config
+            storage_delete_mcp_config,
+            storage_get_workspace_roots,
+            storage_save_workspace_root,
+            storage_delete_workspace_root,
+            mcp_start_server,
+            mcp_stop_server,
+            mcp_get_server_statuses,
+            mcp_list_tools,
+            mcp_list_resources,
+            mcp_list_prompts,
+            mcp_call_tool,
+            permission_respond,
+            permission_list_pending,
+            storage_get_permission_grants,
+            storage_delete_permission_grant
         ])
         // 运行应用
         .run(tauri::generate_context!())
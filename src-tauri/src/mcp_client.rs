@@ -0,0 +1,558 @@
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{oneshot, Mutex};
+use tracing::{debug, error, info, warn};
+
+use crate::database::MCPServerConfig;
+
+/// MCP协议版本
+const PROTOCOL_VERSION: &str = "2024-11-05";
+/// 单个JSON-RPC请求的超时时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// 进程意外退出后的最大自动重启次数
+const MAX_RESTARTS: u32 = 3;
+/// 连续运行超过该时长后视为健康，重置重启计数
+const HEALTHY_UPTIME: Duration = Duration::from_secs(60);
+
+type PendingMap = std::sync::Mutex<HashMap<u64, oneshot::Sender<Result<Value, String>>>>;
+type StatusCallback = Arc<dyn Fn(McpServerStatus) + Send + Sync>;
+
+/// 外部MCP服务器的启动参数
+#[derive(Debug, Clone)]
+pub struct McpServerSpec {
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+}
+
+impl McpServerSpec {
+    /// 从存储的服务器配置解析启动参数，仅支持 `server_type = "external"`
+    pub fn from_config(config: &MCPServerConfig) -> Result<Self, String> {
+        if config.server_type != "external" {
+            return Err(format!("服务器 {} 不是外部服务器", config.name));
+        }
+        let command = config.command.clone()
+            .filter(|c| !c.trim().is_empty())
+            .ok_or_else(|| format!("服务器 {} 未配置启动命令", config.name))?;
+        let args = match &config.args {
+            Some(args) if !args.trim().is_empty() => serde_json::from_str(args)
+                .map_err(|e| format!("args 格式无效: {}", e))?,
+            _ => Vec::new(),
+        };
+        let env = match &config.env {
+            Some(env) if !env.trim().is_empty() => serde_json::from_str(env)
+                .map_err(|e| format!("env 格式无效: {}", e))?,
+            _ => HashMap::new(),
+        };
+        Ok(Self { command, args, env })
+    }
+}
+
+/// 服务器状态，同时作为 `mcp-status` 事件的负载
+#[derive(Debug, Clone, Serialize)]
+pub struct McpServerStatus {
+    pub server_id: String,
+    pub status: String, // "starting", "running", "restarting", "stopped", "crashed", "error"
+    pub message: Option<String>,
+    pub restart_count: u32,
+    pub server_info: Option<Value>,
+}
+
+/// 与单个MCP服务器进程的JSON-RPC连接
+struct McpConnection {
+    stdin: Mutex<ChildStdin>,
+    child: Mutex<Child>,
+    pending: Arc<PendingMap>,
+    next_id: AtomicU64,
+}
+
+impl McpConnection {
+    async fn send(&self, message: &Value) -> Result<(), String> {
+        let mut line = serde_json::to_string(message).map_err(|e| e.to_string())?;
+        line.push('\n');
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(line.as_bytes()).await.map_err(|e| format!("写入MCP服务器失败: {}", e))?;
+        stdin.flush().await.map_err(|e| format!("写入MCP服务器失败: {}", e))
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(e) = self.send(&message).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err("MCP服务器连接已关闭".to_string()),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(format!("MCP请求超时: {}", method))
+            }
+        }
+    }
+
+    async fn notify(&self, method: &str, params: Value) -> Result<(), String> {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params })).await
+    }
+
+    /// 处理服务器输出的一行JSON-RPC消息
+    async fn handle_line(&self, line: &str) {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(_) => {
+                debug!("忽略非JSON输出: {}", line);
+                return;
+            }
+        };
+
+        if let Some(method) = message.get("method").and_then(|m| m.as_str()) {
+            match message.get("id") {
+                // 服务器发起的请求，仅支持ping
+                Some(id) => {
+                    let reply = if method == "ping" {
+                        json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                    } else {
+                        json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": "Method not found" } })
+                    };
+                    if let Err(e) = self.send(&reply).await {
+                        warn!("回复MCP服务器请求失败: {}", e);
+                    }
+                }
+                None => debug!("收到MCP通知: {}", method),
+            }
+            return;
+        }
+
+        let Some(id) = message.get("id").and_then(|id| id.as_u64()) else {
+            return;
+        };
+        let Some(tx) = self.pending.lock().unwrap().remove(&id) else {
+            return;
+        };
+        let result = match message.get("error") {
+            Some(err) => Err(err.get("message").and_then(|m| m.as_str()).unwrap_or("未知错误").to_string()),
+            None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        let _ = tx.send(result);
+    }
+
+    fn fail_pending(&self, reason: &str) {
+        for (_, tx) in self.pending.lock().unwrap().drain() {
+            let _ = tx.send(Err(reason.to_string()));
+        }
+    }
+}
+
+/// 单个已注册服务器的运行时状态
+struct McpServer {
+    id: String,
+    spec: McpServerSpec,
+    connection: Mutex<Option<Arc<McpConnection>>>,
+    status: std::sync::Mutex<McpServerStatus>,
+    // 每次启动递增，用于忽略旧进程的退出通知
+    generation: AtomicU64,
+    stopping: AtomicBool,
+    restart_count: AtomicU32,
+    // 最近一次进入running状态的时间
+    running_since: std::sync::Mutex<Option<Instant>>,
+}
+
+impl McpServer {
+    fn set_status(&self, on_status: &StatusCallback, status: &str, message: Option<String>) {
+        let snapshot = {
+            let mut current = self.status.lock().unwrap();
+            current.status = status.to_string();
+            current.message = message;
+            current.restart_count = self.restart_count.load(Ordering::SeqCst);
+            current.clone()
+        };
+        on_status(snapshot);
+    }
+
+    async fn connection(&self) -> Result<Arc<McpConnection>, String> {
+        self.connection.lock().await.clone()
+            .ok_or_else(|| format!("MCP服务器 {} 未运行", self.id))
+    }
+}
+
+/// 外部MCP服务器管理器
+///
+/// 负责启动stdio服务器进程、完成initialize握手、代理请求，
+/// 并在进程意外退出时按指数退避自动重启。
+pub struct McpClientManager {
+    servers: Mutex<HashMap<String, Arc<McpServer>>>,
+    on_status: StatusCallback,
+}
+
+impl McpClientManager {
+    pub fn new(on_status: impl Fn(McpServerStatus) + Send + Sync + 'static) -> Self {
+        Self {
+            servers: Mutex::new(HashMap::new()),
+            on_status: Arc::new(on_status),
+        }
+    }
+
+    /// 启动服务器，已在运行时先停止旧进程
+    pub async fn start(self: &Arc<Self>, server_id: &str, spec: McpServerSpec) -> Result<McpServerStatus, String> {
+        self.stop(server_id).await;
+
+        let server = Arc::new(McpServer {
+            id: server_id.to_string(),
+            spec,
+            connection: Mutex::new(None),
+            status: std::sync::Mutex::new(McpServerStatus {
+                server_id: server_id.to_string(),
+                status: "stopped".to_string(),
+                message: None,
+                restart_count: 0,
+                server_info: None,
+            }),
+            generation: AtomicU64::new(0),
+            stopping: AtomicBool::new(false),
+            restart_count: AtomicU32::new(0),
+            running_since: std::sync::Mutex::new(None),
+        });
+        self.servers.lock().await.insert(server_id.to_string(), server.clone());
+
+        self.connect(&server).await?;
+        let status = server.status.lock().unwrap().clone();
+        Ok(status)
+    }
+
+    /// 停止服务器并移除注册信息
+    pub async fn stop(&self, server_id: &str) -> bool {
+        let Some(server) = self.servers.lock().await.remove(server_id) else {
+            return false;
+        };
+        server.stopping.store(true, Ordering::SeqCst);
+        server.generation.fetch_add(1, Ordering::SeqCst);
+
+        if let Some(connection) = server.connection.lock().await.take() {
+            connection.fail_pending("MCP服务器已停止");
+            if let Err(e) = connection.child.lock().await.kill().await {
+                warn!("终止MCP服务器进程失败: {}", e);
+            }
+        }
+        server.set_status(&self.on_status, "stopped", None);
+        info!("MCP服务器已停止: {}", server_id);
+        true
+    }
+
+    pub async fn statuses(&self) -> Vec<McpServerStatus> {
+        self.servers.lock().await.values()
+            .map(|server| server.status.lock().unwrap().clone())
+            .collect()
+    }
+
+    pub async fn list_tools(&self, server_id: &str) -> Result<Vec<Value>, String> {
+        self.list_paginated(server_id, "tools/list", "tools").await
+    }
+
+    pub async fn list_resources(&self, server_id: &str) -> Result<Vec<Value>, String> {
+        self.list_paginated(server_id, "resources/list", "resources").await
+    }
+
+    pub async fn list_prompts(&self, server_id: &str) -> Result<Vec<Value>, String> {
+        self.list_paginated(server_id, "prompts/list", "prompts").await
+    }
+
+    pub async fn call_tool(&self, server_id: &str, name: &str, arguments: Value) -> Result<Value, String> {
+        let connection = self.server(server_id).await?.connection().await?;
+        info!("调用MCP工具: {} / {}", server_id, name);
+        connection.request("tools/call", json!({ "name": name, "arguments": arguments })).await
+    }
+
+    async fn server(&self, server_id: &str) -> Result<Arc<McpServer>, String> {
+        self.servers.lock().await.get(server_id).cloned()
+            .ok_or_else(|| format!("MCP服务器 {} 未启动", server_id))
+    }
+
+    async fn list_paginated(&self, server_id: &str, method: &str, key: &str) -> Result<Vec<Value>, String> {
+        let connection = self.server(server_id).await?.connection().await?;
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = connection.request(method, params).await?;
+            if let Some(page) = result.get(key).and_then(|v| v.as_array()) {
+                items.extend(page.iter().cloned());
+            }
+            cursor = result.get("nextCursor").and_then(|c| c.as_str()).map(|c| c.to_string());
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// 启动进程并完成initialize握手
+    ///
+    /// 返回装箱的Future，以打断 connect -> handle_exit -> connect 的递归类型。
+    fn connect<'a>(self: &'a Arc<Self>, server: &'a Arc<McpServer>) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let generation = server.generation.fetch_add(1, Ordering::SeqCst) + 1;
+            server.set_status(&self.on_status, "starting", None);
+            info!("启动MCP服务器: {} ({} {:?})", server.id, server.spec.command, server.spec.args);
+
+            let mut child = match Command::new(&server.spec.command)
+                .args(&server.spec.args)
+                .envs(&server.spec.env)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+            {
+                Ok(child) => child,
+                Err(e) => {
+                    let message = format!("启动MCP服务器失败: {}", e);
+                    server.set_status(&self.on_status, "error", Some(message.clone()));
+                    return Err(message);
+                }
+            };
+
+            let stdin = child.stdin.take().ok_or("无法获取MCP服务器stdin")?;
+            let stdout = child.stdout.take().ok_or("无法获取MCP服务器stdout")?;
+            let stderr = child.stderr.take().ok_or("无法获取MCP服务器stderr")?;
+
+            let connection = Arc::new(McpConnection {
+                stdin: Mutex::new(stdin),
+                child: Mutex::new(child),
+                pending: Arc::new(std::sync::Mutex::new(HashMap::new())),
+                next_id: AtomicU64::new(1),
+            });
+
+            // stderr仅用于日志
+            let server_id = server.id.clone();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    debug!("[MCP {}] {}", server_id, line);
+                }
+            });
+
+            // stdout按行读取JSON-RPC消息，进程退出后触发重启逻辑
+            let reader_connection = connection.clone();
+            let manager = self.clone();
+            let reader_server = server.clone();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    reader_connection.handle_line(&line).await;
+                }
+                reader_connection.fail_pending("MCP服务器进程已退出");
+                manager.handle_exit(reader_server, generation).await;
+            });
+
+            let init = connection.request("initialize", json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": { "name": "aichat", "version": env!("CARGO_PKG_VERSION") },
+            })).await;
+
+            let init = match init {
+                Ok(result) => result,
+                Err(e) => {
+                    // 握手失败不经由退出通知重启，由调用方决定是否重试
+                    server.generation.fetch_add(1, Ordering::SeqCst);
+                    let _ = connection.child.lock().await.kill().await;
+                    let message = format!("MCP握手失败: {}", e);
+                    server.set_status(&self.on_status, "error", Some(message.clone()));
+                    return Err(message);
+                }
+            };
+            connection.notify("notifications/initialized", json!({})).await?;
+
+            // 握手期间可能已被stop或重新启动，此时stop看不到连接，需要在这里终止进程；
+            // 持有连接锁检查，保证之后的stop一定能取到已发布的连接
+            let mut slot = server.connection.lock().await;
+            if server.stopping.load(Ordering::SeqCst) || server.generation.load(Ordering::SeqCst) != generation {
+                drop(slot);
+                connection.fail_pending("MCP服务器已停止");
+                let _ = connection.child.lock().await.kill().await;
+                info!("MCP服务器 {} 在握手期间已停止，终止新进程", server.id);
+                return Err(format!("MCP服务器 {} 已停止", server.id));
+            }
+            server.status.lock().unwrap().server_info = Some(json!({
+                "protocolVersion": init.get("protocolVersion"),
+                "capabilities": init.get("capabilities"),
+                "serverInfo": init.get("serverInfo"),
+            }));
+            *slot = Some(connection);
+            drop(slot);
+            *server.running_since.lock().unwrap() = Some(Instant::now());
+            server.set_status(&self.on_status, "running", None);
+            info!("MCP服务器已就绪: {}", server.id);
+            Ok(())
+        })
+    }
+
+    /// 进程退出处理：非主动停止时按指数退避重启
+    ///
+    /// 稳定运行超过 `HEALTHY_UPTIME` 后的退出重新计数；重启时握手失败同样计为一次尝试，
+    /// 直到用尽 `MAX_RESTARTS` 次才标记为crashed。
+    async fn handle_exit(self: Arc<Self>, server: Arc<McpServer>, generation: u64) {
+        if server.stopping.load(Ordering::SeqCst) || server.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        // 握手未完成时由connect的调用方处理失败，避免与重启循环重复重启
+        if server.connection.lock().await.take().is_none() {
+            return;
+        }
+
+        let healthy = server.running_since.lock().unwrap().take()
+            .is_some_and(|since| since.elapsed() >= HEALTHY_UPTIME);
+        if healthy {
+            server.restart_count.store(0, Ordering::SeqCst);
+        }
+
+        tokio::spawn(async move {
+            let mut generation = generation;
+            loop {
+                let attempt = server.restart_count.fetch_add(1, Ordering::SeqCst) + 1;
+                if attempt > MAX_RESTARTS {
+                    error!("MCP服务器 {} 多次异常退出，不再重启", server.id);
+                    server.set_status(&self.on_status, "crashed", Some("进程多次异常退出".to_string()));
+                    return;
+                }
+
+                let delay = Duration::from_secs(1 << (attempt - 1));
+                warn!("MCP服务器 {} 异常退出，{} 秒后第 {} 次重启", server.id, delay.as_secs(), attempt);
+                server.set_status(&self.on_status, "restarting", Some(format!("进程已退出，{}秒后重启", delay.as_secs())));
+
+                tokio::time::sleep(delay).await;
+                if server.stopping.load(Ordering::SeqCst) || server.generation.load(Ordering::SeqCst) != generation {
+                    return;
+                }
+                match self.connect(&server).await {
+                    Ok(()) => return,
+                    Err(_) if server.stopping.load(Ordering::SeqCst) => return,
+                    Err(e) => {
+                        error!("重启MCP服务器 {} 失败: {}", server.id, e);
+                        generation = server.generation.load(Ordering::SeqCst);
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    // 模拟的stdio MCP服务器：响应initialize和tools/call，调用crash工具时退出，
+    // 存在fail_init标记文件时握手阶段直接退出，存在slow_init时延迟响应握手
+    const MOCK_SERVER: &str = r#"
+echo $$ > "$MOCK_DIR/pid"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*)
+      [ -f "$MOCK_DIR/fail_init" ] && exit 1
+      [ -f "$MOCK_DIR/slow_init" ] && sleep 1
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"mock","version":"1.0"}}}\n' "$id" ;;
+    *'"name":"crash"'*) exit 1 ;;
+    *'"method":"tools/call"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"pong"}]}}\n' "$id" ;;
+  esac
+done
+"#;
+
+    fn mock_spec(dir: &std::path::Path) -> McpServerSpec {
+        let script = dir.join("server.sh");
+        std::fs::write(&script, MOCK_SERVER).unwrap();
+        McpServerSpec {
+            command: "sh".to_string(),
+            args: vec![script.to_string_lossy().to_string()],
+            env: HashMap::from([("MOCK_DIR".to_string(), dir.to_string_lossy().to_string())]),
+        }
+    }
+
+    async fn wait_for(manager: &McpClientManager, check: impl Fn(&McpServerStatus) -> bool) -> McpServerStatus {
+        for _ in 0..200 {
+            if let Some(status) = manager.statuses().await.into_iter().find(|s| check(s)) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("等待MCP服务器状态超时: {:?}", manager.statuses().await);
+    }
+
+    #[tokio::test]
+    async fn initialize_and_call_tool() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = Arc::new(McpClientManager::new(|_| {}));
+
+        let status = manager.start("mock", mock_spec(dir.path())).await.unwrap();
+        assert_eq!(status.status, "running");
+        assert_eq!(status.server_info.unwrap()["serverInfo"]["name"], "mock");
+
+        let result = manager.call_tool("mock", "ping", json!({})).await.unwrap();
+        assert_eq!(result["content"][0]["text"], "pong");
+
+        assert!(manager.stop("mock").await);
+        assert!(manager.call_tool("mock", "ping", json!({})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn restarts_after_crash_and_failed_handshake() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = Arc::new(McpClientManager::new(|_| {}));
+        manager.start("mock", mock_spec(dir.path())).await.unwrap();
+
+        // 第一次重启时握手失败，应计为一次尝试并继续重试
+        std::fs::write(dir.path().join("fail_init"), "").unwrap();
+        assert!(manager.call_tool("mock", "crash", json!({})).await.is_err());
+        wait_for(&manager, |s| s.status == "restarting" && s.restart_count == 2).await;
+
+        std::fs::remove_file(dir.path().join("fail_init")).unwrap();
+        let status = wait_for(&manager, |s| s.status == "running").await;
+        assert_eq!(status.restart_count, 2);
+
+        let result = manager.call_tool("mock", "ping", json!({})).await.unwrap();
+        assert_eq!(result["content"][0]["text"], "pong");
+        manager.stop("mock").await;
+    }
+
+    #[tokio::test]
+    async fn stop_during_handshake_kills_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let statuses = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let manager = Arc::new(McpClientManager::new({
+            let statuses = statuses.clone();
+            move |status| statuses.lock().unwrap().push(status.status)
+        }));
+        std::fs::write(dir.path().join("slow_init"), "").unwrap();
+
+        let starting = tokio::spawn({
+            let manager = manager.clone();
+            let spec = mock_spec(dir.path());
+            async move { manager.start("mock", spec).await }
+        });
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(manager.stop("mock").await);
+        assert!(starting.await.unwrap().is_err());
+
+        let pid = std::fs::read_to_string(dir.path().join("pid")).unwrap();
+        let alive = std::process::Command::new("kill").args(["-0", pid.trim()]).stderr(Stdio::null()).status().unwrap().success();
+        assert!(!alive, "握手期间停止后进程仍在运行");
+        assert_eq!(statuses.lock().unwrap().last().map(String::as_str), Some("stopped"));
+    }
+}
@@ -0,0 +1,619 @@
+use sqlx::{Pool, Sqlite, SqliteConnection, Row};
+use chrono::Utc;
+use tracing::info;
+
+use crate::crypto::SecretCipher;
+
+/// 单个数据库迁移
+///
+/// 版本号必须严格递增，已发布的迁移不允许修改，
+/// 结构调整一律追加新的迁移。
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub statements: &'static [&'static str],
+    pub data: Option<DataMigration>,
+}
+
+/// 无法用纯SQL表达、需要在Rust中处理的数据迁移
+pub enum DataMigration {
+    /// 加密已有的API密钥、代理密码和MCP环境变量
+    EncryptSecrets,
+}
+
+/// 全部迁移，按版本号升序排列
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS ai_providers (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                api_endpoint TEXT NOT NULL,
+                api_key TEXT NOT NULL,
+                models TEXT NOT NULL,
+                default_model_id TEXT,
+                custom_config TEXT,
+                use_custom_config BOOLEAN,
+                auto_fetch_config TEXT,
+                preset_type TEXT,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS proxy_settings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                enabled BOOLEAN NOT NULL DEFAULT FALSE,
+                proxy_type TEXT NOT NULL DEFAULT 'http',
+                host TEXT NOT NULL DEFAULT '',
+                port INTEGER NOT NULL DEFAULT 0,
+                requires_auth BOOLEAN NOT NULL DEFAULT FALSE,
+                username TEXT NOT NULL DEFAULT '',
+                password TEXT NOT NULL DEFAULT '',
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS agents (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                description TEXT NOT NULL,
+                system_prompt TEXT NOT NULL,
+                provider_id TEXT NOT NULL,
+                model_id TEXT NOT NULL,
+                keep_history BOOLEAN NOT NULL DEFAULT TRUE,
+                max_history_messages INTEGER,
+                icon TEXT,
+                is_stream_mode BOOLEAN,
+                temperature REAL,
+                settings TEXT,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS agent_sessions (
+                id TEXT PRIMARY KEY,
+                agent_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (agent_id) REFERENCES agents (id) ON DELETE CASCADE
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS messages (
+                id TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                content TEXT NOT NULL,
+                role TEXT NOT NULL,
+                timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                streaming BOOLEAN,
+                canceled BOOLEAN,
+                reasoning_content TEXT,
+                reasoning_collapsed BOOLEAN,
+                generation_start_time DATETIME,
+                generation_end_time DATETIME,
+                generation_duration INTEGER,
+                FOREIGN KEY (session_id) REFERENCES agent_sessions (id) ON DELETE CASCADE
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS scenes (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                description TEXT NOT NULL,
+                scenario_prompt TEXT NOT NULL,
+                participants TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS scene_sessions (
+                id TEXT PRIMARY KEY,
+                scene_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                is_active BOOLEAN NOT NULL DEFAULT TRUE,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (scene_id) REFERENCES scenes (id) ON DELETE CASCADE
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS scene_messages (
+                id TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                participant_id TEXT NOT NULL,
+                agent_id TEXT,
+                role TEXT NOT NULL,
+                content TEXT NOT NULL,
+                timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                metadata TEXT,
+                FOREIGN KEY (session_id) REFERENCES scene_sessions (id) ON DELETE CASCADE
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS mcp_server_configs (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                description TEXT NOT NULL,
+                enabled BOOLEAN NOT NULL DEFAULT TRUE,
+                server_type TEXT NOT NULL,
+                server_class TEXT,
+                command TEXT,
+                args TEXT,
+                env TEXT,
+                capabilities TEXT NOT NULL,
+                permissions TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS app_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+            "CREATE INDEX IF NOT EXISTS idx_messages_session_id ON messages(session_id)",
+            "CREATE INDEX IF NOT EXISTS idx_messages_timestamp ON messages(timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_agent_sessions_agent_id ON agent_sessions(agent_id)",
+            "CREATE INDEX IF NOT EXISTS idx_scene_messages_session_id ON scene_messages(session_id)",
+            "CREATE INDEX IF NOT EXISTS idx_scene_sessions_scene_id ON scene_sessions(scene_id)",
+        ],
+        data: None,
+    },
+    Migration {
+        version: 2,
+        description: "full-text search index for messages",
+        statements: &[
+            // search_documents 为每条消息分配稳定的整数ID，作为全文索引的rowid
+            r#"
+            CREATE TABLE search_documents (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                source TEXT NOT NULL,
+                message_id TEXT NOT NULL,
+                UNIQUE (source, message_id)
+            )
+            "#,
+            // trigram分词器支持中文等无空格语言的子串匹配
+            r#"
+            CREATE VIRTUAL TABLE search_index USING fts5(
+                content,
+                reasoning_content,
+                tokenize = 'trigram'
+            )
+            "#,
+            // INSERT OR REPLACE 不会触发DELETE触发器，因此插入时先按稳定ID清理旧索引
+            r#"
+            CREATE TRIGGER messages_search_insert AFTER INSERT ON messages BEGIN
+                INSERT OR IGNORE INTO search_documents (source, message_id) VALUES ('chat', new.id);
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'chat' AND message_id = new.id);
+                INSERT INTO search_index (rowid, content, reasoning_content)
+                    VALUES ((SELECT id FROM search_documents WHERE source = 'chat' AND message_id = new.id),
+                            new.content, COALESCE(new.reasoning_content, ''));
+            END
+            "#,
+            r#"
+            CREATE TRIGGER messages_search_update AFTER UPDATE ON messages BEGIN
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'chat' AND message_id = old.id);
+                DELETE FROM search_documents WHERE source = 'chat' AND message_id = old.id;
+                INSERT OR IGNORE INTO search_documents (source, message_id) VALUES ('chat', new.id);
+                INSERT INTO search_index (rowid, content, reasoning_content)
+                    VALUES ((SELECT id FROM search_documents WHERE source = 'chat' AND message_id = new.id),
+                            new.content, COALESCE(new.reasoning_content, ''));
+            END
+            "#,
+            r#"
+            CREATE TRIGGER messages_search_delete AFTER DELETE ON messages BEGIN
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'chat' AND message_id = old.id);
+                DELETE FROM search_documents WHERE source = 'chat' AND message_id = old.id;
+            END
+            "#,
+            r#"
+            CREATE TRIGGER scene_messages_search_insert AFTER INSERT ON scene_messages BEGIN
+                INSERT OR IGNORE INTO search_documents (source, message_id) VALUES ('scene', new.id);
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'scene' AND message_id = new.id);
+                INSERT INTO search_index (rowid, content, reasoning_content)
+                    VALUES ((SELECT id FROM search_documents WHERE source = 'scene' AND message_id = new.id),
+                            new.content, '');
+            END
+            "#,
+            r#"
+            CREATE TRIGGER scene_messages_search_update AFTER UPDATE ON scene_messages BEGIN
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'scene' AND message_id = old.id);
+                DELETE FROM search_documents WHERE source = 'scene' AND message_id = old.id;
+                INSERT OR IGNORE INTO search_documents (source, message_id) VALUES ('scene', new.id);
+                INSERT INTO search_index (rowid, content, reasoning_content)
+                    VALUES ((SELECT id FROM search_documents WHERE source = 'scene' AND message_id = new.id),
+                            new.content, '');
+            END
+            "#,
+            r#"
+            CREATE TRIGGER scene_messages_search_delete AFTER DELETE ON scene_messages BEGIN
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'scene' AND message_id = old.id);
+                DELETE FROM search_documents WHERE source = 'scene' AND message_id = old.id;
+            END
+            "#,
+            // 为已有消息建立索引
+            "INSERT INTO search_documents (source, message_id) SELECT 'chat', id FROM messages",
+            "INSERT INTO search_documents (source, message_id) SELECT 'scene', id FROM scene_messages",
+            r#"
+            INSERT INTO search_index (rowid, content, reasoning_content)
+                SELECT d.id, m.content, COALESCE(m.reasoning_content, '')
+                FROM messages m JOIN search_documents d ON d.source = 'chat' AND d.message_id = m.id
+            "#,
+            r#"
+            INSERT INTO search_index (rowid, content, reasoning_content)
+                SELECT d.id, m.content, ''
+                FROM scene_messages m JOIN search_documents d ON d.source = 'scene' AND d.message_id = m.id
+            "#,
+        ],
+        data: None,
+    },
+    Migration {
+        version: 3,
+        description: "encrypt secrets at rest",
+        statements: &[],
+        data: Some(DataMigration::EncryptSecrets),
+    },
+    Migration {
+        version: 4,
+        description: "workspace roots for filesystem commands",
+        statements: &[
+            r#"
+            CREATE TABLE workspace_roots (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                mode TEXT NOT NULL DEFAULT 'read_write',
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        ],
+        data: None,
+    },
+    Migration {
+        version: 5,
+        description: "per-workspace file access policy",
+        statements: &[
+            "ALTER TABLE workspace_roots ADD COLUMN policy TEXT",
+        ],
+        data: None,
+    },
+    Migration {
+        version: 6,
+        description: "standing permission grants for agents and mcp servers",
+        statements: &[
+            r#"
+            CREATE TABLE permission_grants (
+                id TEXT PRIMARY KEY,
+                subject_type TEXT NOT NULL,
+                subject_id TEXT NOT NULL,
+                action TEXT NOT NULL,
+                target TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (subject_type, subject_id, action, target)
+            )
+            "#,
+        ],
+        data: None,
+    },
+    Migration {
+        version: 7,
+        description: "proxy routing rules",
+        statements: &[
+            "ALTER TABLE proxy_settings ADD COLUMN routing TEXT",
+        ],
+        data: None,
+    },
+    Migration {
+        version: 8,
+        description: "keep search document ids stable and drop orphaned index rows",
+        statements: &[
+            // 外层语句的 OR REPLACE 会覆盖触发器内 INSERT OR IGNORE 的冲突处理，
+            // 重新分配文档ID并留下旧索引行，因此先按旧ID删除索引再写入文档
+            "DROP TRIGGER messages_search_insert",
+            "DROP TRIGGER messages_search_update",
+            "DROP TRIGGER scene_messages_search_insert",
+            "DROP TRIGGER scene_messages_search_update",
+            r#"
+            CREATE TRIGGER messages_search_insert AFTER INSERT ON messages BEGIN
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'chat' AND message_id = new.id);
+                INSERT OR IGNORE INTO search_documents (source, message_id) VALUES ('chat', new.id);
+                INSERT INTO search_index (rowid, content, reasoning_content)
+                    VALUES ((SELECT id FROM search_documents WHERE source = 'chat' AND message_id = new.id),
+                            new.content, COALESCE(new.reasoning_content, ''));
+            END
+            "#,
+            // 更新时沿用原有文档ID，消息ID变化时只改文档的 message_id
+            r#"
+            CREATE TRIGGER messages_search_update AFTER UPDATE ON messages BEGIN
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'chat' AND message_id = old.id);
+                UPDATE search_documents SET message_id = new.id WHERE source = 'chat' AND message_id = old.id;
+                INSERT INTO search_index (rowid, content, reasoning_content)
+                    VALUES ((SELECT id FROM search_documents WHERE source = 'chat' AND message_id = new.id),
+                            new.content, COALESCE(new.reasoning_content, ''));
+            END
+            "#,
+            r#"
+            CREATE TRIGGER scene_messages_search_insert AFTER INSERT ON scene_messages BEGIN
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'scene' AND message_id = new.id);
+                INSERT OR IGNORE INTO search_documents (source, message_id) VALUES ('scene', new.id);
+                INSERT INTO search_index (rowid, content, reasoning_content)
+                    VALUES ((SELECT id FROM search_documents WHERE source = 'scene' AND message_id = new.id),
+                            new.content, '');
+            END
+            "#,
+            r#"
+            CREATE TRIGGER scene_messages_search_update AFTER UPDATE ON scene_messages BEGIN
+                DELETE FROM search_index
+                    WHERE rowid = (SELECT id FROM search_documents WHERE source = 'scene' AND message_id = old.id);
+                UPDATE search_documents SET message_id = new.id WHERE source = 'scene' AND message_id = old.id;
+                INSERT INTO search_index (rowid, content, reasoning_content)
+                    VALUES ((SELECT id FROM search_documents WHERE source = 'scene' AND message_id = new.id),
+                            new.content, '');
+            END
+            "#,
+            "DELETE FROM search_index WHERE rowid NOT IN (SELECT id FROM search_documents)",
+        ],
+        data: None,
+    },
+];
+
+/// 读取数据库当前的schema版本，未执行过任何迁移时返回0
+pub async fn current_version(pool: &Pool<Sqlite>) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query("SELECT COALESCE(MAX(version), 0) AS version FROM schema_migrations")
+        .fetch_one(pool)
+        .await?;
+    Ok(row.get("version"))
+}
+
+/// 执行所有尚未应用的迁移
+///
+/// 每个迁移在独立事务中执行，并在同一事务内写入 `schema_migrations`，
+/// 失败时整体回滚。数据库版本高于程序已知版本时拒绝打开，
+/// 防止旧版本程序写坏新结构的数据。
+pub async fn run(pool: &Pool<Sqlite>, cipher: &SecretCipher) -> Result<(), sqlx::Error> {
+    apply(pool, cipher, MIGRATIONS).await
+}
+
+async fn apply(pool: &Pool<Sqlite>, cipher: &SecretCipher, migrations: &[Migration]) -> Result<(), sqlx::Error> {
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+    "#).execute(pool).await?;
+
+    let current = current_version(pool).await?;
+    let latest = latest_version(migrations);
+    if current > latest {
+        return Err(sqlx::Error::Configuration(format!(
+            "数据库版本 ({}) 高于当前程序支持的版本 ({})，请升级应用后再打开",
+            current, latest
+        ).into()));
+    }
+
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        info!("执行数据库迁移 v{}: {}", migration.version, migration.description);
+
+        let mut tx = pool.begin().await?;
+        for statement in migration.statements {
+            sqlx::query(statement).execute(&mut *tx).await?;
+        }
+        if let Some(DataMigration::EncryptSecrets) = migration.data {
+            encrypt_secrets(&mut tx, cipher).await?;
+        }
+        sqlx::query("INSERT INTO schema_migrations (version, description, applied_at) VALUES (?, ?, ?)")
+            .bind(migration.version)
+            .bind(migration.description)
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// 迁移列表对应的最高schema版本，即当前程序支持的最高版本
+fn latest_version(migrations: &[Migration]) -> i64 {
+    migrations.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// 将明文存储的敏感字段加密
+///
+/// 只跳过能用当前密钥解密的密文，带有密文前缀的旧明文同样加密。
+async fn encrypt_secrets(conn: &mut SqliteConnection, cipher: &SecretCipher) -> Result<(), sqlx::Error> {
+    let encrypt = |value: &str| cipher.encrypt(value).map_err(sqlx::Error::Protocol);
+
+    let rows = sqlx::query("SELECT id, api_key FROM ai_providers").fetch_all(&mut *conn).await?;
+    for row in rows {
+        let id: String = row.get("id");
+        let api_key: String = row.get("api_key");
+        let encrypted = encrypt(&api_key)?;
+        if encrypted != api_key {
+            sqlx::query("UPDATE ai_providers SET api_key = ? WHERE id = ?")
+                .bind(encrypted)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    let rows = sqlx::query("SELECT id, password FROM proxy_settings").fetch_all(&mut *conn).await?;
+    for row in rows {
+        let id: i64 = row.get("id");
+        let password: String = row.get("password");
+        let encrypted = encrypt(&password)?;
+        if encrypted != password {
+            sqlx::query("UPDATE proxy_settings SET password = ? WHERE id = ?")
+                .bind(encrypted)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    let rows = sqlx::query("SELECT id, env FROM mcp_server_configs WHERE env IS NOT NULL").fetch_all(&mut *conn).await?;
+    for row in rows {
+        let id: String = row.get("id");
+        let env: String = row.get("env");
+        let encrypted = encrypt(&env)?;
+        if encrypted != env {
+            sqlx::query("UPDATE mcp_server_configs SET env = ? WHERE id = ?")
+                .bind(encrypted)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    /// 单连接的内存数据库，多个连接会各自打开独立的内存库
+    async fn memory_pool() -> Pool<Sqlite> {
+        SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap()
+    }
+
+    async fn table_exists(pool: &Pool<Sqlite>, name: &str) -> bool {
+        sqlx::query("SELECT 1 FROM sqlite_master WHERE name = ?").bind(name).fetch_optional(pool).await.unwrap().is_some()
+    }
+
+    #[tokio::test]
+    async fn fresh_database_reaches_latest_and_rerun_is_noop() {
+        let dir = tempfile::tempdir().unwrap();
+        let cipher = SecretCipher::load_or_create(&dir.path().join("master.key")).unwrap();
+        let pool = memory_pool().await;
+
+        run(&pool, &cipher).await.unwrap();
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version(MIGRATIONS));
+        assert!(table_exists(&pool, "search_index").await);
+        assert!(table_exists(&pool, "permission_grants").await);
+
+        run(&pool, &cipher).await.unwrap();
+        let applied: i64 = sqlx::query("SELECT COUNT(*) AS n FROM schema_migrations").fetch_one(&pool).await.unwrap().get("n");
+        assert_eq!(applied, MIGRATIONS.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn failed_migration_rolls_back() {
+        const BROKEN: &[Migration] = &[
+            Migration { version: 1, description: "ok", statements: &["CREATE TABLE a (id INTEGER)"], data: None },
+            Migration {
+                version: 2,
+                description: "broken",
+                statements: &["CREATE TABLE b (id INTEGER)", "INSERT INTO missing VALUES (1)"],
+                data: None,
+            },
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let cipher = SecretCipher::load_or_create(&dir.path().join("master.key")).unwrap();
+        let pool = memory_pool().await;
+
+        assert!(apply(&pool, &cipher, BROKEN).await.is_err());
+        assert_eq!(current_version(&pool).await.unwrap(), 1);
+        assert!(table_exists(&pool, "a").await);
+        assert!(!table_exists(&pool, "b").await);
+    }
+
+    #[tokio::test]
+    async fn refuses_newer_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let cipher = SecretCipher::load_or_create(&dir.path().join("master.key")).unwrap();
+        let pool = memory_pool().await;
+        run(&pool, &cipher).await.unwrap();
+
+        sqlx::query("INSERT INTO schema_migrations (version, description) VALUES (?, 'future')")
+            .bind(latest_version(MIGRATIONS) + 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(matches!(run(&pool, &cipher).await, Err(sqlx::Error::Configuration(_))));
+    }
+
+    #[tokio::test]
+    async fn search_index_orphans_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let cipher = SecretCipher::load_or_create(&dir.path().join("master.key")).unwrap();
+        let pool = memory_pool().await;
+        apply(&pool, &cipher, &MIGRATIONS[..7]).await.unwrap();
+
+        sqlx::query("INSERT INTO agents (id, name, description, system_prompt, provider_id, model_id) VALUES ('a', 'a', '', '', 'p', 'm')")
+            .execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO agent_sessions (id, agent_id, name) VALUES ('s', 'a', 's')").execute(&pool).await.unwrap();
+        for content in ["first", "second"] {
+            sqlx::query("INSERT OR REPLACE INTO messages (id, session_id, content, role) VALUES ('m', 's', ?, 'user')")
+                .bind(content)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let count = "SELECT COUNT(*) AS n FROM search_index";
+        assert_eq!(sqlx::query(count).fetch_one(&pool).await.unwrap().get::<i64, _>("n"), 2);
+
+        run(&pool, &cipher).await.unwrap();
+        assert_eq!(sqlx::query(count).fetch_one(&pool).await.unwrap().get::<i64, _>("n"), 1);
+
+        // 新触发器下即使外层使用 OR REPLACE 也不会再留下孤立索引
+        sqlx::query("INSERT OR REPLACE INTO messages (id, session_id, content, role) VALUES ('m', 's', 'third', 'user')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(sqlx::query(count).fetch_one(&pool).await.unwrap().get::<i64, _>("n"), 1);
+    }
+
+    #[tokio::test]
+    async fn prefixed_plaintext_is_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let cipher = SecretCipher::load_or_create(&dir.path().join("master.key")).unwrap();
+        let pool = memory_pool().await;
+        apply(&pool, &cipher, &MIGRATIONS[..2]).await.unwrap();
+
+        let sealed = cipher.encrypt("sk-sealed").unwrap();
+        for (id, key) in [("legacy", "enc:v1:legacy-plaintext"), ("sealed", sealed.as_str())] {
+            sqlx::query("INSERT INTO ai_providers (id, name, api_endpoint, api_key, models) VALUES (?, ?, '', ?, '[]')")
+                .bind(id)
+                .bind(id)
+                .bind(key)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        run(&pool, &cipher).await.unwrap();
+
+        let stored = |id: &'static str| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query("SELECT api_key FROM ai_providers WHERE id = ?").bind(id).fetch_one(&pool).await.unwrap().get::<String, _>("api_key")
+            }
+        };
+        let legacy = stored("legacy").await;
+        assert_ne!(legacy, "enc:v1:legacy-plaintext");
+        assert_eq!(cipher.decrypt(&legacy).unwrap(), "enc:v1:legacy-plaintext");
+        assert_eq!(stored("sealed").await, sealed);
+    }
+}
@@ -0,0 +1,237 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+pub const SUBJECT_AGENT: &str = "agent";
+pub const SUBJECT_MCP_SERVER: &str = "mcp_server";
+/// 通过设置命令修改的配置，主体ID为设置项名称
+pub const SUBJECT_SETTINGS: &str = "settings";
+
+/// 未指定智能体时使用的授权主体
+pub const DEFAULT_AGENT_ID: &str = "default";
+
+pub const ACTION_FS_WRITE: &str = "fs_write";
+pub const ACTION_FS_DELETE: &str = "fs_delete";
+pub const ACTION_FS_MOVE: &str = "fs_move";
+pub const ACTION_MCP_TOOL: &str = "mcp_tool";
+pub const ACTION_HTTP_ALLOWLIST: &str = "http_allowlist";
+/// 添加工作区目录或修改其路径
+pub const ACTION_WORKSPACE_ADD: &str = "workspace_add";
+/// 以读写模式添加工作区，或把只读工作区改为读写
+pub const ACTION_WORKSPACE_WRITE: &str = "workspace_write";
+
+/// 用户对审批请求的答复
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionDecision {
+    AllowOnce,
+    AllowAlways,
+    Deny,
+}
+
+/// 审批请求，同时作为 `permission-request` 事件的负载
+#[derive(Debug, Clone, Serialize)]
+pub struct PermissionRequest {
+    pub request_id: String,
+    pub subject_type: String,
+    pub subject_id: String,
+    pub action: String,
+    pub target: String,
+    pub summary: String, // 展示给用户的操作说明
+    pub details: Value,
+}
+
+impl PermissionRequest {
+    pub fn new(subject_type: &str, subject_id: &str, action: &str, target: &str, summary: String, details: Value) -> Self {
+        Self {
+            request_id: Uuid::new_v4().to_string(),
+            subject_type: subject_type.to_string(),
+            subject_id: subject_id.to_string(),
+            action: action.to_string(),
+            target: target.to_string(),
+            summary,
+            details,
+        }
+    }
+}
+
+type RequestCallback = Box<dyn Fn(&PermissionRequest) + Send + Sync>;
+
+/// 审批代理
+///
+/// 需要用户确认的操作通过回调发出审批请求，然后等待前端调用 `respond`。
+/// 超时或请求被丢弃时视为拒绝。
+pub struct PermissionBroker {
+    pending: Mutex<HashMap<String, (PermissionRequest, oneshot::Sender<PermissionDecision>)>>,
+    on_request: RequestCallback,
+    timeout: Duration,
+}
+
+impl PermissionBroker {
+    pub fn new(timeout: Duration, on_request: impl Fn(&PermissionRequest) + Send + Sync + 'static) -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+            on_request: Box::new(on_request),
+            timeout,
+        }
+    }
+
+    /// 发出审批请求并等待答复
+    pub async fn ask(&self, request: PermissionRequest) -> PermissionDecision {
+        let request_id = request.request_id.clone();
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(request_id.clone(), (request.clone(), tx));
+        info!("等待用户审批: {} {} {} ({})", request.subject_id, request.action, request.target, request_id);
+        (self.on_request)(&request);
+
+        let decision = match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(decision)) => decision,
+            Ok(Err(_)) => PermissionDecision::Deny,
+            Err(_) => {
+                warn!("审批请求超时，按拒绝处理: {}", request_id);
+                PermissionDecision::Deny
+            }
+        };
+        self.pending.lock().unwrap().remove(&request_id);
+        decision
+    }
+
+    /// 答复审批请求，请求不存在或已超时时返回 false
+    pub fn respond(&self, request_id: &str, decision: PermissionDecision) -> bool {
+        match self.pending.lock().unwrap().remove(request_id) {
+            Some((_, tx)) => tx.send(decision).is_ok(),
+            None => false,
+        }
+    }
+
+    /// 尚未答复的审批请求，供前端刷新后恢复显示
+    pub fn pending(&self) -> Vec<PermissionRequest> {
+        self.pending.lock().unwrap().values().map(|(request, _)| request.clone()).collect()
+    }
+}
+
+/// MCP服务器配置中的 `permissions` 字段
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct McpPermissions {
+    pub allow_tool_execution: bool,
+    pub allowed_domains: Vec<String>,
+}
+
+impl McpPermissions {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        if json.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(json).map_err(|e| format!("permissions 格式无效: {}", e))
+    }
+
+    /// 检查工具调用是否被服务器权限允许
+    ///
+    /// 配置了 `allowedDomains` 时，参数中出现的所有URL都必须指向允许的域名或其子域名；
+    /// 列表为空表示不限制域名。
+    pub fn check_tool_call(&self, server_name: &str, tool: &str, arguments: &Value) -> Result<(), String> {
+        if !self.allow_tool_execution {
+            return Err(format!("服务器 {} 不允许执行工具", server_name));
+        }
+        if self.allowed_domains.is_empty() {
+            return Ok(());
+        }
+
+        let mut urls = Vec::new();
+        collect_urls(arguments, &mut urls);
+        for url in urls {
+            let host = url.host_str().unwrap_or_default();
+            if !self.allowed_domains.iter().any(|domain| domain_matches(domain, host)) {
+                return Err(format!("工具 {} 的参数访问了未允许的域名: {}", tool, host));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `example.com` 和 `*.example.com` 都匹配该域名及其子域名
+fn domain_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim().trim_start_matches("*.").trim_end_matches('.').to_ascii_lowercase();
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    !pattern.is_empty() && (host == pattern || host.ends_with(&format!(".{}", pattern)))
+}
+
+/// 递归收集参数中的网络URL
+fn collect_urls(value: &Value, urls: &mut Vec<Url>) {
+    match value {
+        Value::String(text) => {
+            if let Ok(url) = Url::parse(text.trim()) {
+                if matches!(url.scheme(), "http" | "https" | "ws" | "wss") {
+                    urls.push(url);
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_urls(item, urls)),
+        Value::Object(map) => map.values().for_each(|item| collect_urls(item, urls)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn request() -> PermissionRequest {
+        PermissionRequest::new(SUBJECT_AGENT, DEFAULT_AGENT_ID, ACTION_FS_WRITE, "root", "写入文件 a.txt".to_string(), Value::Null)
+    }
+
+    #[tokio::test]
+    async fn broker_waits_for_response() {
+        let broker = Arc::new(PermissionBroker::new(Duration::from_secs(5), |_| {}));
+        let request = request();
+        let request_id = request.request_id.clone();
+
+        let waiter = tokio::spawn({
+            let broker = broker.clone();
+            async move { broker.ask(request).await }
+        });
+        while broker.pending().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        assert!(broker.respond(&request_id, PermissionDecision::AllowAlways));
+        assert_eq!(waiter.await.unwrap(), PermissionDecision::AllowAlways);
+        assert!(broker.pending().is_empty());
+        assert!(!broker.respond(&request_id, PermissionDecision::AllowOnce));
+    }
+
+    #[tokio::test]
+    async fn broker_denies_on_timeout() {
+        let broker = PermissionBroker::new(Duration::from_millis(20), |_| {});
+        assert_eq!(broker.ask(request()).await, PermissionDecision::Deny);
+        assert!(broker.pending().is_empty());
+    }
+
+    #[test]
+    fn mcp_permissions_enforce_tool_execution_and_domains() {
+        let denied = McpPermissions::from_json(r#"{"allowToolExecution": false}"#).unwrap();
+        assert!(denied.check_tool_call("srv", "fetch", &json!({})).is_err());
+
+        let open = McpPermissions::from_json(r#"{"allowToolExecution": true, "allowedDomains": []}"#).unwrap();
+        assert!(open.check_tool_call("srv", "fetch", &json!({"url": "https://anything.test/"})).is_ok());
+
+        let limited = McpPermissions::from_json(
+            r#"{"allowToolExecution": true, "allowResourceAccess": true, "allowedDomains": ["example.com", "*.api.test"]}"#,
+        ).unwrap();
+        let ok = json!({"url": "https://docs.Example.com/a", "items": ["wss://x.api.test/ws", "not a url"]});
+        assert!(limited.check_tool_call("srv", "fetch", &ok).is_ok());
+        let err = limited.check_tool_call("srv", "fetch", &json!({"nested": {"url": "http://evil-example.com/"}}));
+        assert!(err.unwrap_err().contains("evil-example.com"));
+
+        assert!(!McpPermissions::from_json("").unwrap().allow_tool_execution);
+    }
+}
@@ -0,0 +1,650 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 流式请求使用的提供商适配器配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderStreamConfig {
+    pub kind: String, // "openai", "anthropic", "gemini", "custom"
+    pub response: Option<StreamResponseConfig>,
+}
+
+/// 自定义提供商的流式响应解析配置，字段与前端 `StreamConfig.response` 一致
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamResponseConfig {
+    pub format: Option<String>, // "sse" or "json"
+    pub data_prefix: Option<String>,
+    pub content_path: String,
+    pub reasoning_path: Option<String>,
+    pub finish_condition: Option<String>,
+}
+
+/// 解析后的流式事件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProviderEvent {
+    ContentDelta { text: String },
+    ReasoningDelta { text: String },
+    ToolCallDelta {
+        index: u64,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: Option<String>,
+    },
+    Usage {
+        input_tokens: Option<u64>,
+        output_tokens: Option<u64>,
+        total_tokens: Option<u64>,
+    },
+    Finish { reason: String },
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Framing {
+    Sse,
+    JsonArray,
+    // 根据首个非空白字符判断，'[' 为JSON数组，否则为SSE
+    Auto,
+}
+
+enum Adapter {
+    OpenAi,
+    Anthropic,
+    Gemini { next_tool_index: u64 },
+    Custom(StreamResponseConfig),
+}
+
+/// 增量流式响应解析器
+///
+/// 负责SSE分帧或JSON数组分帧，并按提供商格式把每个数据块转换为 `ProviderEvent`。
+/// SSE事件的多行 `data:` 按规范以换行拼接，遇到空行时作为一个数据块处理。
+pub struct StreamParser {
+    adapter: Adapter,
+    framing: Framing,
+    data_prefix: String,
+    finish_condition: Option<String>,
+    buffer: String,
+    // 当前SSE事件中已收到的data行
+    event_data: Option<String>,
+    scanner: JsonArrayScanner,
+    finished: bool,
+}
+
+impl StreamParser {
+    pub fn new(config: &ProviderStreamConfig) -> Result<Self, String> {
+        let (adapter, framing) = match config.kind.as_str() {
+            "openai" => (Adapter::OpenAi, Framing::Sse),
+            "anthropic" => (Adapter::Anthropic, Framing::Sse),
+            "gemini" => (Adapter::Gemini { next_tool_index: 0 }, Framing::Auto),
+            "custom" => {
+                let response = config.response.clone()
+                    .ok_or("自定义提供商缺少流式响应配置")?;
+                let framing = match response.format.as_deref() {
+                    Some("json") => Framing::JsonArray,
+                    Some("sse") => Framing::Sse,
+                    _ => Framing::Auto,
+                };
+                (Adapter::Custom(response), framing)
+            }
+            other => return Err(format!("不支持的提供商类型: {}", other)),
+        };
+
+        let (data_prefix, finish_condition) = match &adapter {
+            Adapter::Custom(response) => (
+                response.data_prefix.clone().unwrap_or_else(|| "data:".to_string()),
+                response.finish_condition.clone().filter(|c| !c.is_empty()),
+            ),
+            Adapter::OpenAi => ("data:".to_string(), Some("[DONE]".to_string())),
+            _ => ("data:".to_string(), None),
+        };
+
+        Ok(Self {
+            adapter,
+            framing,
+            data_prefix,
+            finish_condition,
+            buffer: String::new(),
+            event_data: None,
+            scanner: JsonArrayScanner::default(),
+            finished: false,
+        })
+    }
+
+    /// 输入一段文本，返回其中完整数据块解析出的事件
+    pub fn feed(&mut self, text: &str) -> Vec<ProviderEvent> {
+        self.buffer.push_str(text);
+
+        if self.framing == Framing::Auto {
+            match self.buffer.trim_start().chars().next() {
+                Some('[') => self.framing = Framing::JsonArray,
+                Some(_) => self.framing = Framing::Sse,
+                None => return Vec::new(),
+            }
+        }
+
+        let mut events = Vec::new();
+        match self.framing {
+            Framing::JsonArray => {
+                let objects = self.scanner.drain_objects(&mut self.buffer);
+                for object in objects {
+                    self.handle_payload(&object, &mut events);
+                }
+            }
+            _ => {
+                while let Some(pos) = self.buffer.find('\n') {
+                    let line: String = self.buffer.drain(..=pos).collect();
+                    self.handle_sse_line(line.trim_end_matches(['\r', '\n']), &mut events);
+                }
+            }
+        }
+        events
+    }
+
+    /// 流结束时处理缓冲区中剩余的不完整行和未以空行结束的事件
+    pub fn finish(&mut self) -> Vec<ProviderEvent> {
+        let mut events = Vec::new();
+        if self.framing != Framing::JsonArray && !self.buffer.trim().is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            self.handle_sse_line(line.trim_end_matches(['\r', '\n']), &mut events);
+        }
+        self.dispatch_event(&mut events);
+        self.buffer.clear();
+        events
+    }
+
+    fn handle_sse_line(&mut self, line: &str, events: &mut Vec<ProviderEvent>) {
+        if line.is_empty() {
+            self.dispatch_event(events);
+            return;
+        }
+        if line.starts_with(':') || line.starts_with("event:") || line.starts_with("id:") || line.starts_with("retry:") {
+            return;
+        }
+
+        match line.strip_prefix(self.data_prefix.as_str()) {
+            // 没有前缀时无法区分事件边界，每行单独处理
+            Some(rest) if self.data_prefix.is_empty() => self.handle_payload(rest, events),
+            Some(rest) => {
+                // 规范只去掉冒号后的一个空格
+                let rest = rest.strip_prefix(' ').unwrap_or(rest);
+                match self.event_data.as_mut() {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(rest);
+                    }
+                    None => self.event_data = Some(rest.to_string()),
+                }
+            }
+            // 兼容逐行JSON（NDJSON）格式
+            None if line.trim_start().starts_with('{') => {
+                self.dispatch_event(events);
+                self.handle_payload(line.trim_start(), events);
+            }
+            None => {}
+        }
+    }
+
+    fn dispatch_event(&mut self, events: &mut Vec<ProviderEvent>) {
+        if let Some(data) = self.event_data.take() {
+            if !data.trim().is_empty() {
+                self.handle_payload(&data, events);
+            }
+        }
+    }
+
+    fn handle_payload(&mut self, payload: &str, events: &mut Vec<ProviderEvent>) {
+        if self.finish_condition.as_deref() == Some(payload.trim()) {
+            self.push_finish("stop", events);
+            return;
+        }
+
+        let value: Value = match serde_json::from_str(payload) {
+            Ok(value) => value,
+            Err(e) => {
+                events.push(ProviderEvent::Error { message: format!("无法解析流式数据: {}", e) });
+                return;
+            }
+        };
+
+        let mut parsed = Vec::new();
+        match &mut self.adapter {
+            Adapter::OpenAi => parse_openai(&value, &mut parsed),
+            Adapter::Anthropic => parse_anthropic(&value, &mut parsed),
+            Adapter::Gemini { next_tool_index } => parse_gemini(&value, next_tool_index, &mut parsed),
+            Adapter::Custom(response) => parse_custom(&value, response, &mut parsed),
+        }
+
+        for event in parsed {
+            match event {
+                ProviderEvent::Finish { reason } => self.push_finish(&reason, events),
+                event => events.push(event),
+            }
+        }
+    }
+
+    fn push_finish(&mut self, reason: &str, events: &mut Vec<ProviderEvent>) {
+        if !self.finished {
+            self.finished = true;
+            events.push(ProviderEvent::Finish { reason: reason.to_string() });
+        }
+    }
+}
+
+fn parse_openai(value: &Value, events: &mut Vec<ProviderEvent>) {
+    if let Some(error) = value.get("error") {
+        events.push(ProviderEvent::Error { message: error_message(error) });
+        return;
+    }
+
+    if let Some(delta) = json_path(value, "choices[0].delta") {
+        if let Some(text) = delta.get("reasoning_content").or_else(|| delta.get("reasoning")).and_then(|v| v.as_str()) {
+            if !text.is_empty() {
+                events.push(ProviderEvent::ReasoningDelta { text: text.to_string() });
+            }
+        }
+        if let Some(text) = delta.get("content").and_then(|v| v.as_str()) {
+            if !text.is_empty() {
+                events.push(ProviderEvent::ContentDelta { text: text.to_string() });
+            }
+        }
+        if let Some(tool_calls) = delta.get("tool_calls").and_then(|v| v.as_array()) {
+            for (position, call) in tool_calls.iter().enumerate() {
+                events.push(ProviderEvent::ToolCallDelta {
+                    index: call.get("index").and_then(|v| v.as_u64()).unwrap_or(position as u64),
+                    id: string_at(call, "id"),
+                    name: string_at(call, "function.name"),
+                    arguments: string_at(call, "function.arguments"),
+                });
+            }
+        }
+    }
+
+    if let Some(usage) = value.get("usage").filter(|u| u.is_object()) {
+        events.push(ProviderEvent::Usage {
+            input_tokens: usage.get("prompt_tokens").and_then(|v| v.as_u64()),
+            output_tokens: usage.get("completion_tokens").and_then(|v| v.as_u64()),
+            total_tokens: usage.get("total_tokens").and_then(|v| v.as_u64()),
+        });
+    }
+
+    if let Some(reason) = string_at(value, "choices[0].finish_reason") {
+        events.push(ProviderEvent::Finish { reason });
+    }
+}
+
+fn parse_anthropic(value: &Value, events: &mut Vec<ProviderEvent>) {
+    let index = value.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+    match value.get("type").and_then(|v| v.as_str()) {
+        Some("message_start") => {
+            if let Some(usage) = json_path(value, "message.usage") {
+                events.push(ProviderEvent::Usage {
+                    input_tokens: usage.get("input_tokens").and_then(|v| v.as_u64()),
+                    output_tokens: usage.get("output_tokens").and_then(|v| v.as_u64()),
+                    total_tokens: None,
+                });
+            }
+        }
+        Some("content_block_start") => {
+            let block = value.get("content_block");
+            if block.and_then(|b| b.get("type")).and_then(|v| v.as_str()) == Some("tool_use") {
+                events.push(ProviderEvent::ToolCallDelta {
+                    index,
+                    id: block.and_then(|b| string_at(b, "id")),
+                    name: block.and_then(|b| string_at(b, "name")),
+                    arguments: None,
+                });
+            }
+        }
+        Some("content_block_delta") => {
+            let delta = value.get("delta");
+            match delta.and_then(|d| d.get("type")).and_then(|v| v.as_str()) {
+                Some("text_delta") => {
+                    if let Some(text) = delta.and_then(|d| string_at(d, "text")) {
+                        events.push(ProviderEvent::ContentDelta { text });
+                    }
+                }
+                Some("thinking_delta") => {
+                    if let Some(text) = delta.and_then(|d| string_at(d, "thinking")) {
+                        events.push(ProviderEvent::ReasoningDelta { text });
+                    }
+                }
+                Some("input_json_delta") => {
+                    events.push(ProviderEvent::ToolCallDelta {
+                        index,
+                        id: None,
+                        name: None,
+                        arguments: delta.and_then(|d| string_at(d, "partial_json")),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Some("message_delta") => {
+            if let Some(usage) = value.get("usage") {
+                events.push(ProviderEvent::Usage {
+                    input_tokens: usage.get("input_tokens").and_then(|v| v.as_u64()),
+                    output_tokens: usage.get("output_tokens").and_then(|v| v.as_u64()),
+                    total_tokens: None,
+                });
+            }
+            if let Some(reason) = string_at(value, "delta.stop_reason") {
+                events.push(ProviderEvent::Finish { reason });
+            }
+        }
+        Some("error") => {
+            events.push(ProviderEvent::Error {
+                message: value.get("error").map(error_message).unwrap_or_else(|| "未知错误".to_string()),
+            });
+        }
+        _ => {}
+    }
+}
+
+fn parse_gemini(value: &Value, next_tool_index: &mut u64, events: &mut Vec<ProviderEvent>) {
+    if let Some(error) = value.get("error") {
+        events.push(ProviderEvent::Error { message: error_message(error) });
+        return;
+    }
+
+    if let Some(parts) = json_path(value, "candidates[0].content.parts").and_then(|v| v.as_array()) {
+        for part in parts {
+            if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
+                let is_thought = part.get("thought").and_then(|v| v.as_bool()).unwrap_or(false);
+                if is_thought {
+                    events.push(ProviderEvent::ReasoningDelta { text: text.to_string() });
+                } else {
+                    events.push(ProviderEvent::ContentDelta { text: text.to_string() });
+                }
+            }
+            // Gemini一次性返回完整的函数调用
+            if let Some(call) = part.get("functionCall") {
+                events.push(ProviderEvent::ToolCallDelta {
+                    index: *next_tool_index,
+                    id: string_at(call, "id"),
+                    name: string_at(call, "name"),
+                    arguments: call.get("args").map(|args| args.to_string()),
+                });
+                *next_tool_index += 1;
+            }
+        }
+    }
+
+    if let Some(usage) = value.get("usageMetadata") {
+        events.push(ProviderEvent::Usage {
+            input_tokens: usage.get("promptTokenCount").and_then(|v| v.as_u64()),
+            output_tokens: usage.get("candidatesTokenCount").and_then(|v| v.as_u64()),
+            total_tokens: usage.get("totalTokenCount").and_then(|v| v.as_u64()),
+        });
+    }
+
+    if let Some(reason) = string_at(value, "candidates[0].finishReason") {
+        events.push(ProviderEvent::Finish { reason });
+    }
+}
+
+fn parse_custom(value: &Value, response: &StreamResponseConfig, events: &mut Vec<ProviderEvent>) {
+    if let Some(path) = response.reasoning_path.as_deref().filter(|p| !p.is_empty()) {
+        if let Some(text) = string_at(value, path).filter(|t| !t.is_empty()) {
+            events.push(ProviderEvent::ReasoningDelta { text });
+        }
+    }
+    if let Some(text) = string_at(value, &response.content_path).filter(|t| !t.is_empty()) {
+        events.push(ProviderEvent::ContentDelta { text });
+    }
+}
+
+fn error_message(error: &Value) -> String {
+    error.get("message").and_then(|v| v.as_str())
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| error.to_string())
+}
+
+fn string_at(value: &Value, path: &str) -> Option<String> {
+    json_path(value, path).and_then(|v| v.as_str()).map(|s| s.to_string())
+}
+
+/// 按路径读取JSON值，支持 `choices[0].delta.content` 和 `choices.0.delta` 两种写法
+pub fn json_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = value;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let (key, indexes) = match segment.find('[') {
+            Some(pos) => (&segment[..pos], &segment[pos..]),
+            None => (segment, ""),
+        };
+
+        if !key.is_empty() {
+            current = match (current, key.parse::<usize>()) {
+                (Value::Array(items), Ok(index)) => items.get(index)?,
+                _ => current.get(key)?,
+            };
+        }
+
+        for index in indexes.split('[').filter(|s| !s.is_empty()) {
+            let index: usize = index.trim_end_matches(']').parse().ok()?;
+            current = current.get(index)?;
+        }
+    }
+    Some(current)
+}
+
+/// JSON数组流的增量分帧器，逐个取出顶层数组中的完整对象
+#[derive(Default)]
+struct JsonArrayScanner {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    // 当前对象在缓冲区中的起始位置
+    start: Option<usize>,
+    // 已扫描到的缓冲区位置
+    pos: usize,
+}
+
+impl JsonArrayScanner {
+    fn drain_objects(&mut self, buffer: &mut String) -> Vec<String> {
+        let mut objects = Vec::new();
+        let mut consumed = 0;
+
+        for (offset, ch) in buffer[self.pos..].char_indices() {
+            let index = self.pos + offset;
+            if self.in_string {
+                match ch {
+                    _ if self.escaped => self.escaped = false,
+                    '\\' => self.escaped = true,
+                    '"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match ch {
+                '"' if self.depth > 0 => self.in_string = true,
+                '{' => {
+                    if self.depth == 0 {
+                        self.start = Some(index);
+                    }
+                    self.depth += 1;
+                }
+                '}' if self.depth > 0 => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        if let Some(start) = self.start.take() {
+                            objects.push(buffer[start..=index].to_string());
+                            consumed = index + 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let scanned = buffer.len();
+        buffer.drain(..consumed);
+        self.pos = scanned - consumed;
+        if let Some(start) = self.start.as_mut() {
+            *start -= consumed;
+        }
+        objects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parser(kind: &str, response: Option<StreamResponseConfig>) -> StreamParser {
+        StreamParser::new(&ProviderStreamConfig { kind: kind.to_string(), response }).unwrap()
+    }
+
+    // 按给定的分片依次输入，模拟网络分块
+    fn feed_all(parser: &mut StreamParser, chunks: &[&str]) -> Vec<ProviderEvent> {
+        let mut events: Vec<ProviderEvent> = chunks.iter().flat_map(|chunk| parser.feed(chunk)).collect();
+        events.extend(parser.finish());
+        events
+    }
+
+    fn content(text: &str) -> ProviderEvent {
+        ProviderEvent::ContentDelta { text: text.to_string() }
+    }
+
+    #[test]
+    fn openai_stream() {
+        let stream = concat!(
+            ": keep-alive\n\n",
+            "data: {\"choices\":[{\"delta\":{\"reasoning_content\":\"想\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\r\n\r\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"function\":{\"name\":\"search\",\"arguments\":\"{\\\"q\\\"\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":5,\"total_tokens\":8}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let (head, tail) = stream.split_at(70);
+        let events = feed_all(&mut parser("openai", None), &[head, tail]);
+        assert_eq!(events, vec![
+            ProviderEvent::ReasoningDelta { text: "想".to_string() },
+            content("Hel"),
+            ProviderEvent::ToolCallDelta {
+                index: 0,
+                id: Some("call_1".to_string()),
+                name: Some("search".to_string()),
+                arguments: Some("{\"q\"".to_string()),
+            },
+            ProviderEvent::Usage { input_tokens: Some(3), output_tokens: Some(5), total_tokens: Some(8) },
+            ProviderEvent::Finish { reason: "tool_calls".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn anthropic_stream() {
+        let stream = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":10,\"output_tokens\":1}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"嗯\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":2,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"search\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":2,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{}\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":7}}\n\n",
+        );
+        let events = feed_all(&mut parser("anthropic", None), &[stream]);
+        assert_eq!(events, vec![
+            ProviderEvent::Usage { input_tokens: Some(10), output_tokens: Some(1), total_tokens: None },
+            ProviderEvent::ReasoningDelta { text: "嗯".to_string() },
+            content("Hi"),
+            ProviderEvent::ToolCallDelta { index: 2, id: Some("toolu_1".to_string()), name: Some("search".to_string()), arguments: None },
+            ProviderEvent::ToolCallDelta { index: 2, id: None, name: None, arguments: Some("{}".to_string()) },
+            ProviderEvent::Usage { input_tokens: None, output_tokens: Some(7), total_tokens: None },
+            ProviderEvent::Finish { reason: "end_turn".to_string() },
+        ]);
+
+        let events = feed_all(&mut parser("anthropic", None), &["event: error\ndata: {\"type\":\"error\",\"error\":{\"message\":\"overloaded\"}}\n\n"]);
+        assert_eq!(events, vec![ProviderEvent::Error { message: "overloaded".to_string() }]);
+    }
+
+    #[test]
+    fn gemini_json_array_and_sse() {
+        let array = r#"[{"candidates":[{"content":{"parts":[{"text":"思考","thought":true},{"text":"a]b{"}]}}]}
+,{"candidates":[{"content":{"parts":[{"functionCall":{"name":"f","args":{"x":1}}}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":1,"candidatesTokenCount":2,"totalTokenCount":3}}]"#;
+        let expected = vec![
+            ProviderEvent::ReasoningDelta { text: "思考".to_string() },
+            content("a]b{"),
+            ProviderEvent::ToolCallDelta { index: 0, id: None, name: Some("f".to_string()), arguments: Some("{\"x\":1}".to_string()) },
+            ProviderEvent::Usage { input_tokens: Some(1), output_tokens: Some(2), total_tokens: Some(3) },
+            ProviderEvent::Finish { reason: "STOP".to_string() },
+        ];
+        // 在字符串内的括号处切分，验证跨块扫描
+        let split = array.find("]b{").unwrap();
+        let events = feed_all(&mut parser("gemini", None), &[&array[..split], &array[split..]]);
+        assert_eq!(events, expected);
+
+        let sse = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"x\"}]}}]}\n\n";
+        assert_eq!(feed_all(&mut parser("gemini", None), &[sse]), vec![content("x")]);
+    }
+
+    #[test]
+    fn custom_stream() {
+        let response = StreamResponseConfig {
+            format: Some("sse".to_string()),
+            data_prefix: Some("data:".to_string()),
+            content_path: "output.text".to_string(),
+            reasoning_path: Some("output.reasoning".to_string()),
+            finish_condition: Some("END".to_string()),
+        };
+        let stream = "data:{\"output\":{\"reasoning\":\"r\",\"text\":\"t\"}}\n\ndata: END\n\n";
+        assert_eq!(feed_all(&mut parser("custom", Some(response.clone())), &[stream]), vec![
+            ProviderEvent::ReasoningDelta { text: "r".to_string() },
+            content("t"),
+            ProviderEvent::Finish { reason: "stop".to_string() },
+        ]);
+
+        // NDJSON：没有data前缀的逐行JSON
+        let ndjson = "{\"output\":{\"text\":\"a\"}}\n{\"output\":{\"text\":\"b\"}}";
+        let response = StreamResponseConfig { format: None, ..response };
+        assert_eq!(feed_all(&mut parser("custom", Some(response)), &[ndjson]), vec![content("a"), content("b")]);
+
+        assert!(StreamParser::new(&ProviderStreamConfig { kind: "custom".to_string(), response: None }).is_err());
+        assert!(StreamParser::new(&ProviderStreamConfig { kind: "other".to_string(), response: None }).is_err());
+    }
+
+    #[test]
+    fn sse_multiline_data_and_parse_errors() {
+        // 多行data按换行拼接后作为一个JSON解析
+        let stream = "data: {\"choices\":[{\"delta\":\ndata: {\"content\":\"hi\"}}]}\n\ndata: {broken\n\n";
+        let events = feed_all(&mut parser("openai", None), &[stream]);
+        assert_eq!(events[0], content("hi"));
+        assert!(matches!(&events[1], ProviderEvent::Error { message } if message.starts_with("无法解析流式数据")));
+        assert_eq!(events.len(), 2);
+
+        // 流结束时没有结尾空行的事件也会被处理
+        let events = feed_all(&mut parser("openai", None), &["data: {\"choices\":[{\"delta\":{\"content\":\"end\"}}]}"]);
+        assert_eq!(events, vec![content("end")]);
+    }
+
+    #[test]
+    fn json_path_lookup() {
+        let value = json!({ "choices": [{ "delta": { "content": "x" } }], "grid": [[1, 2], [3, 4]] });
+        assert_eq!(json_path(&value, "choices[0].delta.content"), Some(&json!("x")));
+        assert_eq!(json_path(&value, "choices.0.delta.content"), Some(&json!("x")));
+        assert_eq!(json_path(&value, "grid[1][0]"), Some(&json!(3)));
+        assert_eq!(json_path(&value, ""), Some(&value));
+        assert_eq!(json_path(&value, "choices[1]"), None);
+        assert_eq!(json_path(&value, "choices[x]"), None);
+        assert_eq!(json_path(&value, "missing.key"), None);
+    }
+
+    #[test]
+    fn json_array_scanner_handles_split_objects() {
+        let mut scanner = JsonArrayScanner::default();
+        let mut buffer = String::new();
+        let mut objects = Vec::new();
+        for chunk in ["[{\"a\":\"}\\\"", "{\"}", ",{\"b\":{\"c\":1}", "}]"] {
+            buffer.push_str(chunk);
+            objects.extend(scanner.drain_objects(&mut buffer));
+        }
+        assert_eq!(objects, vec!["{\"a\":\"}\\\"{\"}", "{\"b\":{\"c\":1}}"]);
+        assert_eq!(buffer, "]");
+    }
+}
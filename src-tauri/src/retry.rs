@@ -0,0 +1,246 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// 请求重试策略，由前端按提供商配置传入
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub max_attempts: u32, // 包含首次请求在内的最大尝试次数
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub multiplier: f64,
+    pub jitter: f64, // 抖动比例，0.2 表示在退避时间上下浮动20%
+    pub retry_on_status: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+            retry_on_status: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 不重试的策略，未配置重试时使用
+    pub fn none() -> Self {
+        Self { max_attempts: 1, ..Self::default() }
+    }
+
+    /// 第 `retry` 次重试前的退避时间（从1开始），指数增长并加入随机抖动
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(32) as i32;
+        let base = (self.initial_backoff_ms as f64 * self.multiplier.max(1.0).powi(exponent))
+            .min(self.max_backoff_ms as f64);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter)
+        } else {
+            1.0
+        };
+        Duration::from_millis((base * factor).min(self.max_backoff_ms as f64) as u64)
+    }
+
+    fn should_retry_status(&self, status: u16) -> bool {
+        self.retry_on_status.contains(&status)
+    }
+}
+
+/// 重试事件，供前端显示“正在重试 (2/5)”
+#[derive(Debug, Clone, Serialize)]
+pub struct RetryAttempt {
+    pub attempt: u32, // 即将进行的尝试序号
+    pub max_attempts: u32,
+    pub delay_ms: u64,
+    pub reason: String,
+}
+
+/// 连接失败、超时和连接重置等发送阶段的错误可以重试
+fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+/// 解析 Retry-After 响应头，支持秒数和HTTP日期两种格式
+pub fn parse_retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+    Some((date - Utc::now()).to_std().unwrap_or_default())
+}
+
+/// 按重试策略发送请求
+///
+/// 只在收到响应头之前重试，返回的响应可能仍是错误状态码，由调用方处理。
+/// 请求体无法克隆时只发送一次。
+pub async fn send_with_retry(
+    policy: &RetryPolicy,
+    request: RequestBuilder,
+    mut on_retry: impl FnMut(&RetryAttempt),
+) -> Result<Response, reqwest::Error> {
+    let max_attempts = policy.max_attempts.max(1);
+    let max_delay = Duration::from_millis(policy.max_backoff_ms);
+    let mut attempt = 1;
+
+    loop {
+        // 最后一次尝试或请求体无法克隆时直接发送
+        let current = match (attempt < max_attempts).then(|| request.try_clone()).flatten() {
+            Some(current) => current,
+            None => return request.send().await,
+        };
+
+        let (reason, delay) = match current.send().await {
+            Ok(response) if policy.should_retry_status(response.status().as_u16()) => {
+                let reason = format!("HTTP {}", response.status().as_u16());
+                match parse_retry_after(&response) {
+                    // 服务端要求的等待时间超过上限时不再重试
+                    Some(delay) if delay > max_delay => {
+                        warn!("Retry-After {:?} 超过最大退避时间，停止重试", delay);
+                        return Ok(response);
+                    }
+                    Some(delay) => (reason, delay),
+                    None => (reason, policy.backoff(attempt)),
+                }
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if is_retryable_error(&e) => (e.to_string(), policy.backoff(attempt)),
+            Err(e) => return Err(e),
+        };
+
+        attempt += 1;
+        let retry = RetryAttempt {
+            attempt,
+            max_attempts,
+            delay_ms: delay.as_millis() as u64,
+            reason,
+        };
+        debug!("请求失败 ({})，{}ms 后进行第 {}/{} 次尝试", retry.reason, retry.delay_ms, attempt, max_attempts);
+        on_retry(&retry);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 启动本地HTTP桩服务器，按顺序返回给定的响应，返回地址和请求计数
+    async fn stub_server(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&count);
+
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = [0u8; 4096];
+                let _ = socket.read(&mut buffer).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        (address, count)
+    }
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff_ms: 10,
+            max_backoff_ms: 100,
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+    const UNAVAILABLE: &str = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const RATE_LIMITED: &str = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const RATE_LIMITED_LONG: &str = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 120\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const BAD_REQUEST: &str = "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+    #[test]
+    fn backoff_grows_exponentially_and_is_capped() {
+        let policy = RetryPolicy { jitter: 0.0, ..RetryPolicy::default() };
+        assert_eq!(policy.backoff(1), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(2), Duration::from_millis(2000));
+        assert_eq!(policy.backoff(3), Duration::from_millis(4000));
+        assert_eq!(policy.backoff(20), Duration::from_millis(30_000));
+
+        let jittered = RetryPolicy::default();
+        for _ in 0..100 {
+            let delay = jittered.backoff(1).as_millis();
+            assert!((800..=1200).contains(&delay));
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_retryable_status_until_success() {
+        let (address, count) = stub_server(vec![UNAVAILABLE, RATE_LIMITED, OK]).await;
+        let mut retries = Vec::new();
+
+        let response = send_with_retry(&fast_policy(5), reqwest::Client::new().post(&address).body("x"), |retry| {
+            retries.push(retry.clone());
+        }).await.unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        assert_eq!(retries.iter().map(|r| r.attempt).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(retries[0].reason, "HTTP 503");
+        // Retry-After: 0 优先于退避时间
+        assert_eq!(retries[1].delay_ms, 0);
+    }
+
+    #[tokio::test]
+    async fn returns_last_response_when_attempts_exhausted() {
+        let (address, count) = stub_server(vec![UNAVAILABLE, UNAVAILABLE]).await;
+        let response = send_with_retry(&fast_policy(2), reqwest::Client::new().get(&address), |_| {})
+            .await.unwrap();
+
+        assert_eq!(response.status().as_u16(), 503);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors_or_long_retry_after() {
+        let (address, count) = stub_server(vec![BAD_REQUEST]).await;
+        let response = send_with_retry(&fast_policy(5), reqwest::Client::new().get(&address), |_| {})
+            .await.unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let (address, count) = stub_server(vec![RATE_LIMITED_LONG]).await;
+        let response = send_with_retry(&fast_policy(5), reqwest::Client::new().get(&address), |_| {})
+            .await.unwrap();
+        assert_eq!(response.status().as_u16(), 429);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_connection_errors() {
+        // 先占用端口再释放，得到一个无人监听的地址
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let mut attempts = 0;
+        let result = send_with_retry(&fast_policy(3), reqwest::Client::new().get(&address), |_| attempts += 1).await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 2);
+    }
+}
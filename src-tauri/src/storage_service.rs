@@ -1,9 +1,11 @@
-use crate::database::{Database, AIProvider, ProxySettings, Agent, AgentSession, Message, Scene, SceneSession, SceneMessage, MCPServerConfig, AppSettings};
+use crate::crypto::{redact, REDACTED_PREFIX};
+use crate::database::{Database, AIProvider, ProxySettings, Agent, AgentSession, Message, Scene, SceneSession, SceneMessage, MCPServerConfig, AppSettings, MessageSearchHit, WorkspaceRoot, PermissionGrant};
 use sqlx::{Row, Error as SqlxError};
 use serde_json;
 use chrono::Utc;
 use uuid::Uuid;
 use std::sync::Arc;
+use tracing::warn;
 
 pub struct StorageService {
     db: Arc<Database>,
@@ -22,11 +24,18 @@ impl StorageService {
 
         let mut providers = Vec::new();
         for row in rows {
+            let id: String = row.get("id");
+            let api_key: String = row.get("api_key");
+            // 主密钥丢失或更换后无法解密，清空该提供商的密钥让用户重新填写，不影响其他提供商
+            let api_key = self.db.cipher().decrypt(&api_key).unwrap_or_else(|e| {
+                warn!("提供商 {} 的API密钥无法解密，已按未设置处理: {}", id, e);
+                String::new()
+            });
             providers.push(AIProvider {
-                id: row.get("id"),
+                id,
                 name: row.get("name"),
                 api_endpoint: row.get("api_endpoint"),
-                api_key: row.get("api_key"),
+                api_key,
                 models: row.get("models"),
                 default_model_id: row.get("default_model_id"),
                 custom_config: row.get("custom_config"),
@@ -41,6 +50,18 @@ impl StorageService {
     }
 
     pub async fn save_provider(&self, provider: &AIProvider) -> Result<(), SqlxError> {
+        // 前端回传脱敏值时保留已存储的密钥，提供商不存在时无法还原密钥
+        let api_key = if provider.api_key.starts_with(REDACTED_PREFIX) {
+            sqlx::query("SELECT api_key FROM ai_providers WHERE id = ?")
+                .bind(&provider.id)
+                .fetch_optional(self.db.pool())
+                .await?
+                .map(|row| row.get::<String, _>("api_key"))
+                .ok_or_else(|| SqlxError::Protocol(format!("提供商 {} 不存在，无法保留脱敏的API密钥", provider.id)))?
+        } else {
+            self.db.cipher().encrypt(&provider.api_key).map_err(SqlxError::Protocol)?
+        };
+
         sqlx::query(r#"
             INSERT OR REPLACE INTO ai_providers 
             (id, name, api_endpoint, api_key, models, default_model_id, custom_config, 
@@ -50,7 +71,7 @@ impl StorageService {
         .bind(&provider.id)
         .bind(&provider.name)
         .bind(&provider.api_endpoint)
-        .bind(&provider.api_key)
+        .bind(&api_key)
         .bind(&provider.models)
         .bind(&provider.default_model_id)
         .bind(&provider.custom_config)
@@ -64,6 +85,14 @@ impl StorageService {
         Ok(())
     }
 
+    pub async fn get_providers_redacted(&self) -> Result<Vec<AIProvider>, SqlxError> {
+        let mut providers = self.get_providers().await?;
+        for provider in &mut providers {
+            provider.api_key = redact(&provider.api_key);
+        }
+        Ok(providers)
+    }
+
     pub async fn delete_provider(&self, id: &str) -> Result<(), SqlxError> {
         sqlx::query("DELETE FROM ai_providers WHERE id = ?")
             .bind(id)
@@ -87,7 +116,8 @@ impl StorageService {
                 port: row.get("port"),
                 requires_auth: row.get("requires_auth"),
                 username: row.get("username"),
-                password: row.get("password"),
+                password: self.db.cipher().decrypt(&row.get::<String, _>("password")).map_err(SqlxError::Protocol)?,
+                routing: self.db.cipher().decrypt_opt(&row.get("routing")).map_err(SqlxError::Protocol)?,
                 updated_at: row.get("updated_at"),
             }))
         } else {
@@ -101,8 +131,8 @@ impl StorageService {
         
         sqlx::query(r#"
             INSERT INTO proxy_settings 
-            (enabled, proxy_type, host, port, requires_auth, username, password, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            (enabled, proxy_type, host, port, requires_auth, username, password, routing, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#)
         .bind(&settings.enabled)
         .bind(&settings.proxy_type)
@@ -110,7 +140,8 @@ impl StorageService {
         .bind(&settings.port)
         .bind(&settings.requires_auth)
         .bind(&settings.username)
-        .bind(&settings.password)
+        .bind(self.db.cipher().encrypt(&settings.password).map_err(SqlxError::Protocol)?)
+        .bind(self.db.cipher().encrypt_opt(&settings.routing).map_err(SqlxError::Protocol)?)
         .bind(&settings.updated_at)
         .execute(self.db.pool())
         .await?;
@@ -147,10 +178,16 @@ impl StorageService {
 
     pub async fn save_agent(&self, agent: &Agent) -> Result<(), SqlxError> {
         sqlx::query(r#"
-            INSERT OR REPLACE INTO agents 
+            INSERT INTO agents 
             (id, name, description, system_prompt, provider_id, model_id, keep_history, 
              max_history_messages, icon, is_stream_mode, temperature, settings, created_at, updated_at)
             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name, description = excluded.description, system_prompt = excluded.system_prompt,
+                provider_id = excluded.provider_id, model_id = excluded.model_id, keep_history = excluded.keep_history,
+                max_history_messages = excluded.max_history_messages, icon = excluded.icon,
+                is_stream_mode = excluded.is_stream_mode, temperature = excluded.temperature,
+                settings = excluded.settings, updated_at = excluded.updated_at
         "#)
         .bind(&agent.id)
         .bind(&agent.name)
@@ -198,11 +235,33 @@ impl StorageService {
         Ok(sessions)
     }
 
+    pub async fn get_agent_sessions_by_agent(&self, agent_id: &str) -> Result<Vec<AgentSession>, SqlxError> {
+        let rows = sqlx::query("SELECT * FROM agent_sessions WHERE agent_id = ? ORDER BY updated_at DESC")
+            .bind(agent_id)
+            .fetch_all(self.db.pool())
+            .await?;
+
+        let mut sessions = Vec::new();
+        for row in rows {
+            sessions.push(AgentSession {
+                id: row.get("id"),
+                agent_id: row.get("agent_id"),
+                name: row.get("name"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+            });
+        }
+        Ok(sessions)
+    }
+
     pub async fn save_agent_session(&self, session: &AgentSession) -> Result<(), SqlxError> {
+        // 不能用 INSERT OR REPLACE：替换会先删除旧行，级联删除会话下的所有消息
         sqlx::query(r#"
-            INSERT OR REPLACE INTO agent_sessions 
+            INSERT INTO agent_sessions 
             (id, agent_id, name, created_at, updated_at)
             VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                agent_id = excluded.agent_id, name = excluded.name, updated_at = excluded.updated_at
         "#)
         .bind(&session.id)
         .bind(&session.agent_id)
@@ -250,11 +309,18 @@ impl StorageService {
     }
 
     pub async fn save_message(&self, message: &Message) -> Result<(), SqlxError> {
+        // 更新已有消息走UPDATE触发器，保持全文索引中的文档ID不变
         sqlx::query(r#"
-            INSERT OR REPLACE INTO messages 
+            INSERT INTO messages 
             (id, session_id, content, role, timestamp, streaming, canceled, reasoning_content, 
              reasoning_collapsed, generation_start_time, generation_end_time, generation_duration)
             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                session_id = excluded.session_id, content = excluded.content, role = excluded.role,
+                timestamp = excluded.timestamp, streaming = excluded.streaming, canceled = excluded.canceled,
+                reasoning_content = excluded.reasoning_content, reasoning_collapsed = excluded.reasoning_collapsed,
+                generation_start_time = excluded.generation_start_time,
+                generation_end_time = excluded.generation_end_time, generation_duration = excluded.generation_duration
         "#)
         .bind(&message.id)
         .bind(&message.session_id)
@@ -304,9 +370,12 @@ impl StorageService {
 
     pub async fn save_scene(&self, scene: &Scene) -> Result<(), SqlxError> {
         sqlx::query(r#"
-            INSERT OR REPLACE INTO scenes 
+            INSERT INTO scenes 
             (id, name, description, scenario_prompt, participants, created_at, updated_at)
             VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name, description = excluded.description, scenario_prompt = excluded.scenario_prompt,
+                participants = excluded.participants, updated_at = excluded.updated_at
         "#)
         .bind(&scene.id)
         .bind(&scene.name)
@@ -348,11 +417,35 @@ impl StorageService {
         Ok(sessions)
     }
 
+    pub async fn get_scene_sessions_by_scene(&self, scene_id: &str) -> Result<Vec<SceneSession>, SqlxError> {
+        let rows = sqlx::query("SELECT * FROM scene_sessions WHERE scene_id = ? ORDER BY updated_at DESC")
+            .bind(scene_id)
+            .fetch_all(self.db.pool())
+            .await?;
+
+        let mut sessions = Vec::new();
+        for row in rows {
+            sessions.push(SceneSession {
+                id: row.get("id"),
+                scene_id: row.get("scene_id"),
+                name: row.get("name"),
+                is_active: row.get("is_active"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+            });
+        }
+        Ok(sessions)
+    }
+
     pub async fn save_scene_session(&self, session: &SceneSession) -> Result<(), SqlxError> {
+        // 与智能体会话相同，替换旧行会级联删除会话下的场景消息
         sqlx::query(r#"
-            INSERT OR REPLACE INTO scene_sessions 
+            INSERT INTO scene_sessions 
             (id, scene_id, name, is_active, created_at, updated_at)
             VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                scene_id = excluded.scene_id, name = excluded.name, is_active = excluded.is_active,
+                updated_at = excluded.updated_at
         "#)
         .bind(&session.id)
         .bind(&session.scene_id)
@@ -373,6 +466,19 @@ impl StorageService {
         Ok(())
     }
 
+    pub async fn set_scene_session_active(&self, id: &str, is_active: bool) -> Result<(), SqlxError> {
+        let result = sqlx::query("UPDATE scene_sessions SET is_active = ?, updated_at = ? WHERE id = ?")
+            .bind(is_active)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(self.db.pool())
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(SqlxError::Protocol(format!("场景会话 {} 不存在", id)));
+        }
+        Ok(())
+    }
+
     // Scene Messages
     pub async fn get_scene_messages(&self, session_id: &str) -> Result<Vec<SceneMessage>, SqlxError> {
         let rows = sqlx::query("SELECT * FROM scene_messages WHERE session_id = ? ORDER BY timestamp ASC")
@@ -398,9 +504,13 @@ impl StorageService {
 
     pub async fn save_scene_message(&self, message: &SceneMessage) -> Result<(), SqlxError> {
         sqlx::query(r#"
-            INSERT OR REPLACE INTO scene_messages 
+            INSERT INTO scene_messages 
             (id, session_id, participant_id, agent_id, role, content, timestamp, metadata)
             VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                session_id = excluded.session_id, participant_id = excluded.participant_id,
+                agent_id = excluded.agent_id, role = excluded.role, content = excluded.content,
+                timestamp = excluded.timestamp, metadata = excluded.metadata
         "#)
         .bind(&message.id)
         .bind(&message.session_id)
@@ -415,6 +525,113 @@ impl StorageService {
         Ok(())
     }
 
+    pub async fn update_scene_message_content(&self, id: &str, content: &str) -> Result<(), SqlxError> {
+        let result = sqlx::query("UPDATE scene_messages SET content = ? WHERE id = ?")
+            .bind(content)
+            .bind(id)
+            .execute(self.db.pool())
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(SqlxError::Protocol(format!("场景消息 {} 不存在", id)));
+        }
+        Ok(())
+    }
+
+    pub async fn delete_scene_message(&self, id: &str) -> Result<(), SqlxError> {
+        sqlx::query("DELETE FROM scene_messages WHERE id = ?")
+            .bind(id)
+            .execute(self.db.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Full-text search
+    pub async fn search_messages(&self, query: &str, limit: i64) -> Result<Vec<MessageSearchHit>, SqlxError> {
+        // trigram分词器无法匹配少于3个字符的词，这类词退化为LIKE子串匹配
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        let (match_terms, like_terms): (Vec<&str>, Vec<&str>) = terms.iter().partition(|t| t.chars().count() >= 3);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let use_match = !match_terms.is_empty();
+        let mut conditions = vec!["(m.id IS NOT NULL OR sm.id IS NOT NULL)".to_string()];
+        if use_match {
+            conditions.push("search_index MATCH ?".to_string());
+        }
+        for _ in &like_terms {
+            conditions.push(r"(search_index.content LIKE ? ESCAPE '\' OR search_index.reasoning_content LIKE ? ESCAPE '\')".to_string());
+        }
+
+        // LIKE匹配时取出正文和推理内容，在命中的一列上截取片段
+        let (snippet_expr, reasoning_expr, rank_expr, order_by) = if use_match {
+            ("snippet(search_index, -1, '[', ']', '…', 32)", "NULL", "bm25(search_index)", "rank ASC")
+        } else {
+            ("search_index.content", "search_index.reasoning_content", "0.0", "timestamp DESC")
+        };
+
+        let sql = format!(r#"
+            SELECT d.source, d.message_id,
+                   COALESCE(m.session_id, sm.session_id) AS session_id,
+                   COALESCE(s.agent_id, sm.agent_id) AS agent_id,
+                   ss.scene_id AS scene_id,
+                   COALESCE(m.role, sm.role) AS role,
+                   COALESCE(m.timestamp, sm.timestamp) AS timestamp,
+                   {} AS snippet,
+                   {} AS reasoning,
+                   {} AS rank
+            FROM search_index
+            JOIN search_documents d ON d.id = search_index.rowid
+            LEFT JOIN messages m ON d.source = 'chat' AND m.id = d.message_id
+            LEFT JOIN agent_sessions s ON s.id = m.session_id
+            LEFT JOIN scene_messages sm ON d.source = 'scene' AND sm.id = d.message_id
+            LEFT JOIN scene_sessions ss ON ss.id = sm.session_id
+            WHERE {}
+            ORDER BY {}
+            LIMIT ?
+        "#, snippet_expr, reasoning_expr, rank_expr, conditions.join(" AND "), order_by);
+
+        let mut q = sqlx::query(&sql);
+        if use_match {
+            // 每个词用双引号包裹，避免用户输入被解析为FTS5查询语法
+            let expr = match_terms.iter()
+                .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+                .collect::<Vec<_>>()
+                .join(" ");
+            q = q.bind(expr);
+        }
+        for term in &like_terms {
+            let pattern = format!("%{}%", term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+            q = q.bind(pattern.clone()).bind(pattern);
+        }
+        let rows = q.bind(limit).fetch_all(self.db.pool()).await?;
+
+        let mut hits = Vec::new();
+        for row in rows {
+            let mut snippet: String = row.get("snippet");
+            if !use_match {
+                let reasoning: Option<String> = row.get("reasoning");
+                snippet = match reasoning {
+                    Some(reasoning) if find_ignore_case(&snippet, like_terms[0]).is_none()
+                        && find_ignore_case(&reasoning, like_terms[0]).is_some() => make_snippet(&reasoning, like_terms[0], 32),
+                    _ => make_snippet(&snippet, like_terms[0], 32),
+                };
+            }
+            hits.push(MessageSearchHit {
+                source: row.get("source"),
+                message_id: row.get("message_id"),
+                session_id: row.get("session_id"),
+                agent_id: row.get("agent_id"),
+                scene_id: row.get("scene_id"),
+                role: row.get("role"),
+                snippet,
+                timestamp: row.get("timestamp"),
+                rank: row.get("rank"),
+            });
+        }
+        Ok(hits)
+    }
+
     // MCP Server Configs
     pub async fn get_mcp_server_configs(&self) -> Result<Vec<MCPServerConfig>, SqlxError> {
         let rows = sqlx::query("SELECT * FROM mcp_server_configs ORDER BY created_at ASC")
@@ -432,7 +649,7 @@ impl StorageService {
                 server_class: row.get("server_class"),
                 command: row.get("command"),
                 args: row.get("args"),
-                env: row.get("env"),
+                env: self.db.cipher().decrypt_opt(&row.get("env")).map_err(SqlxError::Protocol)?,
                 capabilities: row.get("capabilities"),
                 permissions: row.get("permissions"),
                 created_at: row.get("created_at"),
@@ -457,7 +674,7 @@ impl StorageService {
         .bind(&config.server_class)
         .bind(&config.command)
         .bind(&config.args)
-        .bind(&config.env)
+        .bind(self.db.cipher().encrypt_opt(&config.env).map_err(SqlxError::Protocol)?)
         .bind(&config.capabilities)
         .bind(&config.permissions)
         .bind(&config.created_at)
@@ -475,6 +692,105 @@ impl StorageService {
         Ok(())
     }
 
+    // Workspace Roots
+    pub async fn get_workspace_roots(&self) -> Result<Vec<WorkspaceRoot>, SqlxError> {
+        let rows = sqlx::query("SELECT * FROM workspace_roots ORDER BY created_at ASC")
+            .fetch_all(self.db.pool())
+            .await?;
+
+        Ok(rows.iter().map(workspace_root_from_row).collect())
+    }
+
+    pub async fn get_workspace_root(&self, id: &str) -> Result<Option<WorkspaceRoot>, SqlxError> {
+        let row = sqlx::query("SELECT * FROM workspace_roots WHERE id = ?")
+            .bind(id)
+            .fetch_optional(self.db.pool())
+            .await?;
+
+        Ok(row.as_ref().map(workspace_root_from_row))
+    }
+
+    pub async fn save_workspace_root(&self, root: &WorkspaceRoot) -> Result<(), SqlxError> {
+        sqlx::query(r#"
+            INSERT OR REPLACE INTO workspace_roots (id, name, path, mode, policy, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#)
+        .bind(&root.id)
+        .bind(&root.name)
+        .bind(&root.path)
+        .bind(&root.mode)
+        .bind(&root.policy)
+        .bind(root.created_at)
+        .bind(root.updated_at)
+        .execute(self.db.pool())
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_workspace_root(&self, id: &str) -> Result<(), SqlxError> {
+        sqlx::query("DELETE FROM workspace_roots WHERE id = ?")
+            .bind(id)
+            .execute(self.db.pool())
+            .await?;
+        Ok(())
+    }
+
+    // Permission Grants
+    pub async fn get_permission_grants(&self) -> Result<Vec<PermissionGrant>, SqlxError> {
+        let rows = sqlx::query("SELECT * FROM permission_grants ORDER BY created_at ASC")
+            .fetch_all(self.db.pool())
+            .await?;
+
+        Ok(rows.iter().map(|row| PermissionGrant {
+            id: row.get("id"),
+            subject_type: row.get("subject_type"),
+            subject_id: row.get("subject_id"),
+            action: row.get("action"),
+            target: row.get("target"),
+            created_at: row.get("created_at"),
+        }).collect())
+    }
+
+    /// 是否存在匹配的长期授权，目标为 "*" 的授权匹配所有目标
+    pub async fn has_permission_grant(&self, subject_type: &str, subject_id: &str, action: &str, target: &str) -> Result<bool, SqlxError> {
+        let row = sqlx::query(r#"
+            SELECT 1 FROM permission_grants
+            WHERE subject_type = ? AND subject_id = ? AND action = ? AND target IN (?, '*')
+            LIMIT 1
+        "#)
+        .bind(subject_type)
+        .bind(subject_id)
+        .bind(action)
+        .bind(target)
+        .fetch_optional(self.db.pool())
+        .await?;
+        Ok(row.is_some())
+    }
+
+    pub async fn save_permission_grant(&self, grant: &PermissionGrant) -> Result<(), SqlxError> {
+        sqlx::query(r#"
+            INSERT OR IGNORE INTO permission_grants (id, subject_type, subject_id, action, target, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+        "#)
+        .bind(&grant.id)
+        .bind(&grant.subject_type)
+        .bind(&grant.subject_id)
+        .bind(&grant.action)
+        .bind(&grant.target)
+        .bind(grant.created_at)
+        .execute(self.db.pool())
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_permission_grant(&self, id: &str) -> Result<(), SqlxError> {
+        sqlx::query("DELETE FROM permission_grants WHERE id = ?")
+            .bind(id)
+            .execute(self.db.pool())
+            .await?;
+        Ok(())
+    }
+
     // App Settings
     pub async fn get_setting(&self, key: &str) -> Result<Option<String>, SqlxError> {
         let row = sqlx::query("SELECT value FROM app_settings WHERE key = ?")
@@ -545,26 +861,7 @@ impl StorageService {
         
         // 批量插入新消息
         for message in messages {
-            sqlx::query(r#"
-                INSERT OR REPLACE INTO messages 
-                (id, session_id, content, role, timestamp, streaming, canceled, reasoning_content, 
-                 reasoning_collapsed, generation_start_time, generation_end_time, generation_duration)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-            "#)
-            .bind(&message.id)
-            .bind(&message.session_id)
-            .bind(&message.content)
-            .bind(&message.role)
-            .bind(&message.timestamp)
-            .bind(&message.streaming)
-            .bind(&message.canceled)
-            .bind(&message.reasoning_content)
-            .bind(&message.reasoning_collapsed)
-            .bind(&message.generation_start_time)
-            .bind(&message.generation_end_time)
-            .bind(&message.generation_duration)
-            .execute(self.db.pool())
-            .await?;
+            self.save_message(message).await?;
         }
         Ok(())
     }
@@ -575,4 +872,217 @@ impl StorageService {
             .await?;
         Ok(())
     }
-} 
\ No newline at end of file
+} 
+
+/// 忽略大小写查找关键词，返回命中部分的字节范围，与LIKE的匹配规则保持一致
+fn find_ignore_case(content: &str, term: &str) -> Option<(usize, usize)> {
+    let term: Vec<char> = term.chars().collect();
+    if term.is_empty() {
+        return None;
+    }
+    content.char_indices().find_map(|(start, _)| {
+        let mut chars = content[start..].char_indices();
+        for expected in &term {
+            let (_, c) = chars.next()?;
+            if !c.to_lowercase().eq(expected.to_lowercase()) {
+                return None;
+            }
+        }
+        let end = chars.next().map_or(content.len(), |(offset, _)| start + offset);
+        Some((start, end))
+    })
+}
+
+/// 截取关键词附近的片段，并用方括号标记命中位置
+fn make_snippet(content: &str, term: &str, context_chars: usize) -> String {
+    let Some((start, end)) = find_ignore_case(content, term) else {
+        return content.chars().take(context_chars * 2).collect();
+    };
+
+    let before: Vec<char> = content[..start].chars().collect();
+    let prefix: String = before[before.len().saturating_sub(context_chars)..].iter().collect();
+    let suffix: String = content[end..].chars().take(context_chars).collect();
+
+    format!(
+        "{}{}[{}]{}{}",
+        if before.len() > context_chars { "…" } else { "" },
+        prefix,
+        &content[start..end],
+        suffix,
+        if content[end..].chars().count() > context_chars { "…" } else { "" },
+    )
+}
+
+fn workspace_root_from_row(row: &sqlx::sqlite::SqliteRow) -> WorkspaceRoot {
+    WorkspaceRoot {
+        id: row.get("id"),
+        name: row.get("name"),
+        path: row.get("path"),
+        mode: row.get("mode"),
+        policy: row.get("policy"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    async fn storage(dir: &std::path::Path) -> StorageService {
+        StorageService::new(Arc::new(Database::open_in_memory(dir).await.unwrap()))
+    }
+
+    fn agent(id: &str) -> Agent {
+        Agent {
+            id: id.to_string(),
+            name: "助手".to_string(),
+            description: String::new(),
+            system_prompt: String::new(),
+            provider_id: "p".to_string(),
+            model_id: "m".to_string(),
+            keep_history: true,
+            max_history_messages: None,
+            icon: None,
+            is_stream_mode: None,
+            temperature: None,
+            settings: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn agent_session(id: &str, agent_id: &str, name: &str) -> AgentSession {
+        AgentSession { id: id.to_string(), agent_id: agent_id.to_string(), name: name.to_string(), created_at: Utc::now(), updated_at: Utc::now() }
+    }
+
+    fn message(id: &str, session_id: &str, content: &str) -> Message {
+        Message {
+            id: id.to_string(),
+            session_id: session_id.to_string(),
+            content: content.to_string(),
+            role: "user".to_string(),
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            streaming: None,
+            canceled: None,
+            reasoning_content: None,
+            reasoning_collapsed: None,
+            generation_start_time: None,
+            generation_end_time: None,
+            generation_duration: None,
+        }
+    }
+
+    fn scene_session(id: &str, name: &str) -> SceneSession {
+        SceneSession { id: id.to_string(), scene_id: "sc".to_string(), name: name.to_string(), is_active: true, created_at: Utc::now(), updated_at: Utc::now() }
+    }
+
+    fn scene_message(id: &str, session_id: &str, content: &str) -> SceneMessage {
+        SceneMessage {
+            id: id.to_string(),
+            session_id: session_id.to_string(),
+            participant_id: "p1".to_string(),
+            agent_id: None,
+            role: "assistant".to_string(),
+            content: content.to_string(),
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            metadata: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn resaving_agent_session_keeps_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        storage.save_agent(&agent("a")).await.unwrap();
+        storage.save_agent_session(&agent_session("s", "a", "新会话")).await.unwrap();
+        storage.save_message(&message("m1", "s", "你好")).await.unwrap();
+        storage.save_message(&message("m2", "s", "在吗")).await.unwrap();
+
+        storage.save_agent(&agent("a")).await.unwrap();
+        storage.save_agent_session(&agent_session("s", "a", "重命名")).await.unwrap();
+
+        assert_eq!(storage.get_agent_sessions_by_agent("a").await.unwrap()[0].name, "重命名");
+        assert_eq!(storage.get_messages("s").await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn snippet_matches_ignoring_case() {
+        assert_eq!(find_ignore_case("Hello World", "world"), Some((6, 11)));
+        assert_eq!(find_ignore_case("使用 Rust 编写", "rust"), Some((7, 11)));
+        assert_eq!(find_ignore_case("abc", "abcd"), None);
+
+        assert_eq!(make_snippet("Say HI to everyone", "hi", 4), "Say [HI] to …");
+        assert_eq!(make_snippet("nothing here", "zz", 3), "nothin");
+    }
+
+    #[tokio::test]
+    async fn resaving_scene_session_keeps_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        let scene = Scene {
+            id: "sc".to_string(),
+            name: "场景".to_string(),
+            description: String::new(),
+            scenario_prompt: String::new(),
+            participants: "[]".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        storage.save_scene(&scene).await.unwrap();
+        storage.save_scene_session(&scene_session("ss", "第一幕")).await.unwrap();
+        storage.save_scene_message(&scene_message("sm", "ss", "开始")).await.unwrap();
+
+        storage.save_scene(&scene).await.unwrap();
+        storage.save_scene_session(&scene_session("ss", "第二幕")).await.unwrap();
+        assert_eq!(storage.get_scene_messages("ss").await.unwrap().len(), 1);
+
+        // 不存在的ID返回错误
+        assert!(storage.set_scene_session_active("missing", false).await.is_err());
+        assert!(storage.update_scene_message_content("missing", "x").await.is_err());
+        storage.set_scene_session_active("ss", false).await.unwrap();
+        storage.update_scene_message_content("sm", "继续").await.unwrap();
+        assert_eq!(storage.get_scene_messages("ss").await.unwrap()[0].content, "继续");
+    }
+
+    #[tokio::test]
+    async fn resaving_message_keeps_one_index_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        storage.save_agent(&agent("a")).await.unwrap();
+        storage.save_agent_session(&agent_session("s", "a", "会话")).await.unwrap();
+        for content in ["流式", "流式输出", "流式输出完成"] {
+            storage.save_message(&message("m", "s", content)).await.unwrap();
+        }
+
+        for table in ["search_index", "search_documents"] {
+            let row = sqlx::query(&format!("SELECT COUNT(*) AS n FROM {}", table)).fetch_one(storage.db.pool()).await.unwrap();
+            assert_eq!(row.get::<i64, _>("n"), 1, "{}", table);
+        }
+        let hits = storage.search_messages("输出完成", 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(storage.search_messages("流式", 10).await.unwrap().iter().all(|hit| hit.message_id == "m"));
+    }
+
+    #[tokio::test]
+    async fn undecryptable_key_is_cleared() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        let other = crate::crypto::SecretCipher::load_or_create(&dir.path().join("other.key")).unwrap();
+        for (id, key) in [("lost", other.encrypt("sk-lost").unwrap()), ("ok", storage.db.cipher().encrypt("sk-ok").unwrap())] {
+            sqlx::query("INSERT INTO ai_providers (id, name, api_endpoint, api_key, models) VALUES (?, ?, '', ?, '[]')")
+                .bind(id)
+                .bind(id)
+                .bind(key)
+                .execute(storage.db.pool())
+                .await
+                .unwrap();
+        }
+
+        let providers = storage.get_providers().await.unwrap();
+        let key = |id: &str| providers.iter().find(|p| p.id == id).unwrap().api_key.clone();
+        assert_eq!(key("lost"), "");
+        assert_eq!(key("ok"), "sk-ok");
+    }
+}
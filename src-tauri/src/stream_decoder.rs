@@ -0,0 +1,189 @@
+/// 增量UTF-8解码器
+///
+/// 网络数据块可能在多字节字符中间截断，不完整的尾部字节保留到下一个数据块再解码。
+/// 无效字节序列替换为U+FFFD，不会丢弃整个数据块。
+#[derive(Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn decode(&mut self, chunk: &[u8]) -> String {
+        self.pending.extend_from_slice(chunk);
+
+        let mut output = String::new();
+        let mut rest: &[u8] = &self.pending;
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    output.push_str(text);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // valid_up_to 之前的字节已验证为UTF-8
+                    output.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            output.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        // 末尾是不完整的字符，等待后续数据
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending = rest.to_vec();
+        output
+    }
+
+    /// 流结束时输出剩余字节，不完整的字符替换为U+FFFD
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        String::from_utf8_lossy(&rest).into_owned()
+    }
+}
+
+/// 流式数据块解码器，只输出完整的行
+///
+/// 在UTF-8解码的基础上缓存不完整的SSE行，保证每个 `data` 事件不会截断在行中间。
+#[derive(Default)]
+pub struct StreamChunkDecoder {
+    utf8: Utf8Decoder,
+    line_buffer: String,
+}
+
+impl StreamChunkDecoder {
+    /// 输入网络数据块，返回其中完整的行（包含换行符），没有完整行时返回None
+    pub fn push(&mut self, chunk: &[u8]) -> Option<String> {
+        self.line_buffer.push_str(&self.utf8.decode(chunk));
+
+        let end = self.line_buffer.rfind('\n')? + 1;
+        let rest = self.line_buffer.split_off(end);
+        Some(std::mem::replace(&mut self.line_buffer, rest))
+    }
+
+    /// 流结束时输出剩余的不完整行
+    pub fn finish(&mut self) -> Option<String> {
+        self.line_buffer.push_str(&self.utf8.finish());
+        if self.line_buffer.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.line_buffer))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{ProviderEvent, ProviderStreamConfig, StreamParser};
+
+    /// 把字节流按指定位置切分后逐块送入解码器
+    fn decode_split(bytes: &[u8], splits: &[usize]) -> Vec<String> {
+        let mut decoder = StreamChunkDecoder::default();
+        let mut output = Vec::new();
+        let mut start = 0;
+        for &split in splits.iter().chain(std::iter::once(&bytes.len())) {
+            if let Some(text) = decoder.push(&bytes[start..split]) {
+                output.push(text);
+            }
+            start = split;
+        }
+        output.extend(decoder.finish());
+        output
+    }
+
+    #[test]
+    fn utf8_decoder_buffers_split_code_points() {
+        let text = "你好🌍";
+        let bytes = text.as_bytes();
+        let mut decoder = Utf8Decoder::default();
+
+        // 在每个字节处切分
+        let mut output = String::new();
+        for byte in bytes {
+            output.push_str(&decoder.decode(std::slice::from_ref(byte)));
+        }
+        output.push_str(&decoder.finish());
+        assert_eq!(output, text);
+    }
+
+    #[test]
+    fn utf8_decoder_holds_incomplete_tail() {
+        let bytes = "中".as_bytes();
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(&bytes[..2]), "");
+        assert_eq!(decoder.decode(&bytes[2..]), "中");
+        assert_eq!(decoder.finish(), "");
+    }
+
+    #[test]
+    fn utf8_decoder_replaces_invalid_bytes() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"a\xffb"), "a\u{FFFD}b");
+        // 流结束时仍不完整的字符
+        assert_eq!(decoder.decode(&"好".as_bytes()[..1]), "");
+        assert_eq!(decoder.finish(), "\u{FFFD}");
+    }
+
+    #[test]
+    fn chunk_decoder_emits_only_complete_lines() {
+        let input = "data: {\"a\":\"中文\"}\n\ndata: [DONE]\n";
+        let bytes = input.as_bytes();
+        // 在"中"的第二个字节和第二行中间切分
+        let first_split = input.find('中').unwrap() + 1;
+        let output = decode_split(bytes, &[first_split, first_split + 10]);
+
+        assert!(output.iter().all(|chunk| chunk.ends_with('\n')));
+        assert_eq!(output.concat(), input);
+    }
+
+    #[test]
+    fn chunk_decoder_flushes_trailing_partial_line() {
+        let output = decode_split("data: 1\ndata: 2".as_bytes(), &[3]);
+        assert_eq!(output, vec!["data: 1\n".to_string(), "data: 2".to_string()]);
+    }
+
+    #[test]
+    fn split_bytes_through_provider_pipeline() {
+        let text = "你好，世界！👋🏽 emoji 測試";
+        let body: String = text.chars()
+            .map(|c| format!("data: {{\"choices\":[{{\"delta\":{{\"content\":\"{}\"}}}}]}}\n\n", c))
+            .collect::<String>() + "data: [DONE]\n\n";
+        let bytes = body.as_bytes();
+
+        // 用多种块大小切分，覆盖所有可能的截断位置
+        for chunk_size in 1..=7 {
+            let config = ProviderStreamConfig { kind: "openai".to_string(), response: None };
+            let mut parser = StreamParser::new(&config).unwrap();
+            let mut decoder = StreamChunkDecoder::default();
+            let mut events = Vec::new();
+
+            for chunk in bytes.chunks(chunk_size) {
+                if let Some(lines) = decoder.push(chunk) {
+                    events.extend(parser.feed(&lines));
+                }
+            }
+            if let Some(rest) = decoder.finish() {
+                events.extend(parser.feed(&rest));
+            }
+            events.extend(parser.finish());
+
+            let content: String = events.iter()
+                .filter_map(|event| match event {
+                    ProviderEvent::ContentDelta { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(content, text, "chunk size {}", chunk_size);
+            assert_eq!(events.last(), Some(&ProviderEvent::Finish { reason: "stop".to_string() }));
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+/// 活动流式请求信息
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveStream {
+    pub stream_id: String,
+    pub url: String,
+    pub started_at: DateTime<Utc>,
+}
+
+struct StreamEntry {
+    info: ActiveStream,
+    token: CancellationToken,
+}
+
+/// 活动流式请求注册表，按 stream_id 保存取消令牌
+#[derive(Default)]
+pub struct StreamRegistry {
+    streams: Mutex<HashMap<String, StreamEntry>>,
+}
+
+impl StreamRegistry {
+    /// 注册新的流式请求，返回的守卫在释放时自动注销
+    pub fn register(self: &Arc<Self>, stream_id: &str, url: &str) -> Result<StreamGuard, String> {
+        let mut streams = self.streams.lock().unwrap();
+        if streams.contains_key(stream_id) {
+            return Err(format!("流式请求已存在: {}", stream_id));
+        }
+
+        let token = CancellationToken::new();
+        streams.insert(stream_id.to_string(), StreamEntry {
+            info: ActiveStream {
+                stream_id: stream_id.to_string(),
+                url: url.to_string(),
+                started_at: Utc::now(),
+            },
+            token: token.clone(),
+        });
+
+        Ok(StreamGuard {
+            registry: Arc::clone(self),
+            stream_id: stream_id.to_string(),
+            token,
+        })
+    }
+
+    /// 取消流式请求，请求不存在时返回false
+    pub fn cancel(&self, stream_id: &str) -> bool {
+        match self.streams.lock().unwrap().get(stream_id) {
+            Some(entry) => {
+                entry.token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<ActiveStream> {
+        let mut streams: Vec<ActiveStream> = self.streams.lock().unwrap()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect();
+        streams.sort_by_key(|s| s.started_at);
+        streams
+    }
+}
+
+/// 流式请求注册守卫，请求结束（包括出错和取消）时从注册表移除
+pub struct StreamGuard {
+    registry: Arc<StreamRegistry>,
+    stream_id: String,
+    token: CancellationToken,
+}
+
+impl StreamGuard {
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.registry.streams.lock().unwrap().remove(&self.stream_id);
+    }
+}
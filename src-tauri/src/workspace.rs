@@ -0,0 +1,248 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use crate::database::WorkspaceRoot;
+use crate::file_policy::{PolicyEngine, BINARY_SNIFF_LEN};
+
+pub const MODE_READ_ONLY: &str = "read_only";
+pub const MODE_READ_WRITE: &str = "read_write";
+
+/// 文件系统操作所需的访问权限
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkspaceAccess {
+    Read,
+    Write,
+}
+
+/// 解析后的工作区路径
+pub struct ResolvedPath {
+    pub root: PathBuf, // 规范化后的工作区根目录
+    pub path: PathBuf, // 规范化后的目标路径
+    pub policy: PolicyEngine,
+}
+
+impl ResolvedPath {
+    /// 目标路径相对于工作区根目录的部分
+    pub fn relative(&self) -> &Path {
+        self.path.strip_prefix(&self.root).unwrap_or(&self.path)
+    }
+
+    /// 读取文件前按策略检查大小和内容类型
+    pub fn check_readable_file(&self) -> Result<(), String> {
+        let metadata = fs::metadata(&self.path).map_err(|e| format!("读取文件信息失败: {}", e))?;
+        if !metadata.is_file() {
+            return Ok(());
+        }
+        self.policy.check_size(self.relative(), metadata.len()).map_err(|v| v.to_string())?;
+
+        let mut head = Vec::with_capacity(BINARY_SNIFF_LEN);
+        fs::File::open(&self.path)
+            .and_then(|file| file.take(BINARY_SNIFF_LEN as u64).read_to_end(&mut head))
+            .map_err(|e| format!("读取文件失败: {}", e))?;
+        self.policy.check_content(self.relative(), &head).map_err(|v| v.to_string())
+    }
+
+    /// 二进制读写前按策略检查文件大小
+    pub fn check_binary_size(&self, size: u64) -> Result<(), String> {
+        self.policy.check_size(self.relative(), size).map_err(|v| v.to_string())
+    }
+
+    /// 写入文件前按策略检查内容
+    pub fn check_writable_content(&self, content: &[u8]) -> Result<(), String> {
+        self.policy.check_size(self.relative(), content.len() as u64).map_err(|v| v.to_string())?;
+        self.policy.check_content(self.relative(), content).map_err(|v| v.to_string())
+    }
+}
+
+/// 校验工作区配置并返回规范化后的根目录，保存工作区前调用
+pub fn normalize_root(root: &WorkspaceRoot) -> Result<PathBuf, String> {
+    let (path, mode) = (&root.path, &root.mode);
+    if mode != MODE_READ_ONLY && mode != MODE_READ_WRITE {
+        return Err(format!("不支持的工作区模式: {}", mode));
+    }
+    PolicyEngine::from_json(root.policy.as_deref())?;
+
+    let canonical = Path::new(path).canonicalize()
+        .map_err(|e| format!("工作区目录无效: {} - {}", path, e))?;
+    if !canonical.is_dir() {
+        return Err(format!("工作区路径不是目录: {}", path));
+    }
+    Ok(canonical)
+}
+
+/// 在工作区内解析相对路径
+///
+/// 对最深的已存在祖先做规范化，再拼接尚不存在的部分，
+/// 因此即使目标文件还不存在，也能识别通过符号链接逃逸出工作区的路径。
+/// 目标本身已存在时只规范化其父目录，返回的路径仍指向最后一级名称，
+/// 这样删除、移动或重命名符号链接时作用于链接本身而不是链接目标。
+pub fn resolve(root: &WorkspaceRoot, relative: &str, access: WorkspaceAccess) -> Result<ResolvedPath, String> {
+    if access == WorkspaceAccess::Write && root.mode != MODE_READ_WRITE {
+        return Err(format!("工作区 {} 为只读模式", root.name));
+    }
+
+    let root_path = Path::new(&root.path).canonicalize()
+        .map_err(|e| format!("工作区目录不可用: {} - {}", root.path, e))?;
+
+    let mut full_path = root_path.clone();
+    for component in Path::new(relative).components() {
+        match component {
+            Component::Normal(name) => full_path.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => return Err("不允许使用 \"..\" 路径".to_string()),
+            Component::RootDir | Component::Prefix(_) => return Err("不允许使用绝对路径".to_string()),
+        }
+    }
+
+    // 找到最深的已存在祖先，symlink_metadata 不跟随链接，失效的符号链接也视为存在
+    let mut existing = full_path;
+    let mut missing: Vec<OsString> = Vec::new();
+    while fs::symlink_metadata(&existing).is_err() {
+        let name = existing.file_name()
+            .ok_or("无法解析路径")?
+            .to_os_string();
+        missing.push(name);
+        existing.pop();
+    }
+
+    // 目标已存在时只规范化父目录，再拼接最后一级名称
+    let mut link_target = None;
+    if missing.is_empty() && existing != root_path {
+        let name = existing.file_name().ok_or("无法解析路径")?.to_os_string();
+        // 符号链接的目标也必须在工作区内，否则读写会跟随链接逃逸
+        let target = existing.canonicalize()
+            .map_err(|e| format!("无法解析路径，可能是失效的符号链接: {}", e))?;
+        if !target.starts_with(&root_path) {
+            return Err("路径超出工作区范围".to_string());
+        }
+        if fs::symlink_metadata(&existing).is_ok_and(|m| m.file_type().is_symlink()) {
+            link_target = Some(target);
+        }
+        missing.push(name);
+        existing.pop();
+    }
+
+    let canonical = existing.canonicalize()
+        .map_err(|e| format!("无法解析路径，可能是失效的符号链接: {}", e))?;
+    if !canonical.starts_with(&root_path) {
+        return Err("路径超出工作区范围".to_string());
+    }
+
+    let path = missing.into_iter().rev().fold(canonical, |path, name| path.join(name));
+    if access == WorkspaceAccess::Write && path == root_path {
+        return Err("不允许修改工作区根目录本身".to_string());
+    }
+
+    // 按规范化后的相对路径检查工作区策略
+    let policy = PolicyEngine::from_json(root.policy.as_deref())?;
+    let resolved = ResolvedPath { root: root_path, path, policy };
+    resolved.policy.check_path(resolved.relative(), resolved.path.is_dir())
+        .map_err(|v| v.to_string())?;
+    // 读写会跟随链接，链接目标同样要符合策略，否则 `ok.txt -> .env` 可绕过拒绝规则
+    if let Some(target) = link_target {
+        let relative = target.strip_prefix(&resolved.root).unwrap_or(&target);
+        resolved.policy.check_path(relative, target.is_dir()).map_err(|v| v.to_string())?;
+    }
+
+    Ok(resolved)
+}
+
+/// 测试用工作区根目录，供各文件系统模块的测试共用
+#[cfg(test)]
+pub(crate) fn test_root(dir: &Path, mode: &str) -> WorkspaceRoot {
+    WorkspaceRoot {
+        id: "root".to_string(),
+        name: "测试".to_string(),
+        path: dir.to_string_lossy().to_string(),
+        mode: mode.to_string(),
+        policy: None,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_parent_and_absolute_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = test_root(dir.path(), MODE_READ_WRITE);
+        let canonical = dir.path().canonicalize().unwrap();
+
+        assert!(resolve(&root, "../outside.txt", WorkspaceAccess::Read).is_err());
+        assert!(resolve(&root, "src/../../outside.txt", WorkspaceAccess::Read).is_err());
+        assert!(resolve(&root, "/etc/passwd", WorkspaceAccess::Read).is_err());
+
+        let resolved = resolve(&root, "./src/new.txt", WorkspaceAccess::Write).unwrap();
+        assert_eq!(resolved.path, canonical.join("src/new.txt"));
+        assert_eq!(resolved.relative(), Path::new("src/new.txt"));
+        assert!(resolve(&root, "", WorkspaceAccess::Write).is_err());
+        assert_eq!(resolve(&root, "", WorkspaceAccess::Read).unwrap().path, canonical);
+    }
+
+    #[test]
+    fn read_only_root_rejects_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let root = test_root(dir.path(), MODE_READ_ONLY);
+
+        assert!(resolve(&root, "a.txt", WorkspaceAccess::Read).is_ok());
+        assert!(matches!(resolve(&root, "a.txt", WorkspaceAccess::Write), Err(e) if e.contains("只读")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_cannot_escape_and_resolve_to_the_link_itself() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let root_dir = dir.path().join("root");
+        fs::create_dir_all(root_dir.join("src")).unwrap();
+        fs::write(root_dir.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        symlink(outside.path(), root_dir.join("escape")).unwrap();
+        symlink(outside.path().join("secret.txt"), root_dir.join("secret.txt")).unwrap();
+        symlink(root_dir.join("src/main.rs"), root_dir.join("main.rs")).unwrap();
+        symlink(root_dir.join("missing"), root_dir.join("dangling")).unwrap();
+        let root = test_root(&root_dir, MODE_READ_WRITE);
+
+        // 通过目录链接或文件链接逃逸都会被拒绝，包括尚不存在的目标
+        assert!(resolve(&root, "escape/secret.txt", WorkspaceAccess::Read).is_err());
+        assert!(resolve(&root, "escape/new.txt", WorkspaceAccess::Write).is_err());
+        assert!(resolve(&root, "secret.txt", WorkspaceAccess::Read).is_err());
+        assert!(resolve(&root, "dangling", WorkspaceAccess::Write).is_err());
+
+        // 指向工作区内的链接解析为链接路径本身，删除或重命名不会作用于目标
+        let resolved = resolve(&root, "main.rs", WorkspaceAccess::Write).unwrap();
+        assert_eq!(resolved.path, root_dir.canonicalize().unwrap().join("main.rs"));
+        assert!(fs::symlink_metadata(&resolved.path).unwrap().file_type().is_symlink());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_targets_are_checked_against_policy() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".secrets")).unwrap();
+        fs::write(dir.path().join(".env"), "TOKEN=1").unwrap();
+        fs::write(dir.path().join("key.pem"), "key").unwrap();
+        fs::write(dir.path().join("notes.txt"), "notes").unwrap();
+        symlink(dir.path().join(".env"), dir.path().join("ok.txt")).unwrap();
+        symlink(dir.path().join("key.pem"), dir.path().join("cert.txt")).unwrap();
+        symlink(dir.path().join(".secrets"), dir.path().join("public")).unwrap();
+        symlink(dir.path().join("notes.txt"), dir.path().join("readme.txt")).unwrap();
+        let mut root = test_root(dir.path(), MODE_READ_WRITE);
+        root.policy = Some(r#"{"deny": ["*.pem"]}"#.to_string());
+
+        for path in ["ok.txt", "cert.txt", "public"] {
+            assert!(matches!(resolve(&root, path, WorkspaceAccess::Read), Err(e) if e.contains("策略")), "{}", path);
+        }
+        assert!(resolve(&root, "readme.txt", WorkspaceAccess::Read).is_ok());
+    }
+}
@@ -944,7 +944,8 @@ class AIService {
       method: (options.method as string) || 'POST',
       headers: options.headers as Record<string, string>,
       body: options.body as string,
-      proxySettings: proxySettings.enabled ? proxySettings : undefined
+      proxySettings: proxySettings.enabled ? proxySettings : undefined,
+      providerId: provider.id
     });
 
     if (!httpResponse.success) {
@@ -1730,6 +1731,7 @@ class AIService {
       headers: options.headers as Record<string, string>,
       body: options.body as string,
       proxySettings: proxySettings.enabled ? proxySettings : undefined,
+      providerId: provider.id,
       onData: (chunk: string) => {
         // 根据用户配置的格式解析流式响应
         // 修复：如果没有format字段或者数据看起来像SSE，则使用SSE解析
@@ -67,7 +67,8 @@ class APIAutoFetchService {
   private async makeRequest(
     endpoint: string,
     method: 'GET' | 'POST',
-    headers: Record<string, string>
+    headers: Record<string, string>,
+    providerId?: string
   ): Promise<Record<string, unknown>> {
     try {
       // 获取代理设置
@@ -80,7 +81,8 @@ class APIAutoFetchService {
           'Content-Type': 'application/json',
           ...headers,
         },
-        proxySettings: proxySettings.enabled ? proxySettings : undefined
+        proxySettings: proxySettings.enabled ? proxySettings : undefined,
+        providerId
       });
 
       if (!response.success) {
@@ -117,7 +119,7 @@ class APIAutoFetchService {
       const headers = this.buildHeaders(config.headers, provider.apiKey);
       
       // 发送请求
-      const response = await this.makeRequest(endpoint, config.method || 'GET', headers);
+      const response = await this.makeRequest(endpoint, config.method || 'GET', headers, provider.id);
       
       // 提取模型列表
       const modelsData = config.responsePath 
@@ -208,7 +210,7 @@ class APIAutoFetchService {
       const headers = this.buildHeaders(config.headers, provider.apiKey);
       
       // 发送请求
-      const response = await this.makeRequest(endpoint, config.method || 'GET', headers);
+      const response = await this.makeRequest(endpoint, config.method || 'GET', headers, provider.id);
       
       // 提取定价数据
       const pricingData = config.responsePath 
@@ -247,7 +249,7 @@ class APIAutoFetchService {
       const headers = this.buildHeaders(config.headers, provider.apiKey);
       
       // 发送请求
-      const response = await this.makeRequest(endpoint, config.method || 'GET', headers);
+      const response = await this.makeRequest(endpoint, config.method || 'GET', headers, provider.id);
       
       // 提取余额数据
       const balanceData = config.responsePath 
@@ -10,10 +10,11 @@ class BackendStorageService {
   
   /**
    * 获取AI提供商列表
+   * 返回的apiKey已脱敏，发送请求时由后端按提供商ID替换为真实密钥
    */
   async getProviders(): Promise<AIProvider[]> {
     try {
-      const result = await invoke<string>('storage_get_providers');
+      const result = await invoke<string>('storage_get_providers_redacted');
       return JSON.parse(result);
     } catch (error) {
       logService.error('获取AI提供商失败:', error);
@@ -33,6 +33,16 @@ interface FileInfo {
   permissions: string;
 }
 
+/**
+ * 工作区根目录接口，字段与后端 `WorkspaceRoot` 一致
+ */
+interface WorkspaceRoot {
+  id: string;
+  name: string;
+  path: string;
+  mode: string;
+}
+
 /**
  * 搜索选项接口
  */
@@ -45,16 +55,45 @@ interface SearchOptions {
 /**
  * 文件系统服务
  * 通过Tauri命令调用Rust后端进行文件操作
+ *
+ * 所有路径都是相对于工作区根目录的路径。未指定 `rootId` 时，
+ * 使用 `setDefaultRoot` 设置的工作区，仍未设置则使用第一个已配置的工作区。
  */
 export class FilesystemService {
+  private defaultRootId?: string;
+
+  /**
+   * 设置默认工作区
+   */
+  setDefaultRoot(rootId?: string) {
+    this.defaultRootId = rootId;
+  }
+
+  /**
+   * 确定本次操作使用的工作区ID
+   */
+  private async resolveRootId(rootId?: string): Promise<string> {
+    if (rootId) return rootId;
+    if (this.defaultRootId) return this.defaultRootId;
+
+    const roots: WorkspaceRoot[] = JSON.parse(await invoke<string>('storage_get_workspace_roots'));
+    if (roots.length === 0) {
+      throw new Error('未配置工作区，请先在设置中添加工作区目录');
+    }
+    return roots[0].id;
+  }
+
   /**
    * 读取文件内容
    */
-  async readFile(filePath: string): Promise<string> {
+  async readFile(filePath: string, rootId?: string): Promise<string> {
     try {
       logService.info(`文件系统服务：读取文件 ${filePath}`);
       
-      const result: FileSystemResult = await invoke('fs_read_file', { path: filePath });
+      const result: FileSystemResult = await invoke('fs_read_file', {
+        rootId: await this.resolveRootId(rootId),
+        path: filePath
+      });
       
       if (result.success && result.data) {
         logService.info(`文件系统服务：读取文件成功 ${filePath}`);
@@ -74,11 +113,12 @@ export class FilesystemService {
   /**
    * 写入文件内容
    */
-  async writeFile(filePath: string, content: string): Promise<void> {
+  async writeFile(filePath: string, content: string, rootId?: string): Promise<void> {
     try {
       logService.info(`文件系统服务：写入文件 ${filePath} (${content.length} 字符)`);
       
-      const result: FileSystemResult = await invoke('fs_write_file', { 
+      const result: FileSystemResult = await invoke('fs_write_file', {
+        rootId: await this.resolveRootId(rootId),
         path: filePath, 
         content: content 
       });
@@ -100,7 +140,7 @@ export class FilesystemService {
   /**
    * 列出目录内容
    */
-  async listDirectory(dirPath: string): Promise<{
+  async listDirectory(dirPath: string, rootId?: string): Promise<{
     name: string;
     type: 'file' | 'directory';
     size?: number;
@@ -109,7 +149,10 @@ export class FilesystemService {
     try {
       logService.info(`文件系统服务：列出目录 ${dirPath}`);
       
-      const result: FileSystemResult = await invoke('fs_list_directory', { path: dirPath });
+      const result: FileSystemResult = await invoke('fs_list_directory', {
+        rootId: await this.resolveRootId(rootId),
+        path: dirPath
+      });
       
       if (result.success && result.data) {
         const items: DirectoryItem[] = JSON.parse(result.data);
@@ -137,11 +180,14 @@ export class FilesystemService {
   /**
    * 创建目录
    */
-  async createDirectory(dirPath: string): Promise<void> {
+  async createDirectory(dirPath: string, rootId?: string): Promise<void> {
     try {
       logService.info(`文件系统服务：创建目录 ${dirPath}`);
       
-      const result: FileSystemResult = await invoke('fs_create_directory', { path: dirPath });
+      const result: FileSystemResult = await invoke('fs_create_directory', {
+        rootId: await this.resolveRootId(rootId),
+        path: dirPath
+      });
       
       if (result.success) {
         logService.info(`文件系统服务：创建目录成功 ${dirPath}`);
@@ -160,11 +206,14 @@ export class FilesystemService {
   /**
    * 删除文件
    */
-  async deleteFile(filePath: string): Promise<void> {
+  async deleteFile(filePath: string, rootId?: string): Promise<void> {
     try {
       logService.info(`文件系统服务：删除文件 ${filePath}`);
       
-      const result: FileSystemResult = await invoke('fs_delete_file', { path: filePath });
+      const result: FileSystemResult = await invoke('fs_delete_file', {
+        rootId: await this.resolveRootId(rootId),
+        path: filePath
+      });
       
       if (result.success) {
         logService.info(`文件系统服务：删除文件成功 ${filePath}`);
@@ -183,11 +232,14 @@ export class FilesystemService {
   /**
    * 删除目录
    */
-  async deleteDirectory(dirPath: string): Promise<void> {
+  async deleteDirectory(dirPath: string, rootId?: string): Promise<void> {
     try {
       logService.info(`文件系统服务：删除目录 ${dirPath}`);
       
-      const result: FileSystemResult = await invoke('fs_delete_directory', { path: dirPath });
+      const result: FileSystemResult = await invoke('fs_delete_directory', {
+        rootId: await this.resolveRootId(rootId),
+        path: dirPath
+      });
       
       if (result.success) {
         logService.info(`文件系统服务：删除目录成功 ${dirPath}`);
@@ -206,11 +258,12 @@ export class FilesystemService {
   /**
    * 移动/重命名文件或目录
    */
-  async moveItem(sourcePath: string, targetPath: string): Promise<void> {
+  async moveItem(sourcePath: string, targetPath: string, rootId?: string): Promise<void> {
     try {
       logService.info(`文件系统服务：移动 ${sourcePath} -> ${targetPath}`);
       
-      const result: FileSystemResult = await invoke('fs_move_item', { 
+      const result: FileSystemResult = await invoke('fs_move_item', {
+        rootId: await this.resolveRootId(rootId),
         source: sourcePath, 
         target: targetPath 
       });
@@ -232,11 +285,12 @@ export class FilesystemService {
   /**
    * 复制文件
    */
-  async copyFile(sourcePath: string, targetPath: string): Promise<void> {
+  async copyFile(sourcePath: string, targetPath: string, rootId?: string): Promise<void> {
     try {
       logService.info(`文件系统服务：复制文件 ${sourcePath} -> ${targetPath}`);
       
-      const result: FileSystemResult = await invoke('fs_copy_file', { 
+      const result: FileSystemResult = await invoke('fs_copy_file', {
+        rootId: await this.resolveRootId(rootId),
         source: sourcePath, 
         target: targetPath 
       });
@@ -258,7 +312,7 @@ export class FilesystemService {
   /**
    * 获取文件或目录信息
    */
-  async getItemInfo(itemPath: string): Promise<{
+  async getItemInfo(itemPath: string, rootId?: string): Promise<{
     name: string;
     type: 'file' | 'directory';
     size: number;
@@ -270,7 +324,10 @@ export class FilesystemService {
     try {
       logService.info(`文件系统服务：获取信息 ${itemPath}`);
       
-      const result: FileSystemResult = await invoke('fs_get_item_info', { path: itemPath });
+      const result: FileSystemResult = await invoke('fs_get_item_info', {
+        rootId: await this.resolveRootId(rootId),
+        path: itemPath
+      });
       
       if (result.success && result.data) {
         const info: FileInfo = JSON.parse(result.data);
@@ -305,7 +362,7 @@ export class FilesystemService {
     recursive?: boolean;
     caseSensitive?: boolean;
     fileOnly?: boolean;
-  } = {}): Promise<string[]> {
+  } = {}, rootId?: string): Promise<string[]> {
     try {
       logService.info(`文件系统服务：搜索文件 ${dirPath} 模式:"${pattern}"`);
       
@@ -315,7 +372,8 @@ export class FilesystemService {
         file_only: options.fileOnly
       };
       
-      const result: FileSystemResult = await invoke('fs_search_files', { 
+      const result: FileSystemResult = await invoke('fs_search_files', {
+        rootId: await this.resolveRootId(rootId),
         path: dirPath, 
         pattern: pattern,
         options: searchOptions
@@ -1,7 +1,43 @@
 import { invoke } from "@tauri-apps/api/core";
-import { listen } from "@tauri-apps/api/event";
+import { listen, UnlistenFn } from "@tauri-apps/api/event";
 import { ProxySettings } from '../types';
 
+/**
+ * HTTP桥接错误类型，与后端 HttpBridgeError 的 kind 对应
+ */
+export type HttpErrorKind = 'invalid_url' | 'domain_not_allowed' | 'private_network' | 'request';
+
+/**
+ * HTTP请求错误，保留后端返回的错误类型供调用方区分处理
+ */
+export class HttpRequestError extends Error {
+  kind: HttpErrorKind;
+
+  constructor(kind: HttpErrorKind, message: string) {
+    super(message);
+    this.name = 'HttpRequestError';
+    this.kind = kind;
+  }
+}
+
+/**
+ * 生效的HTTP允许列表
+ */
+export interface HttpAllowlistInfo {
+  provider_hosts: string[];
+  user_domains: string[];
+  allow_private_network: boolean;
+}
+
+/**
+ * 后端发出的审批请求，这里只处理允许列表相关的字段
+ */
+interface PermissionRequestEvent {
+  request_id: string;
+  action: string;
+  summary: string;
+}
+
 /**
  * HTTP请求参数接口
  */
@@ -11,6 +47,7 @@ interface HttpRequestParams {
   headers?: Record<string, string>;
   body?: string;
   proxy_config?: ProxyConfig;
+  provider_id?: string;
 }
 
 /**
@@ -46,6 +83,7 @@ interface StreamRequestParams {
   headers?: Record<string, string>;
   body?: string;
   proxy_config?: ProxyConfig;
+  provider_id?: string;
   stream_id: string;
 }
 
@@ -88,6 +126,26 @@ class HttpService {
     };
   }
 
+  /**
+   * 将后端返回的错误转换为 HttpRequestError
+   * 命令以 { kind, message } 拒绝，测试代理等命令仍返回字符串
+   */
+  private toRequestError(prefix: string, error: unknown): HttpRequestError {
+    if (error && typeof error === 'object' && 'kind' in error && 'message' in error) {
+      const { kind, message } = error as { kind: HttpErrorKind; message: string };
+      switch (kind) {
+        case 'private_network':
+          return new HttpRequestError(kind, `${prefix}: ${message}。访问Ollama、LM Studio等本地服务需要在设置中开启局域网访问`);
+        case 'domain_not_allowed':
+          return new HttpRequestError(kind, `${prefix}: ${message}。请先将该地址添加为提供商或允许的域名`);
+        default:
+          return new HttpRequestError(kind, `${prefix}: ${message}`);
+      }
+    }
+    const message = error instanceof Error ? error.message : String(error);
+    return new HttpRequestError('request', `${prefix}: ${message}`);
+  }
+
   /**
    * 发送HTTP请求
    */
@@ -98,6 +156,7 @@ class HttpService {
       headers?: Record<string, string>;
       body?: string;
       proxySettings?: ProxySettings;
+      providerId?: string; // 请求中的脱敏密钥由后端替换为该提供商的真实密钥
     } = {}
   ): Promise<HttpResponse> {
     const params: HttpRequestParams = {
@@ -106,13 +165,14 @@ class HttpService {
       headers: options.headers,
       body: options.body,
       proxy_config: options.proxySettings ? this.convertProxySettings(options.proxySettings) : undefined,
+      provider_id: options.providerId,
     };
 
     try {
       const response = await invoke<HttpResponse>("send_http_request", { params });
       return response;
     } catch (error) {
-      throw new Error(`HTTP请求失败: ${error}`);
+      throw this.toRequestError('HTTP请求失败', error);
     }
   }
 
@@ -133,7 +193,7 @@ class HttpService {
       const result = await invoke<string>("test_proxy_connection", { proxyConfig });
       return result;
     } catch (error) {
-      throw new Error(`代理测试失败: ${error}`);
+      throw this.toRequestError('代理测试失败', error);
     }
   }
 
@@ -175,6 +235,7 @@ class HttpService {
       headers?: Record<string, string>;
       body?: string;
       proxySettings?: ProxySettings;
+      providerId?: string; // 请求中的脱敏密钥由后端替换为该提供商的真实密钥
       onData?: (data: string) => void;
       onEnd?: () => void;
       onError?: (error: string) => void;
@@ -188,6 +249,7 @@ class HttpService {
       headers: options.headers,
       body: options.body,
       proxy_config: options.proxySettings ? this.convertProxySettings(options.proxySettings) : undefined,
+      provider_id: options.providerId,
       stream_id: streamId,
     };
 
@@ -226,9 +288,39 @@ class HttpService {
       return result;
     } catch (error) {
       unlisten(); // 发生错误时清理监听器
-      throw new Error(`流式HTTP请求失败: ${error}`);
+      throw this.toRequestError('流式HTTP请求失败', error);
     }
   }
+
+  /**
+   * 获取生效的HTTP允许列表
+   */
+  async getAllowlist(): Promise<HttpAllowlistInfo> {
+    const result = await invoke<string>('http_get_allowlist');
+    return JSON.parse(result) as HttpAllowlistInfo;
+  }
+
+  /**
+   * 开启或关闭局域网访问，开启时需要在审批弹窗中确认
+   */
+  async setAllowPrivateNetwork(enabled: boolean): Promise<void> {
+    await invoke('storage_save_setting', { key: 'http_allow_private_network', value: String(enabled) });
+  }
+
+  /**
+   * 监听放宽HTTP允许列表的审批请求并弹窗确认
+   * 添加提供商、允许域名或开启局域网访问时，后端会等待这里的答复
+   */
+  async listenAllowlistApprovals(): Promise<UnlistenFn> {
+    return listen<PermissionRequestEvent>('permission-request', async (event) => {
+      const request = event.payload;
+      if (request.action !== 'http_allowlist') {
+        return;
+      }
+      const decision = window.confirm(`${request.summary}\n\n确定允许吗？`) ? 'allow_once' : 'deny';
+      await invoke('permission_respond', { requestId: request.request_id, decision });
+    });
+  }
 }
 
 // 导出单例实例
@@ -195,7 +195,7 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                path: { type: 'string', description: '文件路径' }
+                path: { type: 'string', description: '文件路径（相对于工作区根目录）' }
               },
               required: ['path']
             }
@@ -206,7 +206,7 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                path: { type: 'string', description: '文件路径' },
+                path: { type: 'string', description: '文件路径（相对于工作区根目录）' },
                 content: { type: 'string', description: '文件内容' }
               },
               required: ['path', 'content']
@@ -218,7 +218,7 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                path: { type: 'string', description: '目录路径' }
+                path: { type: 'string', description: '目录路径（相对于工作区根目录）' }
               },
               required: ['path']
             }
@@ -229,7 +229,7 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                path: { type: 'string', description: '目录路径' }
+                path: { type: 'string', description: '目录路径（相对于工作区根目录）' }
               },
               required: ['path']
             }
@@ -240,7 +240,7 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                path: { type: 'string', description: '文件路径' }
+                path: { type: 'string', description: '文件路径（相对于工作区根目录）' }
               },
               required: ['path']
             }
@@ -251,7 +251,7 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                path: { type: 'string', description: '目录路径' }
+                path: { type: 'string', description: '目录路径（相对于工作区根目录）' }
               },
               required: ['path']
             }
@@ -262,8 +262,8 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                source: { type: 'string', description: '源路径' },
-                target: { type: 'string', description: '目标路径' }
+                source: { type: 'string', description: '源路径（相对于工作区根目录）' },
+                target: { type: 'string', description: '目标路径（相对于工作区根目录）' }
               },
               required: ['source', 'target']
             }
@@ -274,8 +274,8 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                source: { type: 'string', description: '源文件路径' },
-                target: { type: 'string', description: '目标文件路径' }
+                source: { type: 'string', description: '源文件路径（相对于工作区根目录）' },
+                target: { type: 'string', description: '目标文件路径（相对于工作区根目录）' }
               },
               required: ['source', 'target']
             }
@@ -286,7 +286,7 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                path: { type: 'string', description: '文件或目录路径' }
+                path: { type: 'string', description: '文件或目录路径（相对于工作区根目录）' }
               },
               required: ['path']
             }
@@ -297,7 +297,7 @@ class MCPService {
             inputSchema: {
               type: 'object',
               properties: {
-                path: { type: 'string', description: '搜索目录路径' },
+                path: { type: 'string', description: '搜索目录路径（相对于工作区根目录）' },
                 pattern: { type: 'string', description: '搜索模式（正则表达式）' },
                 recursive: { type: 'boolean', description: '是否递归搜索' },
                 case_sensitive: { type: 'boolean', description: '是否区分大小写' },
@@ -92,6 +92,9 @@ const SettingsInterface: FC = () => {
   // 代理测试状态
   const [testingProxy, setTestingProxy] = useState(false);
   
+  // 局域网访问状态
+  const [allowPrivateNetwork, setAllowPrivateNetwork] = useState(false);
+  
   // 模型管理对话框状态
   const [modelDialogOpen, setModelDialogOpen] = useState(false);
   const [currentProvider, setCurrentProvider] = useState<string>('');
@@ -140,11 +143,27 @@ const SettingsInterface: FC = () => {
       const savedProxy = await storageService.getProxySettings();
       setProxySettings(savedProxy);
       logService.info(`已加载代理设置，代理状态: ${savedProxy.enabled ? '启用' : '禁用'}`);
+      
+      // 加载局域网访问设置
+      try {
+        const allowlist = await httpService.getAllowlist();
+        setAllowPrivateNetwork(allowlist.allow_private_network);
+      } catch (error) {
+        logService.error('加载HTTP允许列表失败:', error);
+      }
     };
     
     loadSettings();
   }, []);
   
+  // 添加提供商或开启局域网访问时，后端会请求确认
+  useEffect(() => {
+    const unlisten = httpService.listenAllowlistApprovals();
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+  
   // 添加新的AI提供商
   const handleAddProvider = () => {
     if (!newProvider.name || !newProvider.apiEndpoint) {
@@ -297,6 +316,20 @@ const SettingsInterface: FC = () => {
   
 
   
+  // 开启或关闭局域网访问
+  const handleTogglePrivateNetwork = async (enabled: boolean) => {
+    try {
+      await httpService.setAllowPrivateNetwork(enabled);
+      setAllowPrivateNetwork(enabled);
+      toast.success(enabled ? '已开启局域网访问' : '已关闭局域网访问');
+      logService.info(`局域网访问已${enabled ? '开启' : '关闭'}`);
+    } catch (error) {
+      const errorMsg = `保存局域网访问设置失败: ${error instanceof Error ? error.message : String(error)}`;
+      logService.error(errorMsg);
+      toast.error(errorMsg);
+    }
+  };
+  
   // 打开模型管理对话框
   const openModelDialog = (providerId: string) => {
     setCurrentProvider(providerId);
@@ -871,6 +904,27 @@ const SettingsInterface: FC = () => {
         </CardContent>
       </Card>
       
+      {/* 网络访问设置 */}
+      <Card className="mb-8">
+        <CardHeader>
+          <CardTitle>网络访问</CardTitle>
+        </CardHeader>
+        <CardContent className="space-y-2">
+          <div className="flex items-center space-x-2">
+            <Checkbox
+              id="allow-private-network"
+              checked={allowPrivateNetwork}
+              onCheckedChange={(checked) => handleTogglePrivateNetwork(checked === true)}
+              className="cursor-pointer"
+            />
+            <Label htmlFor="allow-private-network" className="cursor-pointer">允许访问本机和局域网地址</Label>
+          </div>
+          <p className="text-sm text-gray-500">
+            使用Ollama、LM Studio等本地模型服务时需要开启，开启前会弹窗确认
+          </p>
+        </CardContent>
+      </Card>
+      
       {/* MCP工具设置 */}
       <Card className="mb-8">
         <CardHeader>